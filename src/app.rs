@@ -1,29 +1,24 @@
 use anyhow::Result;
 use clap::{crate_authors, crate_description, crate_name, crate_version, Arg};
+use dmux::tmux::expand_path;
 
+use std::collections::BTreeMap;
 use std::fs::canonicalize;
 use std::io;
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::path::{Path, PathBuf};
 // const DEFAULT_LAYOUT: &str = "34ed,230x56,0,0{132x56,0,0,3,97x56,133,0,222}";
 
 fn args() -> clap::ArgMatches {
-    let fzf_available = Command::new("fzf")
-        .arg("--version")
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .spawn()
-        .is_ok();
     clap::Command::new(crate_name!())
         .version(crate_version!())
         .author(crate_authors!())
         .about(crate_description!())
+        // subcommands don't open a directory
+        .subcommand_negates_reqs(true)
         .arg(
             Arg::new("selected_dir")
                 .help("Open this directory directly without starting a selector")
-                .takes_value(true)
-                // if fzf isn't available, this needs to be specified
-                .required(!fzf_available),
+                .takes_value(true),
         )
         .arg(
             Arg::new("session_name")
@@ -39,6 +34,81 @@ fn args() -> clap::ArgMatches {
                 .help("specify the window name")
                 .takes_value(true),
         )
+        .arg(
+            Arg::new("window_index")
+                .short('i')
+                .long("window-index")
+                .help("target the window at this index instead of by name")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("fresh")
+                .long("fresh")
+                .help("kill the session if it's already open and start it over"),
+        )
+        .arg(
+            Arg::new("detached")
+                .long("detached")
+                .visible_alias("no-attach")
+                .help("set up the workspace without attaching to it and print its session's name"),
+        )
+        .arg(Arg::new("control_mode").long("control-mode").help(
+            "send tmux commands over one control mode client instead of running tmux for each",
+        ))
+        .arg(Arg::new("dry_run").long("dry-run").help(
+            "print the tmux commands that would set the workspace up instead of running them",
+        ))
+        .arg(
+            Arg::new("multiplexer")
+                .long("multiplexer")
+                .alias("backend")
+                .takes_value(true)
+                .possible_values(["tmux", "zellij", "wezterm", "kitty", "screen"])
+                .help("the multiplexer to open the workspace in"),
+        )
+        .arg(
+            Arg::new("socket_name")
+                .short('L')
+                .long("socket-name")
+                .takes_value(true)
+                .conflicts_with("socket_path")
+                .help("use the tmux server with this socket name, like tmux -L"),
+        )
+        .arg(
+            Arg::new("socket_path")
+                .short('S')
+                .long("socket-path")
+                .takes_value(true)
+                .help("use the tmux server at this socket path, like tmux -S"),
+        )
+        .arg(
+            Arg::new("host")
+                .long("host")
+                .takes_value(true)
+                .help("open the workspace in tmux on this host over ssh, in a dir of that host"),
+        )
+        .arg(
+            Arg::new("wait_for_prompt")
+                .long("wait-for-prompt")
+                .takes_value(true)
+                .value_name("SECONDS")
+                .help("wait up to this long for each pane's prompt before sending its command"),
+        )
+        .arg(
+            Arg::new("reapply")
+                .long("reapply")
+                .help("bring windows that are already open in line with the config"),
+        )
+        .arg(
+            Arg::new("reapply_commands")
+                .long("reapply-commands")
+                .help("like --reapply, also sending the commands again to panes at a prompt"),
+        )
+        .arg(
+            Arg::new("force_commands")
+                .long("force-commands")
+                .help("send the commands again when run from the workspace's own window"),
+        )
         .arg(
             Arg::new("number_of_panes")
                 .short('p')
@@ -78,12 +148,17 @@ fn args() -> clap::ArgMatches {
                 .help("override of the dir to select from.")
                 .takes_value(true),
         )
+        .arg(
+            Arg::new("zoxide")
+                .long("zoxide")
+                .help("pick from the dirs zoxide knows, most frecent first, instead of searching"),
+        )
         .subcommand(
             clap::Command::new("clone")
                 .about("clones a git repository, and then opens a workspace in the repo")
                 .arg(
                     Arg::new("repo")
-                        .help("specifies the repo to clone from")
+                        .help("specifies the repo to clone from, or org/repo on GitHub")
                         .required(true),
                 )
                 .arg(
@@ -97,6 +172,51 @@ fn args() -> clap::ArgMatches {
         .subcommand(
             clap::Command::new("layout").about("generates the current layout string from tmux"),
         )
+        .subcommand(
+            clap::Command::new("list")
+                .about("lists the open sessions with their windows and panes")
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("print every session, window and pane as JSON"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("snapshot")
+                .about("prints a session as a config profile that opens it again the way it is")
+                .arg(
+                    Arg::new("session")
+                        .help("the session to snapshot, the current one when not given")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("name")
+                        .short('n')
+                        .long("name")
+                        .help("the profile's name, the session's when not given")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("kill")
+                .about("kills a workspace's window, or a session, picking one when none is given")
+                .arg(
+                    Arg::new("name")
+                        .help("a workspace's directory, a session or session:window")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("worktree")
+                .about(
+                    "opens a workspace in a git worktree of the branch, adding one if it has none",
+                )
+                .arg(
+                    Arg::new("branch")
+                        .help("the branch to check out")
+                        .required(true),
+                ),
+        )
         .get_matches()
 }
 
@@ -113,6 +233,15 @@ tmux list-windows -F \"#{{window_active}} #{{window_layout}}\"
   | grep \"^1\" 
   | cut -d \" \" -f 2
 `
+
+One of tmux's preset names (even-horizontal,
+even-vertical, main-horizontal, main-vertical,
+their -mirrored versions and tiled) lays out
+any number of panes without a checksum.
+
+`auto` picks a preset based on the number of
+panes instead, and `auto:<preset>` uses the given
+preset with the same main pane size.
  ",
         crate_name!()
     )
@@ -127,15 +256,40 @@ will be opened by {:?}. The commands index
 (beginning with 0) corresponds to the pane
 id. Pane id's can be found easily with 
 `<prefix >q` in tmux.
+
+A command like `file:boot.sh` sends each line
+of that script to its pane one at a time, rather
+than sourcing it. The path is relative to the
+directory being opened.
+
+{{{{dir}}}}, {{{{dir_name}}}}, {{{{session}}}}, {{{{window}}}}
+and {{{{git_branch}}}} in a command are filled in
+from the workspace before it's sent.
  ",
         crate_name!()
     )
 }
 
+fn default_dir_source() -> String {
+    "search".to_owned()
+}
+
+fn default_finder() -> String {
+    "builtin".to_owned()
+}
+
+fn default_clone_protocol() -> String {
+    "https".to_owned()
+}
+
 fn default_search_dir() -> PathBuf {
     dirs::home_dir().unwrap()
 }
 
+fn default_multiplexer() -> String {
+    "tmux".to_string()
+}
+
 fn default_layout_checksum() -> String {
     "34ed,230x56,0,0{132x56,0,0,3,97x56,133,0,222}".to_string()
 }
@@ -156,7 +310,7 @@ fn default_window_name() -> Option<String> {
     None
 }
 
-fn config_file_settings() -> Result<config::Config> {
+fn config_file_settings(local_dir: Option<&Path>) -> Result<config::Config> {
     // switch to confy perobably
     let default = WorkSpaceArgs::default();
     let mut settings = config::Config::default();
@@ -171,12 +325,28 @@ fn config_file_settings() -> Result<config::Config> {
     let mut mac_config =
         dirs::home_dir().ok_or_else(|| anyhow!("Home directory couldn't be found"))?;
     mac_config.push(".config/dmux/dmux.conf.xxx");
-    Ok(settings
+    settings
         // ~/dmux.conf.(yaml | json | toml)
         .merge(config::File::with_name(config_conf.to_str().unwrap()).required(false))?
         // ~/{xdg_config|.config}dmux.conf.(yaml | json | toml)
         .merge(config::File::with_name(home_conf.to_str().unwrap()).required(false))?
-        .merge(config::File::with_name(mac_config.to_str().unwrap()).required(false))?
+        .merge(config::File::with_name(mac_config.to_str().unwrap()).required(false))?;
+
+    // a project's own config goes over the global one, unless that turns it
+    // off with `local_config = false`, since its commands run on opening
+    if let Some(dir) = local_dir {
+        if settings.get_bool("local_config").unwrap_or(true) {
+            for local in [dir.join(".dmux.toml"), dir.join(".dmux/config.toml")] {
+                settings.merge(
+                    config::File::from(local)
+                        .format(config::FileFormat::Toml)
+                        .required(false),
+                )?;
+            }
+        }
+    }
+
+    Ok(settings
         // Add in settings from the environment (with a prefix of DMUX)
         // Eg.. `DMUX_SESSION_NAME=foo dmux` would set the `session_name` key
         .merge(config::Environment::with_prefix("DMUX"))?
@@ -190,8 +360,15 @@ fn config_file_settings() -> Result<config::Config> {
 
 fn settings_config(settings: config::Config, target: Option<&str>) -> Result<WorkSpaceArgs> {
     if let Some(target) = target {
-        let profile: WorkSpaceArgs = settings.get(target)?;
-        return Ok(profile);
+        // a profile is a table of its own, like `[web]`, or `[profile.web]`
+        for key in [format!("profile.{}", target), target.to_owned()] {
+            match settings.get::<WorkSpaceArgs>(&key) {
+                Ok(profile) => return Ok(profile),
+                Err(config::ConfigError::NotFound(_)) => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        return Err(anyhow!("There's no profile named {} in the config", target));
     }
     let profile: WorkSpaceArgs = settings.try_into()?;
     Ok(profile)
@@ -210,6 +387,18 @@ pub enum CommandType {
     Pull(PullArgs),
     // Generate a tmux layout for the setup of panes in the current window
     Layout,
+    // Kill a workspace's window or a session, picked from a fuzzy finder
+    // when it's not named
+    Kill(KillArgs),
+    // List the open sessions, as JSON when it's true
+    List {
+        json: bool,
+        workspace: WorkSpaceArgs,
+    },
+    // Print a session as a config profile
+    Snapshot(SnapshotArgs),
+    // Open a branch's worktree of the repo dmux is run in
+    Worktree(WorktreeArgs),
 }
 
 // I don't like the repetition here
@@ -219,14 +408,159 @@ pub struct WorkSpaceArgs {
     pub layout: String,
     #[serde(default = "default_session_name")]
     pub session_name: String,
+    // used instead of session_name, like "{{git_repo}}-{{git_branch}}"
+    #[serde(default)]
+    pub session_name_template: Option<String>,
     #[serde(default = "default_number_of_panes")]
     pub number_of_panes: u8,
     #[serde(default = "default_search_dir")]
     pub search_dir: PathBuf,
+    // search, zoxide or both
+    #[serde(default = "default_dir_source")]
+    pub dir_source: String,
+    // builtin, or a command like fzf-tmux that picks from its stdin
+    #[serde(default = "default_finder")]
+    pub finder: String,
+    // how `dmux clone org/repo` reaches GitHub, https or ssh
+    #[serde(default = "default_clone_protocol")]
+    pub clone_protocol: String,
+    // where `dmux worktree` adds worktrees
+    #[serde(default)]
+    pub worktree_dir: Option<PathBuf>,
     #[serde(default = "default_commands")]
     pub commands: Vec<String>,
     #[serde(default = "default_window_name")]
     pub window_name: Option<String>,
+    #[serde(default)]
+    pub keep_initial_window: bool,
+    #[serde(default)]
+    pub window_index: Option<usize>,
+    #[serde(default)]
+    pub login_shell: bool,
+    // (scope, option, value), the scope being global, session or window
+    #[serde(default)]
+    pub options: Vec<(String, String, String)>,
+    #[serde(default)]
+    pub verify_commands: bool,
+    // seconds to wait for each pane's shell to show its prompt
+    #[serde(default)]
+    pub wait_for_prompt: Option<f64>,
+    // latest, largest or smallest
+    #[serde(default)]
+    pub window_size: Option<String>,
+    #[serde(default)]
+    pub fresh: bool,
+    #[serde(default)]
+    pub commands_by_position: bool,
+    // tmux commands run once attached
+    #[serde(default)]
+    pub post_attach: Vec<String>,
+    // shell commands run on the host before the workspace is set up
+    #[serde(default)]
+    pub before_setup: Vec<String>,
+    // and once it's set up, before attaching to it rather than after, since
+    // attaching only returns when the client detaches
+    #[serde(default)]
+    pub after_setup: Vec<String>,
+    // a failing hook stops dmux rather than only being warned about
+    #[serde(default)]
+    pub abort_on_hook_failure: bool,
+    // open windows get their missing panes and layout again, and with
+    // reapply_commands idle panes their commands
+    #[serde(default)]
+    pub reapply: bool,
+    #[serde(default)]
+    pub reapply_commands: bool,
+    // close the gaps in an open session's window indexes first
+    #[serde(default)]
+    pub renumber_windows: bool,
+    #[serde(default)]
+    pub force_commands: bool,
+    #[serde(default)]
+    pub detached: bool,
+    #[serde(default)]
+    pub control_mode: bool,
+    // only ever given on the command line
+    #[serde(skip)]
+    pub dry_run: bool,
+    // tmux, zellij, wezterm, kitty or screen
+    #[serde(default = "default_multiplexer", alias = "backend")]
+    pub multiplexer: String,
+    // an ssh host to run tmux on
+    #[serde(default)]
+    pub host: Option<String>,
+    // a tmux server other than the default, by name (-L) or path (-S)
+    #[serde(default)]
+    pub socket_name: Option<String>,
+    #[serde(default)]
+    pub socket_path: Option<PathBuf>,
+    #[serde(default)]
+    pub multiline_commands: bool,
+    #[serde(default)]
+    pub monitor_activity: bool,
+    // seconds
+    #[serde(default)]
+    pub monitor_silence: Option<u32>,
+    // typing in one pane types in all of them, once the commands are sent
+    #[serde(default)]
+    pub synchronize: bool,
+    // off, top or bottom
+    #[serde(default)]
+    pub pane_border_status: Option<String>,
+    #[serde(default)]
+    pub pane_border_format: Option<String>,
+    // more windows opened in the same session
+    #[serde(default)]
+    pub windows: Vec<WindowArgs>,
+    // the window to land on, the workspace's own when not given
+    #[serde(default)]
+    pub active_window: Option<String>,
+    #[serde(default)]
+    pub panes: Vec<PaneArgs>,
+    // set in every pane
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    // the panes as a tree of splits, instead of a layout
+    #[serde(default)]
+    pub splits: Option<SplitArgs>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SplitArgs {
+    // horizontal or vertical
+    #[serde(default)]
+    pub split: Option<String>,
+    // percent of the parent, like "70%"
+    #[serde(default)]
+    pub size: Option<String>,
+    #[serde(default)]
+    pub children: Vec<SplitArgs>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PaneArgs {
+    // relative to the opened dir
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    // shown in the pane's border with pane_border_status
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct WindowArgs {
+    pub name: String,
+    // relative to the opened dir
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+    #[serde(default)]
+    pub layout: Option<String>,
+    #[serde(default)]
+    pub commands: Vec<String>,
+    #[serde(default)]
+    pub number_of_panes: u8,
 }
 
 impl Default for WorkSpaceArgs {
@@ -235,9 +569,49 @@ impl Default for WorkSpaceArgs {
             window_name: None,
             layout: default_layout_checksum(),
             session_name: default_session_name(),
+            session_name_template: None,
             number_of_panes: default_number_of_panes(),
             search_dir: dirs::home_dir().unwrap(),
+            dir_source: default_dir_source(),
+            finder: default_finder(),
+            clone_protocol: default_clone_protocol(),
+            worktree_dir: None,
             commands: default_commands(),
+            keep_initial_window: false,
+            window_index: None,
+            login_shell: false,
+            options: vec![],
+            verify_commands: false,
+            wait_for_prompt: None,
+            window_size: None,
+            fresh: false,
+            commands_by_position: false,
+            post_attach: vec![],
+            before_setup: vec![],
+            after_setup: vec![],
+            abort_on_hook_failure: false,
+            reapply: false,
+            reapply_commands: false,
+            renumber_windows: false,
+            force_commands: false,
+            detached: false,
+            control_mode: false,
+            dry_run: false,
+            multiplexer: default_multiplexer(),
+            host: None,
+            socket_name: None,
+            socket_path: None,
+            multiline_commands: false,
+            monitor_activity: false,
+            synchronize: false,
+            monitor_silence: None,
+            pane_border_status: None,
+            pane_border_format: None,
+            windows: vec![],
+            active_window: None,
+            panes: vec![],
+            env: BTreeMap::new(),
+            splits: None,
         }
     }
 }
@@ -251,9 +625,31 @@ pub struct OpenArgs {
 pub struct PullArgs {
     pub repo_url: String,
     pub target_dir: PathBuf,
+    // the cloned repo's dir name, the repo's own otherwise
+    pub name: Option<String>,
+    // https or ssh, for `org/repo`
+    pub protocol: String,
+}
+
+pub struct SnapshotArgs {
+    // the current session when it's not given
+    pub session: Option<String>,
+    pub profile: Option<String>,
+    pub workspace: WorkSpaceArgs,
+}
+
+pub struct KillArgs {
+    // a directory, or the name of a session or window
+    pub name: Option<String>,
     pub workspace: WorkSpaceArgs,
 }
 
+pub struct WorktreeArgs {
+    pub branch: String,
+    // where new worktrees go, next to the repo otherwise
+    pub worktree_dir: Option<PathBuf>,
+}
+
 fn read_line_iter() -> Result<String> {
     let mut input = String::new();
     io::stdin().read_line(&mut input)?;
@@ -274,17 +670,45 @@ fn select_dir(args: &clap::ArgMatches) -> Option<PathBuf> {
     }
 }
 
-fn build_workspace_args(args: &clap::ArgMatches) -> Result<WorkSpaceArgs> {
-    let settings = config_file_settings()?;
+/// The settings for opening `dir`, read over again with its `.dmux.toml` or
+/// `.dmux/config.toml` for when it's only picked after starting up.
+pub fn workspace_args_in(dir: &Path) -> Result<WorkSpaceArgs> {
+    build_workspace_args(&args(), Some(dir))
+}
+
+fn build_workspace_args(args: &clap::ArgMatches, dir: Option<&Path>) -> Result<WorkSpaceArgs> {
+    let settings = config_file_settings(dir)?;
     let conf_from_settings = settings_config(settings, args.value_of("profile"))?;
-    let search_dir = args
-        .value_of_t::<PathBuf>("search_dir")
-        .unwrap_or(conf_from_settings.search_dir);
+    // a socket on the command line goes over either one in the config
+    let (socket_name, socket_path) =
+        if args.is_present("socket_name") || args.is_present("socket_path") {
+            (
+                args.value_of("socket_name").map(str::to_owned),
+                args.value_of_t::<PathBuf>("socket_path").ok(),
+            )
+        } else {
+            (
+                conf_from_settings.socket_name,
+                conf_from_settings.socket_path,
+            )
+        };
+    let search_dir = expand_path(
+        &args
+            .value_of_t::<PathBuf>("search_dir")
+            .unwrap_or(conf_from_settings.search_dir),
+    );
     Ok(WorkSpaceArgs {
-        window_name: args.value_of_t::<String>("window_name").ok(),
+        window_name: args
+            .value_of_t::<String>("window_name")
+            .ok()
+            .or(conf_from_settings.window_name),
         session_name: args
             .value_of_t::<String>("session_name")
             .unwrap_or(conf_from_settings.session_name),
+        // a name given on the command line is used as it is
+        session_name_template: conf_from_settings
+            .session_name_template
+            .filter(|_| !args.is_present("session_name")),
         layout: args
             .value_of_t::<String>("layout")
             .unwrap_or(conf_from_settings.layout),
@@ -295,11 +719,70 @@ fn build_workspace_args(args: &clap::ArgMatches) -> Result<WorkSpaceArgs> {
             .values_of_t::<String>("commands")
             .unwrap_or(conf_from_settings.commands),
         search_dir,
+        dir_source: if args.is_present("zoxide") {
+            "zoxide".to_owned()
+        } else {
+            conf_from_settings.dir_source
+        },
+        finder: conf_from_settings.finder,
+        clone_protocol: conf_from_settings.clone_protocol,
+        worktree_dir: conf_from_settings.worktree_dir.map(|dir| expand_path(&dir)),
+        keep_initial_window: conf_from_settings.keep_initial_window,
+        window_index: args
+            .value_of_t::<usize>("window_index")
+            .ok()
+            .or(conf_from_settings.window_index),
+        login_shell: conf_from_settings.login_shell,
+        options: conf_from_settings.options,
+        verify_commands: conf_from_settings.verify_commands,
+        wait_for_prompt: args
+            .value_of_t::<f64>("wait_for_prompt")
+            .ok()
+            .or(conf_from_settings.wait_for_prompt),
+        window_size: conf_from_settings.window_size,
+        fresh: args.is_present("fresh") || conf_from_settings.fresh,
+        commands_by_position: conf_from_settings.commands_by_position,
+        post_attach: conf_from_settings.post_attach,
+        before_setup: conf_from_settings.before_setup,
+        after_setup: conf_from_settings.after_setup,
+        abort_on_hook_failure: conf_from_settings.abort_on_hook_failure,
+        reapply_commands: args.is_present("reapply_commands")
+            || conf_from_settings.reapply_commands,
+        reapply: args.is_present("reapply")
+            || args.is_present("reapply_commands")
+            || conf_from_settings.reapply
+            || conf_from_settings.reapply_commands,
+        renumber_windows: conf_from_settings.renumber_windows,
+        force_commands: args.is_present("force_commands") || conf_from_settings.force_commands,
+        detached: args.is_present("detached") || conf_from_settings.detached,
+        control_mode: args.is_present("control_mode") || conf_from_settings.control_mode,
+        dry_run: args.is_present("dry_run"),
+        multiplexer: args
+            .value_of("multiplexer")
+            .map(str::to_owned)
+            .unwrap_or(conf_from_settings.multiplexer),
+        host: args
+            .value_of("host")
+            .map(str::to_owned)
+            .or(conf_from_settings.host),
+        socket_name,
+        socket_path: socket_path.map(|path| expand_path(&path)),
+        multiline_commands: conf_from_settings.multiline_commands,
+        monitor_activity: conf_from_settings.monitor_activity,
+        synchronize: conf_from_settings.synchronize,
+        monitor_silence: conf_from_settings.monitor_silence,
+        pane_border_status: conf_from_settings.pane_border_status,
+        pane_border_format: conf_from_settings.pane_border_format,
+        windows: conf_from_settings.windows,
+        active_window: conf_from_settings.active_window,
+        panes: conf_from_settings.panes,
+        env: conf_from_settings.env,
+        splits: conf_from_settings.splits,
     })
 }
 
 fn expand_selected_dir(path: PathBuf) -> Result<PathBuf> {
-    if path == PathBuf::from(".") {
+    if path == *"." {
         Ok(std::env::current_dir()?)
     } else {
         Ok(path)
@@ -308,22 +791,37 @@ fn expand_selected_dir(path: PathBuf) -> Result<PathBuf> {
 
 pub fn build_app() -> Result<CommandType> {
     let args = args();
-    let workspace = build_workspace_args(&args)?;
-    match args.subcommand_name() {
-        None => {
-            if let Some(selected_dir) = select_dir(&args) {
-                Ok(CommandType::Open(OpenArgs {
+    let workspace = build_workspace_args(&args, None)?;
+    if args.subcommand_name().is_none() {
+        if let Some(selected_dir) = select_dir(&args) {
+            // a host's dirs can't be resolved or read config from here
+            if workspace.host.is_some() {
+                return Ok(CommandType::Open(OpenArgs {
                     workspace,
-                    selected_dir: expand_selected_dir(canonicalize(selected_dir)?)?,
-                }))
-            } else {
-                Ok(CommandType::Select(SelectArgs { workspace }))
+                    selected_dir,
+                }));
             }
+            let selected_dir = expand_selected_dir(canonicalize(expand_path(&selected_dir))?)?;
+            return Ok(CommandType::Open(OpenArgs {
+                workspace: build_workspace_args(&args, Some(&selected_dir))?,
+                selected_dir,
+            }));
         }
+    }
+    if workspace.host.is_some()
+        && matches!(args.subcommand_name(), None | Some("clone" | "worktree"))
+    {
+        return Err(anyhow!(
+            "--host only opens a directory that's given, since it's on the host"
+        ));
+    }
+    match args.subcommand_name() {
+        None => Ok(CommandType::Select(SelectArgs { workspace })),
         Some("clone") => {
-            let repo_url = args
+            let clone = args
                 .subcommand_matches("clone")
-                .ok_or_else(|| anyhow!("Problem reading clones"))?
+                .ok_or_else(|| anyhow!("Problem reading clones"))?;
+            let repo_url = clone
                 .value_of("repo")
                 .ok_or_else(|| anyhow!("No repo specified, what should I clone?"))?
                 .to_owned();
@@ -332,11 +830,88 @@ pub fn build_app() -> Result<CommandType> {
                 target_dir: args
                     .value_of_t::<PathBuf>("target_dir")
                     .unwrap_or_else(|_| dirs::home_dir().unwrap()),
-                workspace,
+                name: clone.value_of("name").map(str::to_owned),
+                protocol: workspace.clone_protocol,
             }))
         }
 
         Some("layout") => Ok(CommandType::Layout),
+        Some("list") => Ok(CommandType::List {
+            json: args
+                .subcommand_matches("list")
+                .is_some_and(|list| list.is_present("json")),
+            workspace,
+        }),
+        Some("snapshot") => {
+            let snapshot = args
+                .subcommand_matches("snapshot")
+                .ok_or_else(|| anyhow!("Problem reading snapshot"))?;
+            Ok(CommandType::Snapshot(SnapshotArgs {
+                session: snapshot.value_of("session").map(str::to_owned),
+                profile: snapshot.value_of("name").map(str::to_owned),
+                workspace,
+            }))
+        }
+        Some("kill") => Ok(CommandType::Kill(KillArgs {
+            name: args
+                .subcommand_matches("kill")
+                .and_then(|kill| kill.value_of("name"))
+                .map(str::to_owned),
+            workspace,
+        })),
+        Some("worktree") => Ok(CommandType::Worktree(WorktreeArgs {
+            branch: args
+                .subcommand_matches("worktree")
+                .and_then(|worktree| worktree.value_of("branch"))
+                .ok_or_else(|| anyhow!("No branch specified, what should I check out?"))?
+                .to_owned(),
+            worktree_dir: workspace.worktree_dir,
+        })),
         Some(_) => Err(anyhow!("unexpected subcommand")),
     }
 }
+
+#[test]
+fn project_config_goes_over_the_global_one() {
+    let dir = std::env::temp_dir().join(format!("dmux-local-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join(".dmux")).unwrap();
+    let local = |dir: &Path| settings_config(config_file_settings(Some(dir)).unwrap(), None);
+
+    std::fs::write(dir.join(".dmux/config.toml"), "number_of_panes = 3\n").unwrap();
+    assert_eq!(local(&dir).unwrap().number_of_panes, 3);
+
+    std::fs::write(
+        dir.join(".dmux.toml"),
+        "commands = [\"cargo watch\"]\n[rust]\nlayout = \"tiled\"\n",
+    )
+    .unwrap();
+    let workspace = local(&dir).unwrap();
+    assert_eq!(workspace.commands, ["cargo watch"]);
+    assert_eq!(workspace.number_of_panes, 3);
+    let settings = config_file_settings(Some(&dir)).unwrap();
+    assert_eq!(
+        settings_config(settings, Some("rust")).unwrap().layout,
+        "tiled"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn profiles_are_read_from_their_own_tables() {
+    let dir = std::env::temp_dir().join(format!("dmux-profiles-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join(".dmux.toml"),
+        "[profile.web]\nnumber_of_panes = 4\n[rust]\nnumber_of_panes = 1\n",
+    )
+    .unwrap();
+    let profile = |name| settings_config(config_file_settings(Some(&dir)).unwrap(), Some(name));
+
+    assert_eq!(profile("web").unwrap().number_of_panes, 4);
+    assert_eq!(profile("rust").unwrap().number_of_panes, 1);
+    assert_eq!(
+        profile("go").unwrap_err().to_string(),
+        "There's no profile named go in the config"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
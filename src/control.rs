@@ -0,0 +1,204 @@
+// ControlClient::connect
+// ControlClient::run
+// command_line
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Output, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use tmux_interface::{TmuxCommand, TmuxOutput};
+
+/// A `tmux -C` client that stays attached, so commands are written to it
+/// one line at a time instead of starting tmux for each.
+#[derive(Debug)]
+pub(crate) struct ControlClient {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+/// Why a command couldn't be run over the connection. The connection is no
+/// good after any of these.
+#[derive(Debug)]
+pub(crate) enum ControlError {
+    /// The client went away. When `sent` is false tmux never saw the
+    /// command, so it's safe to run it again some other way.
+    Disconnected {
+        sent: bool,
+    },
+    TimedOut,
+}
+
+impl ControlClient {
+    /// Starts a control client from `process`, a `tmux -C attach` command.
+    /// Control clients need a session to attach to, so this fails when
+    /// there's no server running.
+    pub(crate) fn connect(mut process: Command, timeout: Duration) -> Result<ControlClient> {
+        let mut child = process
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        // notifications like %output keep coming whether they're read or
+        // not, so the pipe is drained on its own thread
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                if line.map(|line| sender.send(line)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut client = ControlClient {
+            child,
+            stdin,
+            lines,
+        };
+        // attaching answers with a block of its own before anything is sent
+        let deadline = Instant::now() + timeout;
+        loop {
+            match client.next_line(deadline) {
+                Ok(line) if line.starts_with("%end ") => return Ok(client),
+                Ok(line) if line.starts_with("%error ") || line.starts_with("%exit") => break,
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+        client.close();
+        Err(anyhow!("Couldn't start a tmux control client"))
+    }
+
+    /// Runs a command over the connection, turning the `%begin`/`%end` or
+    /// `%error` block tmux answers with back into the output of a process.
+    pub(crate) fn run(
+        &mut self,
+        command: &TmuxCommand,
+        timeout: Duration,
+    ) -> std::result::Result<TmuxOutput, ControlError> {
+        writeln!(self.stdin, "{}", command_line(command))
+            .and_then(|_| self.stdin.flush())
+            .map_err(|_| ControlError::Disconnected { sent: false })?;
+
+        let deadline = Instant::now() + timeout;
+        let mut block: Option<(String, Vec<String>)> = None;
+        loop {
+            let line = self.next_line(deadline)?;
+            match &mut block {
+                None => {
+                    // a block is ours when its flags say this client sent it
+                    if let Some(header) = line.strip_prefix("%begin ") {
+                        if let [_, number, "1"] = header.split(' ').collect::<Vec<_>>()[..] {
+                            block = Some((number.to_owned(), vec![]));
+                        }
+                    } else if line.starts_with("%exit") {
+                        // tmux drops whatever a client sends once it's
+                        // detached it, so the command never ran
+                        return Err(ControlError::Disconnected { sent: false });
+                    }
+                }
+                Some((number, output)) => {
+                    let end = |prefix: &str| {
+                        line.strip_prefix(prefix)
+                            .and_then(|rest| rest.split(' ').nth(1))
+                            == Some(number.as_str())
+                    };
+                    if end("%end ") || end("%error ") {
+                        let succeeded = end("%end ");
+                        let mut text = output.join("\n").into_bytes();
+                        if !text.is_empty() {
+                            text.push(b'\n');
+                        }
+                        let (stdout, stderr) = if succeeded {
+                            (text, vec![])
+                        } else {
+                            (vec![], text)
+                        };
+                        return Ok(TmuxOutput(Output {
+                            status: exit_status(succeeded),
+                            stdout,
+                            stderr,
+                        }));
+                    }
+                    output.push(line);
+                }
+            }
+        }
+    }
+
+    fn next_line(&self, deadline: Instant) -> std::result::Result<String, ControlError> {
+        let wait = deadline.saturating_duration_since(Instant::now());
+        self.lines.recv_timeout(wait).map_err(|err| match err {
+            RecvTimeoutError::Timeout => ControlError::TimedOut,
+            RecvTimeoutError::Disconnected => ControlError::Disconnected { sent: true },
+        })
+    }
+
+    pub(crate) fn close(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Drop for ControlClient {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+// The command as a line tmux's own parser reads back into the same
+// arguments. Each argument is double quoted, with the characters that are
+// special in there escaped.
+pub(crate) fn command_line(command: &TmuxCommand) -> String {
+    let mut line = command.cmd.as_deref().unwrap_or_default().to_owned();
+    for arg in command.cmd_args.iter().flatten() {
+        line.push_str(" \"");
+        for c in arg.chars() {
+            match c {
+                '\\' | '"' | '$' => {
+                    line.push('\\');
+                    line.push(c);
+                }
+                '\n' => line.push_str("\\n"),
+                '\r' => line.push_str("\\r"),
+                '\t' => line.push_str("\\t"),
+                _ => line.push(c),
+            }
+        }
+        line.push('"');
+    }
+    line
+}
+
+#[cfg(unix)]
+pub(crate) fn exit_status(succeeded: bool) -> std::process::ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    std::process::ExitStatus::from_raw(if succeeded { 0 } else { 1 << 8 })
+}
+
+#[cfg(windows)]
+pub(crate) fn exit_status(succeeded: bool) -> std::process::ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+    std::process::ExitStatus::from_raw(if succeeded { 0 } else { 1 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_lines_quote_every_argument() {
+        let mut send_keys = TmuxCommand::new().send_keys();
+        send_keys.target_pane("%1").key("echo \"$HOME\"\r");
+        assert_eq!(
+            command_line(&send_keys.0),
+            r#"send "-t" "%1" "echo \"\$HOME\"\r""#
+        );
+    }
+}
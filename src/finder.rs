@@ -0,0 +1,288 @@
+// pick
+// matches
+
+use anyhow::Result;
+
+/// Picks one of `items` in the terminal itself, narrowing them down to the
+/// ones fuzzy matching what's typed, or nothing when it's cancelled with
+/// escape or ctrl-c. Up and down, or ctrl-p and ctrl-n, move the selection
+/// and ctrl-u clears what's typed.
+#[cfg(unix)]
+pub fn pick(items: &[String]) -> Result<Option<String>> {
+    let mut tty = Tty::open()?;
+    let mut query = String::new();
+    let mut selected = 0;
+    loop {
+        let matches = matches(items, &query);
+        selected = selected.min(matches.len().saturating_sub(1));
+        tty.draw(&query, &matches, items.len(), selected)?;
+        match tty.key()? {
+            Key::Char(c) => {
+                query.push(c);
+                selected = 0;
+            }
+            Key::Backspace => {
+                query.pop();
+                selected = 0;
+            }
+            Key::Clear => {
+                query.clear();
+                selected = 0;
+            }
+            Key::Up => selected = selected.saturating_sub(1),
+            Key::Down => selected += 1,
+            Key::Enter => return Ok(matches.get(selected).map(|item| item.to_string())),
+            Key::Cancel => return Ok(None),
+            Key::Other => {}
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn pick(_items: &[String]) -> Result<Option<String>> {
+    Err(anyhow!(
+        "The built-in finder needs a unix terminal, set finder = \"fzf\" in the config"
+    ))
+}
+
+// The items matching `query`, best first. Ties keep the order they're given
+// in, so a source that ranks its items, like zoxide, keeps its ranking.
+fn matches<'a>(items: &'a [String], query: &str) -> Vec<&'a str> {
+    let mut scored: Vec<(i64, &str)> = items
+        .iter()
+        .filter_map(|item| Some((score(item, query)?, item.as_str())))
+        .collect();
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    scored.into_iter().map(|(_, item)| item).collect()
+}
+
+// How well `item` matches `query`, whose characters have to show up in it in
+// order, or None when they don't. Characters matched in a row, at the start
+// of a word or in the last part of a path score higher. A query in lowercase
+// ignores case.
+fn score(item: &str, query: &str) -> Option<i64> {
+    let ignore_case = !query.chars().any(char::is_uppercase);
+    let fold = |c: char| {
+        if ignore_case {
+            c.to_lowercase().next().unwrap_or(c)
+        } else {
+            c
+        }
+    };
+    let last_part = item.trim_end_matches('/').rfind('/').map_or(0, |at| at + 1);
+    let mut wanted = query.chars().map(fold).peekable();
+    let mut score = 0;
+    let mut previous: Option<char> = None;
+    let mut in_a_row = false;
+    for (at, c) in item.char_indices() {
+        let Some(&next) = wanted.peek() else {
+            break;
+        };
+        if fold(c) == next {
+            wanted.next();
+            score += 1;
+            if in_a_row {
+                score += 4;
+            }
+            if previous.is_none_or(|p| matches!(p, '/' | '-' | '_' | '.' | ' ')) {
+                score += 3;
+            }
+            if at >= last_part {
+                score += 2;
+            }
+            in_a_row = true;
+        } else {
+            in_a_row = false;
+        }
+        previous = Some(c);
+    }
+    wanted.peek().is_none().then_some(score)
+}
+
+#[cfg(unix)]
+enum Key {
+    Char(char),
+    Backspace,
+    Clear,
+    Up,
+    Down,
+    Enter,
+    Cancel,
+    Other,
+}
+
+// The controlling terminal in raw mode, drawn on in the alternate screen so
+// whatever was on it comes back once it's dropped
+#[cfg(unix)]
+struct Tty {
+    file: std::fs::File,
+    saved: libc::termios,
+}
+
+#[cfg(unix)]
+impl Tty {
+    fn open() -> Result<Tty> {
+        use std::os::unix::io::AsRawFd;
+
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .map_err(|err| anyhow!("Couldn't open the terminal to pick from: {}", err))?;
+        let fd = file.as_raw_fd();
+        // SAFETY: termios is plain data that tcgetattr fills in
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut saved) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let mut raw = saved;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let mut tty = Tty { file, saved };
+        tty.write("\x1b[?1049h")?;
+        Ok(tty)
+    }
+
+    fn write(&mut self, text: &str) -> Result<()> {
+        use std::io::Write;
+
+        self.file.write_all(text.as_bytes())?;
+        self.file.flush()?;
+        Ok(())
+    }
+
+    // rows and columns, or a small terminal's when they can't be read
+    fn size(&self) -> (usize, usize) {
+        use std::os::unix::io::AsRawFd;
+
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        let read = unsafe { libc::ioctl(self.file.as_raw_fd(), libc::TIOCGWINSZ, &mut size) };
+        if read != 0 || size.ws_row == 0 || size.ws_col == 0 {
+            return (24, 80);
+        }
+        (size.ws_row.into(), size.ws_col.into())
+    }
+
+    // The prompt on top, how many items match under it and then as many
+    // matches as fit, scrolled to keep the selected one in view
+    fn draw(&mut self, query: &str, matches: &[&str], total: usize, selected: usize) -> Result<()> {
+        let (rows, cols) = self.size();
+        let shown = rows.saturating_sub(2).max(1);
+        let first = selected.saturating_sub(shown - 1);
+        let mut frame = format!("\x1b[H\x1b[2J> {}\r\n  {}/{}", query, matches.len(), total);
+        for (nth, item) in matches.iter().enumerate().skip(first).take(shown) {
+            let item: String = item.chars().take(cols.saturating_sub(2)).collect();
+            if nth == selected {
+                frame.push_str(&format!("\r\n\x1b[7m> {}\x1b[0m", item));
+            } else {
+                frame.push_str(&format!("\r\n  {}", item));
+            }
+        }
+        frame.push_str(&format!("\x1b[1;{}H", query.chars().count() + 3));
+        self.write(&frame)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        use std::io::Read;
+
+        let mut byte = [0];
+        self.file.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    // The next byte when it comes right away, as it does in the middle of
+    // an escape sequence but not after a lone escape
+    fn byte_soon(&mut self) -> Result<Option<u8>> {
+        use std::os::unix::io::AsRawFd;
+
+        let mut poll = libc::pollfd {
+            fd: self.file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut poll, 1, 25) } <= 0 {
+            return Ok(None);
+        }
+        self.byte().map(Some)
+    }
+
+    fn key(&mut self) -> Result<Key> {
+        let byte = self.byte()?;
+        Ok(match byte {
+            b'\r' | b'\n' => Key::Enter,
+            // ctrl-c and ctrl-g
+            3 | 7 => Key::Cancel,
+            // ctrl-p and ctrl-k
+            0x10 | 0x0b => Key::Up,
+            // ctrl-n
+            0x0e => Key::Down,
+            // ctrl-u
+            0x15 => Key::Clear,
+            0x7f | 8 => Key::Backspace,
+            0x1b => match self.byte_soon()? {
+                None => Key::Cancel,
+                Some(b'[' | b'O') => match self.byte_soon()? {
+                    Some(b'A') => Key::Up,
+                    Some(b'B') => Key::Down,
+                    _ => Key::Other,
+                },
+                Some(_) => Key::Other,
+            },
+            0x80.. => {
+                let len = match byte {
+                    0xf0.. => 4,
+                    0xe0.. => 3,
+                    _ => 2,
+                };
+                let mut bytes = vec![byte];
+                for _ in 1..len {
+                    bytes.push(self.byte()?);
+                }
+                match String::from_utf8(bytes).ok().and_then(|c| c.chars().next()) {
+                    Some(c) => Key::Char(c),
+                    None => Key::Other,
+                }
+            }
+            0x20.. => Key::Char(byte.into()),
+            _ => Key::Other,
+        })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Tty {
+    fn drop(&mut self) {
+        use std::os::unix::io::AsRawFd;
+
+        let _ = self.write("\x1b[?1049l");
+        unsafe { libc::tcsetattr(self.file.as_raw_fd(), libc::TCSANOW, &self.saved) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_are_ranked_by_how_well_they_match() {
+        let items: Vec<String> = [
+            "/src/site/docs",
+            "/src/dmux",
+            "/home/me/Documents",
+            "/src/api",
+        ]
+        .iter()
+        .map(|item| item.to_string())
+        .collect();
+        assert_eq!(matches(&items, ""), items);
+        assert_eq!(
+            matches(&items, "doc"),
+            ["/src/site/docs", "/home/me/Documents"]
+        );
+        assert_eq!(matches(&items, "Doc"), ["/home/me/Documents"]);
+        assert_eq!(matches(&items, "dmx"), ["/src/dmux"]);
+        assert!(matches(&items, "xyz").is_empty());
+    }
+}
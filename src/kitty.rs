@@ -0,0 +1,266 @@
+// Kitty::open
+// session_names
+
+use anyhow::Result;
+
+use crate::multiplexer::{cli_runs, run_cli, send_commands, split_panes, Multiplexer};
+use crate::tmux::{SplitDirection, WorkSpace};
+
+// the user variable marking the kitty windows a dmux session opened
+const SESSION_VAR: &str = "dmux_session";
+
+/// Opens workspaces in kitty through its remote control protocol, so kitty
+/// has to allow remote control. kitty has no sessions, so the windows dmux
+/// opens are marked with the session's name: each dmux window is a tab in
+/// the `splits` layout, and each pane a kitty window in it. Layout checksums
+/// are tmux's own and are left out.
+#[derive(Debug, Clone)]
+pub struct Kitty {
+    pub(crate) bin: String,
+}
+
+impl Default for Kitty {
+    fn default() -> Self {
+        Kitty {
+            bin: "kitty".to_owned(),
+        }
+    }
+}
+
+impl Kitty {
+    pub fn new() -> Kitty {
+        Kitty::default()
+    }
+
+    fn remote(&self, args: &[&str]) -> Result<String> {
+        let args: Vec<String> = ["@"].iter().chain(args).map(|a| a.to_string()).collect();
+        run_cli(&self.bin, &args)
+    }
+
+    // Opens a kitty window for the `nth` pane of the workspace, with the
+    // session marked on it, returning its id
+    fn launch(&self, workspace: &WorkSpace, nth: usize, placement: &[&str]) -> Result<String> {
+        let var = format!("{}={}", SESSION_VAR, workspace.session_name);
+        let dir = workspace.pane_dir(nth as u8);
+        let mut args = vec!["launch", "--cwd", &dir, "--var", &var];
+        args.extend(placement);
+        let env = workspace.pane_env(nth as u8);
+        for pair in &env {
+            args.extend(["--env", pair]);
+        }
+        self.remote(&args)
+    }
+
+    // Opens a tab for the workspace and splits it into the workspace's
+    // panes, typing each command into its pane
+    fn open_tab(&self, workspace: &WorkSpace) -> Result<()> {
+        let title = workspace.window_name();
+        let first = self.launch(workspace, 0, &["--type=tab", "--tab-title", &title])?;
+        self.remote(&[
+            "goto-layout",
+            "--match",
+            &format!("window_id:{}", first),
+            "splits",
+        ])?;
+
+        let panes = split_panes(workspace, first, |step, target| {
+            let location = match step.direction {
+                SplitDirection::Horizontal => "vsplit",
+                SplitDirection::Vertical => "hsplit",
+            };
+            self.launch(
+                workspace,
+                step.pane,
+                &[
+                    "--type=window",
+                    "--next-to",
+                    &format!("id:{}", target),
+                    "--location",
+                    location,
+                    "--bias",
+                    &step.percent.to_string(),
+                ],
+            )
+        })?;
+        send_commands(workspace, &panes, |pane, line| {
+            // send-text reads its text with Python's escapes
+            let text = format!("{}\\r", line.replace('\\', "\\\\"));
+            self.remote(&["send-text", "--match", &format!("id:{}", pane), &text])?;
+            Ok(())
+        })
+    }
+}
+
+impl Multiplexer for Kitty {
+    fn name(&self) -> &'static str {
+        "kitty"
+    }
+
+    fn is_installed(&self) -> bool {
+        cli_runs(&self.bin, &["--version"])
+    }
+
+    fn session_names(&self) -> Result<Vec<String>> {
+        let mut names = session_names(&self.remote(&["ls"])?);
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    fn open(&self, workspace: WorkSpace, ready: &mut dyn FnMut() -> Result<()>) -> Result<String> {
+        let workspace = workspace.normalized()?;
+        if !self.session_names()?.contains(&workspace.session_name) {
+            self.open_tab(&workspace)?;
+            for window in workspace.window_workspaces()? {
+                self.open_tab(&window)?;
+            }
+        }
+
+        ready()?;
+        if workspace.detached {
+            return Ok(workspace.session_name);
+        }
+        let session = format!("var:{}={}", SESSION_VAR, workspace.session_name);
+        self.remote(&["focus-window", "--match", &session])?;
+        Ok(workspace.session_name)
+    }
+}
+
+// The sessions marked on the windows in the JSON of `kitty @ ls`, which puts
+// each user variable on a line of its own
+fn session_names(ls: &str) -> Vec<String> {
+    let prefix = format!("\"{}\": \"", SESSION_VAR);
+    ls.lines()
+        .filter_map(|line| line.trim().strip_prefix(&prefix))
+        .filter_map(|rest| rest.trim_end_matches(',').strip_suffix('"'))
+        .map(str::to_owned)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tmux::PaneSpec;
+
+    #[test]
+    fn sessions_are_read_from_window_variables() {
+        let ls = r#"[
+  {
+    "tabs": [
+      {
+        "windows": [
+          {
+            "id": 1,
+            "user_vars": {
+              "dmux_session": "site",
+              "other": "x"
+            }
+          },
+          {
+            "id": 2,
+            "user_vars": {}
+          }
+        ]
+      }
+    ]
+  }
+]"#;
+        assert_eq!(session_names(ls), ["site"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn panes_are_kitty_windows_in_a_tab() {
+        use crate::multiplexer::fake_cli;
+
+        // new windows are numbered by how many calls came before them
+        let (bin, log) = fake_cli(
+            "kitty",
+            "case \"$2\" in\n\
+             ls) echo '[]' ;;\n\
+             launch) wc -l < $LOG | tr -d ' ' ;;\n\
+             esac",
+        );
+        let kitty = Kitty { bin };
+        let dir = std::env::temp_dir();
+        let workspace = WorkSpace {
+            path: dir.clone(),
+            session_name: "site".to_owned(),
+            format_checksum: "tiled".to_owned(),
+            window_name: Some("dev".to_owned()),
+            commands: vec![String::new(), "npm start".to_owned()],
+            panes: vec![
+                PaneSpec::default(),
+                PaneSpec {
+                    env: vec![("PORT".to_owned(), "3000".to_owned())],
+                    ..Default::default()
+                },
+            ],
+            detached: true,
+            ..Default::default()
+        };
+        kitty.open(workspace, &mut || Ok(())).unwrap();
+
+        let dir = dir.display();
+        let calls = std::fs::read_to_string(log).unwrap();
+        let expected = [
+            "@ ls".to_owned(),
+            format!(
+                "@ launch --cwd {} --var dmux_session=site --type=tab --tab-title dev",
+                dir
+            ),
+            "@ goto-layout --match window_id:2 splits".to_owned(),
+            format!(
+                "@ launch --cwd {} --var dmux_session=site --type=window --next-to id:2 \
+                 --location vsplit --bias 50 --env PORT=3000",
+                dir
+            ),
+            "@ send-text --match id:4 npm start\\r".to_owned(),
+        ];
+        assert_eq!(calls.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ready_runs_before_focusing_the_tab() {
+        use crate::multiplexer::fake_cli;
+        use std::io::Write;
+
+        let (bin, log) = fake_cli(
+            "kitty-ready",
+            "case \"$2\" in\n\
+             ls) echo '[]' ;;\n\
+             launch) wc -l < $LOG | tr -d ' ' ;;\n\
+             esac",
+        );
+        let kitty = Kitty { bin };
+        let workspace = WorkSpace {
+            path: std::env::temp_dir(),
+            session_name: "site".to_owned(),
+            format_checksum: "tiled".to_owned(),
+            window_name: Some("dev".to_owned()),
+            ..Default::default()
+        };
+        kitty
+            .open(workspace, &mut || {
+                let mut log = std::fs::OpenOptions::new().append(true).open(&log)?;
+                writeln!(log, "ready")?;
+                Ok(())
+            })
+            .unwrap();
+
+        let calls = std::fs::read_to_string(&log).unwrap();
+        let calls: Vec<&str> = calls
+            .lines()
+            .map(|call| call.split(' ').take(2).collect::<Vec<_>>())
+            .map(|call| match call[..] {
+                ["@", command] => command,
+                _ => "ready",
+            })
+            .collect();
+        assert_eq!(
+            calls,
+            ["ls", "launch", "goto-layout", "ready", "focus-window"]
+        );
+    }
+}
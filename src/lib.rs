@@ -0,0 +1,16 @@
+#[macro_use]
+extern crate anyhow;
+#[macro_use]
+extern crate log;
+
+mod control;
+pub mod kitty;
+pub mod multiplexer;
+pub mod resurrect;
+pub mod screen;
+pub mod snapshot;
+pub mod tmux;
+#[cfg(feature = "tokio")]
+pub mod tmux_async;
+pub mod wezterm;
+pub mod zellij;
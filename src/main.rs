@@ -4,19 +4,29 @@ extern crate serde_derive;
 extern crate anyhow;
 
 mod app;
+mod finder;
 mod select;
-mod tmux;
+mod worktree;
 
 use anyhow::Result;
 use app::CommandType;
 use colored::*;
-use select::Selector;
-use std::path::PathBuf;
+use dmux::kitty::Kitty;
+use dmux::multiplexer::Multiplexer;
+use dmux::screen::Screen;
+use dmux::snapshot;
+use dmux::tmux::{self, Socket, Tmux, TmuxConfig, WorkSpace};
+use dmux::wezterm::WezTerm;
+use dmux::zellij::Zellij;
+use select::{Finder, Selector};
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
-use tmux::WorkSpace;
+use std::time::Duration;
 use url::Url;
 
 fn main() {
+    // RUST_LOG=debug shows every tmux command as it's run
+    env_logger::init();
     if let Err(err) = run_command() {
         eprintln!("{}: {}", "Error".red(), err);
         err.chain()
@@ -29,74 +39,485 @@ fn main() {
 fn run_command() -> Result<()> {
     let command = app::build_app()?;
 
-    if !tmux::has_tmux() {
-        return Err(anyhow!("Tmux is not installed."));
-    }
     match command {
         CommandType::Open(open_config) => open_selected_dir(open_config),
         CommandType::Select(select_config) => {
-            match Selector::new(&select_config.workspace.search_dir).select_dir()? {
+            let workspace = &select_config.workspace;
+            let selector = Selector::new(
+                &workspace.search_dir,
+                workspace.dir_source.parse()?,
+                workspace.finder.as_str().into(),
+            );
+            match selector.select_dir()? {
                 Some(dir) => open_selected_dir(app::OpenArgs {
+                    workspace: app::workspace_args_in(&dir)?,
                     selected_dir: dir,
-                    workspace: select_config.workspace,
                 }),
                 None => Ok(()),
             }
         }
         CommandType::Pull(pull_config) => match clone_from(&pull_config) {
             Ok(dir) => open_selected_dir(app::OpenArgs {
+                workspace: app::workspace_args_in(&dir)?,
                 selected_dir: dir,
-                workspace: pull_config.workspace,
             }),
             Err(err) => Err(err),
         },
         CommandType::Layout => {
+            if !tmux::has_tmux() {
+                return Err(anyhow!("Tmux is not installed."));
+            }
             if !tmux::in_tmux() {
                 return Err(anyhow!("Not inside a tmux session. Run `tmux a` and select the window you want the layout of."));
             };
-            tmux::generate_layout()
+            println!("{}", Tmux::new().generate_layout()?);
+            Ok(())
         }
+        CommandType::Kill(kill_config) => kill(kill_config),
+        CommandType::List { json, workspace } => list(json, &workspace),
+        CommandType::Snapshot(snapshot_config) => snapshot(snapshot_config),
+        CommandType::Worktree(worktree_config) => open_worktree(worktree_config),
     }
 }
 
-fn open_selected_dir(config: app::OpenArgs) -> Result<()> {
-    if !config.selected_dir.exists() {
+fn open_selected_dir(mut config: app::OpenArgs) -> Result<()> {
+    if let Some(host) = &config.workspace.host {
+        config.selected_dir = remote_dir(host, &config.selected_dir)?;
+    } else if !config.selected_dir.exists() {
         return Err(anyhow!("{:?} isn't a valid path", config.selected_dir));
     }
-    tmux::setup_workspace(WorkSpace {
+    let mut multiplexer = multiplexer(&config.workspace)?;
+    if !multiplexer.is_installed() {
+        // screen is all some servers have, though dry runs are only for tmux
+        // and it'd be this machine's screen rather than the host's
+        let fall_back = multiplexer.name() == "tmux"
+            && !config.workspace.dry_run
+            && config.workspace.host.is_none();
+        if !fall_back || !Screen::new().is_installed() {
+            return Err(anyhow!("{} is not installed.", multiplexer.name()));
+        }
+        eprintln!(
+            "{}: tmux is not installed, opening the workspace in screen",
+            "Warning".yellow()
+        );
+        multiplexer = Box::new(Screen::new());
+    }
+
+    let hooks = Hooks {
+        dir: match config.workspace.host {
+            Some(_) => std::env::current_dir()?,
+            None => config.selected_dir.clone(),
+        },
+        abort: config.workspace.abort_on_hook_failure,
+        dry_run: config.workspace.dry_run,
+    };
+    let before_setup = std::mem::take(&mut config.workspace.before_setup);
+    let after_setup = std::mem::take(&mut config.workspace.after_setup);
+    // a dry run prints its commands instead
+    let print_session = config.workspace.detached && !config.workspace.dry_run;
+    let workspace = workspace(config)?;
+    hooks.run("before_setup", &before_setup)?;
+    // after_setup runs once the session is built, rather than once attaching
+    // returns, which outside of tmux is only when the client detaches
+    let session = multiplexer.open(workspace, &mut || hooks.run("after_setup", &after_setup))?;
+    // scripts that open a workspace detached get the name to attach by
+    if print_session {
+        println!("{}", session);
+    }
+    Ok(())
+}
+
+// How the `before_setup` and `after_setup` hooks are run: with `sh` in the
+// workspace's directory, on the host rather than in a pane
+struct Hooks {
+    dir: PathBuf,
+    abort: bool,
+    dry_run: bool,
+}
+
+impl Hooks {
+    fn run(&self, kind: &str, hooks: &[String]) -> Result<()> {
+        for hook in hooks {
+            if self.dry_run {
+                println!("{}", hook);
+                continue;
+            }
+            let status = Command::new("sh")
+                .arg("-c")
+                .arg(hook)
+                .current_dir(&self.dir)
+                .status();
+            let failure = match status {
+                Ok(status) if status.success() => continue,
+                Ok(status) => format!("{} hook `{}` failed with {}", kind, hook, status),
+                Err(err) => format!("Couldn't run {} hook `{}`: {}", kind, hook, err),
+            };
+            if self.abort {
+                return Err(anyhow!(failure));
+            }
+            eprintln!("{}: {}", "Warning".yellow(), failure);
+        }
+        Ok(())
+    }
+}
+
+// `dir` on `host`, where a dir under the home dir here, or starting with
+// `~`, is taken to be under the host's home dir, as is a relative one
+fn remote_dir(host: &str, dir: &Path) -> Result<PathBuf> {
+    let home = dirs::home_dir();
+    let in_home = dir
+        .strip_prefix("~")
+        .ok()
+        .or_else(|| dir.strip_prefix(home.as_ref()?).ok());
+    let rest = match in_home {
+        Some(rest) => rest,
+        None if dir.is_absolute() => return Ok(dir.to_owned()),
+        None => dir,
+    };
+    let output = Command::new("ssh")
+        .arg(host)
+        .arg("printf %s \"$HOME\"")
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Couldn't reach {}: {}",
+            host,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(PathBuf::from(String::from_utf8(output.stdout)?).join(rest))
+}
+
+// The workspace to open in the selected dir, going by the config
+fn workspace(config: app::OpenArgs) -> Result<WorkSpace> {
+    Ok(WorkSpace {
         commands: config.workspace.commands,
         path: config.selected_dir,
         session_name: config.workspace.session_name,
+        session_name_template: config.workspace.session_name_template,
         format_checksum: config.workspace.layout,
         window_name: config.workspace.window_name,
         number_of_panes: config.workspace.number_of_panes,
-    });
+        keep_initial_window: config.workspace.keep_initial_window,
+        window_index: config.workspace.window_index,
+        login_shell: config.workspace.login_shell,
+        verify_commands: config.workspace.verify_commands,
+        wait_for_prompt: config
+            .workspace
+            .wait_for_prompt
+            .map(|seconds| {
+                Duration::try_from_secs_f64(seconds)
+                    .map_err(|_| anyhow!("wait_for_prompt has to be a number of seconds"))
+            })
+            .transpose()?,
+        fresh: config.workspace.fresh,
+        commands_by_position: config.workspace.commands_by_position,
+        post_attach: config.workspace.post_attach,
+        reapply: config.workspace.reapply,
+        reapply_commands: config.workspace.reapply_commands,
+        renumber_windows: config.workspace.renumber_windows,
+        force_commands: config.workspace.force_commands,
+        detached: config.workspace.detached,
+        remote: config.workspace.host.is_some(),
+        multiline_commands: config.workspace.multiline_commands,
+        monitor_activity: config.workspace.monitor_activity,
+        synchronize: config.workspace.synchronize,
+        monitor_silence: config.workspace.monitor_silence,
+        window_size: config
+            .workspace
+            .window_size
+            .map(|size| size.parse())
+            .transpose()?,
+        pane_border_status: config
+            .workspace
+            .pane_border_status
+            .map(|status| status.parse())
+            .transpose()?,
+        pane_border_format: config.workspace.pane_border_format,
+        windows: config
+            .workspace
+            .windows
+            .into_iter()
+            .map(|window| tmux::WindowSpec {
+                name: window.name,
+                dir: window.dir,
+                layout: window.layout,
+                commands: window.commands,
+                number_of_panes: window.number_of_panes,
+            })
+            .collect(),
+        active_window: config.workspace.active_window,
+        panes: config
+            .workspace
+            .panes
+            .into_iter()
+            .map(|pane| tmux::PaneSpec {
+                dir: pane.dir,
+                env: pane.env.into_iter().collect(),
+                title: pane.title,
+            })
+            .collect(),
+        env: config.workspace.env.into_iter().collect(),
+        splits: config.workspace.splits.map(split_tree).transpose()?,
+        options: config
+            .workspace
+            .options
+            .into_iter()
+            .map(|(scope, name, value)| Ok((scope.parse()?, name, value)))
+            .collect::<Result<_>>()?,
+    })
+}
+
+fn open_worktree(config: app::WorktreeArgs) -> Result<()> {
+    let worktree = worktree::worktree_for(
+        &std::env::current_dir()?,
+        &config.branch,
+        config.worktree_dir.as_deref(),
+    )?;
+    let mut workspace = app::workspace_args_in(&worktree.dir)?;
+    workspace.session_name = worktree.session_name;
+    workspace.session_name_template = None;
+    open_selected_dir(app::OpenArgs {
+        workspace,
+        selected_dir: worktree.dir,
+    })
+}
+
+fn kill(config: app::KillArgs) -> Result<()> {
+    let tmux = Tmux::with_config(tmux_config(&config.workspace)?);
+    if !tmux.has_tmux() {
+        return Err(anyhow!("Tmux is not installed."));
+    }
+    let selected = match config.name {
+        Some(name) => name,
+        None => {
+            match Finder::from(config.workspace.finder.as_str()).pick(&tmux.pickable_targets()?)? {
+                Some(picked) => picked,
+                None => return Ok(()),
+            }
+        }
+    };
+    // a directory stands for the workspace `dmux <dir>` would open
+    let dir = tmux::expand_path(Path::new(&selected));
+    if dir.is_dir() {
+        let dir = dir.canonicalize()?;
+        tmux.kill_workspace(workspace(app::OpenArgs {
+            workspace: app::workspace_args_in(&dir)?,
+            selected_dir: dir,
+        })?)
+    } else {
+        tmux.kill_target(&selected)
+    }
+}
+
+fn multiplexer(workspace: &app::WorkSpaceArgs) -> Result<Box<dyn Multiplexer>> {
+    if workspace.dry_run && workspace.multiplexer != "tmux" {
+        return Err(anyhow!("--dry-run only works with tmux"));
+    }
+    if workspace.host.is_some() && workspace.multiplexer != "tmux" {
+        return Err(anyhow!("--host only works with tmux"));
+    }
+    Ok(match workspace.multiplexer.as_str() {
+        "tmux" => Box::new(Tmux::with_config(tmux_config(workspace)?)),
+        "zellij" => Box::new(Zellij::new()),
+        "wezterm" => Box::new(WezTerm::new()),
+        "kitty" => Box::new(Kitty::new()),
+        "screen" => Box::new(Screen::new()),
+        other => {
+            return Err(anyhow!(
+                "{:?} isn't a multiplexer dmux supports, expected tmux, zellij, wezterm, kitty or screen",
+                other
+            ))
+        }
+    })
+}
+
+// How tmux is run going by the settings, on the server of the socket they
+// name, if any
+fn tmux_config(workspace: &app::WorkSpaceArgs) -> Result<TmuxConfig> {
+    let socket = match (&workspace.socket_name, &workspace.socket_path) {
+        (Some(_), Some(_)) => {
+            return Err(anyhow!(
+                "socket_name and socket_path can't both be set, they name different servers"
+            ))
+        }
+        (Some(name), None) => Some(Socket::Name(name.clone())),
+        (None, Some(path)) => Some(Socket::Path(path.clone())),
+        (None, None) => None,
+    };
+    Ok(TmuxConfig {
+        control_mode: workspace.control_mode,
+        dry_run: workspace.dry_run,
+        host: workspace.host.clone(),
+        socket,
+        ..Default::default()
+    })
+}
+
+fn list(json: bool, workspace: &app::WorkSpaceArgs) -> Result<()> {
+    let sessions = Tmux::with_config(tmux_config(workspace)?).sessions()?;
+    if json {
+        return print_json(&sessions);
+    }
+    for session in sessions {
+        let attached = match session.attached() {
+            0 => String::new(),
+            clients => format!(" (attached to {})", clients),
+        };
+        println!("{}{}", session.name.bold(), attached);
+        for window in session.iter_windows() {
+            let panes = window.panes_summary();
+            // a window's directory is where its first pane is
+            let dir = panes
+                .first()
+                .and_then(|(_, _, path)| *path)
+                .map(|path| path.display().to_string())
+                .unwrap_or_default();
+            let titles: Vec<&str> = window
+                .iter_panes()
+                .filter_map(|pane| pane.title())
+                .collect();
+            let titles = if titles.is_empty() {
+                String::new()
+            } else {
+                format!(": {}", titles.join(", "))
+            };
+            println!(
+                "  {}: {} ({} panes{}) {}",
+                window.index,
+                window.name,
+                panes.len(),
+                titles,
+                dir
+            );
+        }
+    }
+    Ok(())
+}
+
+fn snapshot(config: app::SnapshotArgs) -> Result<()> {
+    let tmux = Tmux::with_config(tmux_config(&config.workspace)?);
+    let name = match config.session.or_else(|| tmux.current_session()) {
+        Some(name) => name,
+        None => {
+            return Err(anyhow!(
+                "Not inside a tmux session, name the session to snapshot."
+            ))
+        }
+    };
+    let session = tmux
+        .find_session(&name)?
+        .ok_or_else(|| anyhow!("There's no tmux session named {}", name))?;
+    let workspace = snapshot::snapshot(&session)?;
+    print!(
+        "{}",
+        snapshot::to_config(config.profile.as_deref().unwrap_or(&name), &workspace)
+    );
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+fn print_json(sessions: &[tmux::Session]) -> Result<()> {
+    println!("{}", Tmux::to_json(sessions)?);
     Ok(())
 }
 
+#[cfg(not(feature = "serde"))]
+fn print_json(_: &[tmux::Session]) -> Result<()> {
+    Err(anyhow!(
+        "--json needs dmux built with the serde feature, as in `cargo install dmux --features serde`"
+    ))
+}
+
+fn split_tree(args: app::SplitArgs) -> Result<tmux::SplitTree> {
+    Ok(tmux::SplitTree {
+        split: args.split.map(|split| split.parse()).transpose()?,
+        size: args
+            .size
+            .map(|size| {
+                size.trim_end_matches('%')
+                    .parse()
+                    .map_err(|_| anyhow!("{:?} isn't a percent", size))
+            })
+            .transpose()?,
+        children: args
+            .children
+            .into_iter()
+            .map(split_tree)
+            .collect::<Result<_>>()?,
+    })
+}
+
 fn git_url_to_dir_name(git_url: &str) -> Result<String> {
     if let Ok(url) = Url::parse(git_url) {
         Ok(url
             .path_segments()
             .ok_or_else(|| anyhow!("cannot be base"))?
-            .last()
+            .next_back()
             .ok_or_else(|| anyhow!("no segments"))?
             .replace(".git", ""))
     } else {
         Ok(git_url
             .split('/')
-            .last()
+            .next_back()
             .ok_or_else(|| anyhow!("I don't know how to parse a dir from {:?}", git_url))?
             .replace(".git", ""))
     }
 }
 
+// `org/repo` as the URL of that GitHub repo over `protocol`, and any other
+// repo as it's given
+fn expand_repo(repo: &str, protocol: &str) -> Result<String> {
+    let parts: Vec<&str> = repo.split('/').collect();
+    let shorthand = parts.len() == 2
+        && parts.iter().all(|part| !part.is_empty())
+        && !repo.contains(':')
+        && !repo.starts_with(['.', '~']);
+    if !shorthand {
+        return Ok(repo.to_owned());
+    }
+    let repo = repo.trim_end_matches(".git");
+    match protocol {
+        "https" => Ok(format!("https://github.com/{}.git", repo)),
+        "ssh" => Ok(format!("git@github.com:{}.git", repo)),
+        _ => Err(anyhow!(
+            "Unknown clone protocol {:?}, expected https or ssh",
+            protocol
+        )),
+    }
+}
+
+// Whether gh is installed and logged in to GitHub, so cloning through it
+// reuses its credentials
+fn gh_logged_in() -> bool {
+    Command::new("gh")
+        .args(["auth", "status", "--hostname", "github.com"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
 fn clone_from(config: &app::PullArgs) -> Result<PathBuf> {
-    let dir_name = git_url_to_dir_name(&config.repo_url)?;
+    let repo_url = expand_repo(&config.repo_url, &config.protocol)?;
+    let dir_name = match &config.name {
+        Some(name) => name.clone(),
+        None => git_url_to_dir_name(&repo_url)?,
+    };
     let target = config.target_dir.join(dir_name);
-    let output = Command::new("git")
-        .arg("clone")
-        .arg(config.repo_url.as_str())
+    let mut clone = if repo_url.contains("github.com") && gh_logged_in() {
+        let mut gh = Command::new("gh");
+        gh.args(["repo", "clone"]);
+        gh
+    } else {
+        let mut git = Command::new("git");
+        git.arg("clone");
+        git
+    };
+    let output = clone
+        .arg(repo_url.as_str())
         .arg(
             target
                 .to_str()
@@ -128,6 +549,26 @@ fn clone_from(config: &app::PullArgs) -> Result<PathBuf> {
 //     Ok(String::from(file_str?))
 // }
 
+#[test]
+fn github_shorthand_is_expanded() {
+    assert_eq!(
+        expand_repo("zdcthomas/dmux", "https").unwrap(),
+        "https://github.com/zdcthomas/dmux.git"
+    );
+    assert_eq!(
+        expand_repo("zdcthomas/dmux.git", "ssh").unwrap(),
+        "git@github.com:zdcthomas/dmux.git"
+    );
+    for repo in [
+        "git@github.com:zdcthomas/dmux.git",
+        "https://gitlab.com/org/repo",
+        "../repos/dmux",
+    ] {
+        assert_eq!(expand_repo(repo, "https").unwrap(), repo);
+    }
+    assert!(expand_repo("zdcthomas/dmux", "git").is_err());
+}
+
 #[test]
 fn git_url_to_dir_name_test() {
     assert_eq!(
@@ -139,3 +580,25 @@ fn git_url_to_dir_name_test() {
         git_url_to_dir_name("git@github.com:zdcthomas/dmux.git").unwrap()
     );
 }
+
+#[test]
+fn hooks_run_in_the_workspace_dir() {
+    let dir = std::env::temp_dir().join(format!("dmux-hooks-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let hooks = Hooks {
+        dir: dir.clone(),
+        abort: false,
+        dry_run: false,
+    };
+    let ran = ["false".to_owned(), "touch ran".to_owned()];
+    assert!(hooks.run("before_setup", &ran).is_ok());
+    assert!(dir.join("ran").is_file());
+
+    let aborting = Hooks {
+        abort: true,
+        ..hooks
+    };
+    let failed = aborting.run("after_setup", &ran).unwrap_err().to_string();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(failed.starts_with("after_setup hook `false` failed"));
+}
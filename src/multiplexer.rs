@@ -0,0 +1,156 @@
+// Multiplexer::open
+// Multiplexer::is_installed
+
+use std::process::{Command, Stdio};
+
+use anyhow::Result;
+
+use crate::tmux::{command_lines, warn, PlannedSplit, SplitTree, Tmux, WorkSpace};
+
+/// Launches workspaces in a terminal multiplexer, so the same config and
+/// picker work over tmux or Zellij. It only works at the level of whole
+/// workspaces: sessions, windows and panes can't be driven one at a time
+/// through it, which is left to `Tmux` and the types it hands out.
+pub trait Multiplexer {
+    /// How the multiplexer is called in messages, like `tmux`.
+    fn name(&self) -> &'static str;
+
+    /// Whether the multiplexer's binary can be run.
+    fn is_installed(&self) -> bool;
+
+    /// The names of the sessions that are open, none when there's no
+    /// server running.
+    fn session_names(&self) -> Result<Vec<String>>;
+
+    /// Opens the workspace and attaches to it, unless it's `detached`. A
+    /// session that's already open is reused rather than opened twice.
+    /// `ready` is called once the session is built and before attaching,
+    /// which can block until the client detaches, and its error is returned
+    /// without attaching. Returns the session's name, as the multiplexer
+    /// ended up calling it.
+    fn open(&self, workspace: WorkSpace, ready: &mut dyn FnMut() -> Result<()>) -> Result<String>;
+}
+
+// Backends that start panes through a command line tool have no way to
+// give them variables of their own
+pub(crate) fn warn_env_ignored(name: &str, workspace: &WorkSpace) {
+    if !workspace.env.is_empty() || workspace.panes.iter().any(|p| !p.env.is_empty()) {
+        warn(format!(
+            "{} can't set environment variables for a pane, so env is ignored",
+            name
+        ));
+    }
+}
+
+// Splits the pane `first` into the workspace's panes the way `splits` says,
+// or with a main pane on the left, returning their ids in order. `split`
+// makes the pane for one split off the pane with the given id.
+pub(crate) fn split_panes(
+    workspace: &WorkSpace,
+    first: String,
+    mut split: impl FnMut(&PlannedSplit, &str) -> Result<String>,
+) -> Result<Vec<String>> {
+    let tree = workspace
+        .splits
+        .clone()
+        .unwrap_or_else(|| SplitTree::main_vertical(workspace.number_of_panes().into()));
+    let mut panes = vec![None; tree.pane_count()];
+    panes[0] = Some(first);
+    for step in tree.plan() {
+        let target = panes[step.target]
+            .clone()
+            .expect("split panes are made first");
+        panes[step.pane] = Some(split(&step, &target)?);
+    }
+    Ok(panes.into_iter().flatten().collect())
+}
+
+// Types each of the workspace's commands into its pane, a line at a time,
+// with `send` given the pane's id and the line.
+pub(crate) fn send_commands(
+    workspace: &WorkSpace,
+    panes: &[String],
+    mut send: impl FnMut(&str, &str) -> Result<()>,
+) -> Result<()> {
+    for (pane, command) in panes.iter().zip(&workspace.commands) {
+        for line in command_lines(command)? {
+            if !line.is_empty() {
+                send(pane, &line)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Whether `bin` runs and exits cleanly with `args`, like `--version`.
+pub(crate) fn cli_runs(bin: &str, args: &[&str]) -> bool {
+    Command::new(bin)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+// Runs a multiplexer's command line tool, returning what it printed, or
+// what it complained about as the error.
+pub(crate) fn run_cli(bin: &str, args: &[String]) -> Result<String> {
+    debug!("running {} {}", bin, args.join(" "));
+    let output = Command::new(bin).args(args).stdin(Stdio::null()).output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Couldn't run {} {}: {}",
+            bin,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+impl Multiplexer for Tmux {
+    fn name(&self) -> &'static str {
+        "tmux"
+    }
+
+    fn is_installed(&self) -> bool {
+        self.has_tmux()
+    }
+
+    fn session_names(&self) -> Result<Vec<String>> {
+        Ok(self
+            .sessions()?
+            .into_iter()
+            .map(|session| session.name)
+            .collect())
+    }
+
+    fn open(&self, workspace: WorkSpace, ready: &mut dyn FnMut() -> Result<()>) -> Result<String> {
+        Ok(self.setup_workspace_then(workspace, ready)?.session)
+    }
+}
+
+// A stand-in for a multiplexer's command line tool that logs its arguments
+// a line at a time before running `script`, in which `$LOG` is the log.
+// They're logged with printf since some shells' echo reads escapes.
+#[cfg(all(test, unix))]
+pub(crate) fn fake_cli(name: &str, script: &str) -> (String, std::path::PathBuf) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("dmux-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (fake, log) = (dir.join(name), dir.join("log"));
+    let _ = std::fs::remove_file(&log);
+    std::fs::write(
+        &fake,
+        format!(
+            "#!/bin/sh\nprintf '%s\\n' \"$*\" >> '{}'\n{}",
+            log.display(),
+            script.replace("$LOG", &format!("'{}'", log.display()))
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+    (fake.to_string_lossy().into_owned(), log)
+}
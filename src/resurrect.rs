@@ -0,0 +1,186 @@
+// load
+// parse
+// default_path
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::tmux::{PaneSpec, WorkSpace};
+
+/// The workspaces read from a tmux-resurrect save, along with descriptions of
+/// the parts of it dmux has no way to represent.
+#[derive(Debug, Default)]
+pub struct Restore {
+    pub workspaces: Vec<WorkSpace>,
+    pub skipped: Vec<String>,
+}
+
+struct SavedPane {
+    index: u8,
+    dir: String,
+    command: String,
+}
+
+/// Where tmux-resurrect links its most recent save, if there is one.
+pub fn default_path() -> Option<PathBuf> {
+    let xdg_save = dirs::data_dir().map(|dir| dir.join("tmux/resurrect/last"));
+    let home_save = dirs::home_dir().map(|dir| dir.join(".tmux/resurrect/last"));
+    [xdg_save, home_save]
+        .into_iter()
+        .flatten()
+        .find(|path| path.exists())
+}
+
+pub fn load(path: &Path) -> Result<Restore> {
+    Ok(parse(&fs::read_to_string(path)?))
+}
+
+/// Converts each saved window into a workspace. Only the window's name and
+/// layout and its panes' directories and commands are carried over.
+pub fn parse(save: &str) -> Restore {
+    let mut restore = Restore::default();
+    let mut panes: BTreeMap<(&str, &str), Vec<SavedPane>> = BTreeMap::new();
+    let mut windows = vec![];
+
+    for line in save.lines().filter(|l| !l.is_empty()) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let pane = match fields[..] {
+            // saves since resurrect 3.0 include the pane title
+            ["pane", session, window, _, _, index, _, dir, _, _, command] => {
+                Some((session, window, index, dir, command))
+            }
+            ["pane", session, window, _, _, index, dir, _, _, command] => {
+                Some((session, window, index, dir, command))
+            }
+            ["window", session, index, name, _, flags, layout, ..] => {
+                if flags.contains('Z') {
+                    restore
+                        .skipped
+                        .push(format!("zoom of window {}:{}", session, index));
+                }
+                windows.push((session, index, strip_colon(name), layout));
+                None
+            }
+            _ => {
+                restore.skipped.push(format!("line {:?}", line));
+                None
+            }
+        };
+
+        if let Some((session, window, index, dir, command)) = pane {
+            match index.parse() {
+                Ok(index) => panes.entry((session, window)).or_default().push(SavedPane {
+                    index,
+                    dir: strip_colon(dir).replace("\\ ", " "),
+                    command: strip_colon(command).to_owned(),
+                }),
+                Err(_) => restore.skipped.push(format!("line {:?}", line)),
+            }
+        }
+    }
+
+    for (session, index, name, layout) in windows {
+        let mut window_panes = panes.remove(&(session, index)).unwrap_or_default();
+        window_panes.sort_by_key(|p| p.index);
+
+        // the first pane decides the workspace's directory, and the other
+        // panes only get one of their own when theirs is different
+        let dir = window_panes
+            .first()
+            .map(|p| p.dir.clone())
+            .unwrap_or_default();
+        let mut pane_specs: Vec<PaneSpec> = window_panes
+            .iter()
+            .map(|p| PaneSpec {
+                dir: (p.dir != dir).then(|| PathBuf::from(&p.dir)),
+                ..Default::default()
+            })
+            .collect();
+        while pane_specs.last().is_some_and(|p| p.dir.is_none()) {
+            pane_specs.pop();
+        }
+
+        let mut commands: Vec<String> = window_panes.iter().map(|p| p.command.clone()).collect();
+        while commands.last().is_some_and(String::is_empty) {
+            commands.pop();
+        }
+
+        restore.workspaces.push(WorkSpace {
+            path: PathBuf::from(dir),
+            session_name: session.to_owned(),
+            format_checksum: layout.to_owned(),
+            commands,
+            window_name: Some(name.to_owned()),
+            number_of_panes: window_panes.len() as u8,
+            panes: pane_specs,
+            ..Default::default()
+        });
+    }
+
+    for (session, window) in panes.keys() {
+        restore
+            .skipped
+            .push(format!("panes of unknown window {}:{}", session, window));
+    }
+    restore
+}
+
+// resurrect prefixes fields that might be empty with a colon
+fn strip_colon(field: &str) -> &str {
+    field.strip_prefix(':').unwrap_or(field)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_windows_panes_and_commands() {
+        let restore = parse(include_str!("../tests/fixtures/resurrect_last.txt"));
+        assert_eq!(restore.workspaces.len(), 2);
+
+        let dmux = &restore.workspaces[0];
+        assert_eq!(dmux.session_name, "dev");
+        assert_eq!(dmux.window_name.as_deref(), Some("dmux"));
+        assert_eq!(dmux.path, PathBuf::from("/home/zt/dev/dmux"));
+        assert_eq!(
+            dmux.format_checksum,
+            "34ed,230x56,0,0{132x56,0,0,3,97x56,133,0,222}"
+        );
+        assert_eq!(dmux.commands, vec!["nvim src/main.rs"]);
+        assert_eq!(dmux.number_of_panes, 2);
+
+        let site = &restore.workspaces[1];
+        assert_eq!(site.path, PathBuf::from("/home/zt/dev/my site"));
+        assert_eq!(site.commands, vec!["npm run dev"]);
+
+        assert_eq!(restore.skipped.len(), 2);
+        assert!(restore.skipped[0].starts_with("line \"state"));
+        assert!(restore.skipped[1].starts_with("line \"grouped_session"));
+    }
+
+    #[test]
+    fn parse_keeps_the_directories_of_panes_that_differ() {
+        let save = "pane\tmono\t0\t1\t:*\t0\t:/src/mono\t1\tfish\t:\n\
+                    pane\tmono\t0\t1\t:*\t1\t:/src/mono/api\t0\tfish\t:\n\
+                    pane\tmono\t0\t1\t:*\t2\t:/src/mono\t0\tfish\t:\n\
+                    window\tmono\t0\t:dev\t1\t:*\ttiled\t:\n";
+        let restore = parse(save);
+        assert!(restore.skipped.is_empty());
+        let workspace = &restore.workspaces[0];
+        assert_eq!(workspace.path, PathBuf::from("/src/mono"));
+        assert_eq!(
+            workspace.panes,
+            [
+                PaneSpec::default(),
+                PaneSpec {
+                    dir: Some(PathBuf::from("/src/mono/api")),
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,281 @@
+// Screen::open
+// screenrc
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::Result;
+
+use crate::multiplexer::Multiplexer;
+use crate::tmux::{command_lines, SplitDirection, SplitTree, WorkSpace};
+
+/// Opens workspaces in GNU screen, for machines that only have screen. The
+/// session is started from a screenrc dmux writes, with a screen window for
+/// each pane, split into regions when attached. screen splits regions evenly
+/// and only keeps them while attached, so sizes are left out, and a detached
+/// workspace only gets its windows.
+#[derive(Debug, Clone)]
+pub struct Screen {
+    pub(crate) bin: String,
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Screen {
+            bin: "screen".to_owned(),
+        }
+    }
+}
+
+impl Screen {
+    pub fn new() -> Screen {
+        Screen::default()
+    }
+}
+
+impl Multiplexer for Screen {
+    fn name(&self) -> &'static str {
+        "screen"
+    }
+
+    // `screen -v` fails even though it prints the version, so only
+    // starting it counts
+    fn is_installed(&self) -> bool {
+        Command::new(&self.bin)
+            .arg("-v")
+            .stdin(Stdio::null())
+            .output()
+            .is_ok()
+    }
+
+    fn session_names(&self) -> Result<Vec<String>> {
+        // `screen -ls` fails when there are no sessions
+        let output = Command::new(&self.bin)
+            .arg("-ls")
+            .stdin(Stdio::null())
+            .output()?;
+        Ok(session_names(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    fn open(&self, workspace: WorkSpace, ready: &mut dyn FnMut() -> Result<()>) -> Result<String> {
+        let workspace = workspace.normalized()?;
+        let name = workspace.session_name.clone();
+        let open = self.session_names()?.contains(&name);
+        let mut screen = Command::new(&self.bin);
+        if open {
+            if workspace.detached {
+                ready()?;
+                return Ok(name);
+            }
+            // -x shares the session with wherever else it's attached
+            screen.args(["-x", &name]);
+        } else {
+            let user_rc = dirs::home_dir()
+                .map(|home| home.join(".screenrc"))
+                .filter(|rc| rc.is_file());
+            let rc = std::env::temp_dir().join(format!("dmux-{}.screenrc", name));
+            std::fs::write(&rc, screenrc(&workspace, user_rc.as_deref())?)?;
+            if workspace.detached {
+                screen.arg("-dm");
+            }
+            screen.args(["-S", &name, "-c"]).arg(&rc);
+        }
+
+        debug!("running {:?}", screen);
+        let status = if workspace.detached {
+            let status = screen.stdin(Stdio::null()).output()?.status;
+            if status.success() {
+                ready()?;
+            }
+            status
+        } else if open {
+            ready()?;
+            screen.status()?
+        } else {
+            // a new session is started and attached to in one go, so
+            // `ready` runs while it starts
+            let mut attached = screen.spawn()?;
+            let ready = ready();
+            let status = attached.wait()?;
+            ready?;
+            status
+        };
+        if !status.success() {
+            return Err(anyhow!("Couldn't open screen session {}", name));
+        }
+        Ok(name)
+    }
+}
+
+// The names in `screen -ls` output, where each session is listed like
+// `	1234.name	(Detached)`.
+fn session_names(ls: &str) -> Vec<String> {
+    ls.lines()
+        .filter(|line| line.starts_with('\t'))
+        .filter_map(|line| line.split_whitespace().next()?.split_once('.'))
+        .map(|(_, name)| name.to_owned())
+        .collect()
+}
+
+/// The screenrc that starts the workspace: the user's own, then a window for
+/// each pane in the region the splits give it, then a window for each pane
+/// of the workspace's other windows.
+pub(crate) fn screenrc(workspace: &WorkSpace, user_rc: Option<&Path>) -> Result<String> {
+    let mut rc = vec![];
+    if let Some(user_rc) = user_rc {
+        rc.push(format!("source {}", quote(&user_rc.display().to_string())));
+    }
+
+    let tree = workspace
+        .splits
+        .clone()
+        .unwrap_or_else(|| SplitTree::main_vertical(workspace.number_of_panes().into()));
+    pane_window(workspace, 0, &mut rc)?;
+    // the panes made so far in the order of their regions, which `focus`
+    // cycles through, and the one focused
+    let mut regions = vec![0];
+    let mut focused = 0;
+    for step in tree.plan() {
+        let at = regions
+            .iter()
+            .position(|pane| *pane == step.target)
+            .expect("split panes are made first");
+        for _ in 0..(at + regions.len() - focused) % regions.len() {
+            rc.push("focus next".to_owned());
+        }
+        rc.push(
+            match step.direction {
+                SplitDirection::Horizontal => "split -v",
+                SplitDirection::Vertical => "split",
+            }
+            .to_owned(),
+        );
+        // the new region comes right after the split one
+        rc.push("focus next".to_owned());
+        regions.insert(at + 1, step.pane);
+        focused = at + 1;
+        pane_window(workspace, step.pane, &mut rc)?;
+    }
+    if regions.len() > 1 {
+        rc.push("focus top".to_owned());
+    }
+
+    for window in workspace.window_workspaces()? {
+        for nth in 0..window.number_of_panes() {
+            pane_window(&window, nth.into(), &mut rc)?;
+        }
+    }
+    if !workspace.windows.is_empty() {
+        rc.push("select 0".to_owned());
+    }
+    rc.push(String::new());
+    Ok(rc.join("\n"))
+}
+
+// Opens the `nth` pane as a screen window in the focused region, with its
+// variables set only while it starts, and stuffs its command into it
+fn pane_window(workspace: &WorkSpace, nth: usize, rc: &mut Vec<String>) -> Result<()> {
+    let env: Vec<(String, String)> = workspace
+        .pane_env(nth as u8)
+        .iter()
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect();
+
+    rc.push(format!("chdir {}", quote(&workspace.pane_dir(nth as u8))));
+    for (name, value) in &env {
+        rc.push(format!("setenv {} {}", name, quote(value)));
+    }
+    rc.push(format!("screen -t {}", quote(&workspace.window_name())));
+    for (name, _) in &env {
+        rc.push(format!("unsetenv {}", name));
+    }
+    if let Some(command) = workspace.commands.get(nth) {
+        for line in command_lines(command)? {
+            if !line.is_empty() {
+                rc.push(format!("stuff {}", quote(&format!("{}\r", line))));
+            }
+        }
+    }
+    Ok(())
+}
+
+// A screenrc string. screen expands `$VARS` and `^X` in them, so those are
+// escaped along with quotes, and a carriage return is written in octal.
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '\\' | '"' | '$' | '^' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\r' => quoted.push_str("\\015"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tmux::PaneSpec;
+    use std::path::PathBuf;
+
+    #[test]
+    fn sessions_are_read_from_the_listing() {
+        let ls = "There are screens on:\n\
+                  \t4242.site\t(Detached)\n\
+                  \t17.my.notes\t(01/02/24 10:00:00)\t(Attached)\n\
+                  2 Sockets in /run/screen/S-me.\n";
+        assert_eq!(session_names(ls), ["site", "my.notes"]);
+    }
+
+    #[test]
+    fn panes_become_windows_in_split_regions() {
+        let workspace = WorkSpace {
+            path: PathBuf::from("/src/site"),
+            session_name: "site".to_owned(),
+            window_name: Some("dev".to_owned()),
+            commands: vec![
+                "nvim".to_owned(),
+                String::new(),
+                "echo \"$PORT\"".to_owned(),
+            ],
+            number_of_panes: 3,
+            panes: vec![
+                PaneSpec::default(),
+                PaneSpec::default(),
+                PaneSpec {
+                    env: vec![("PORT".to_owned(), "3000".to_owned())],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let rc = screenrc(&workspace, Some(Path::new("/home/me/.screenrc"))).unwrap();
+        assert_eq!(
+            rc.lines().collect::<Vec<_>>(),
+            [
+                r#"source "/home/me/.screenrc""#,
+                r#"chdir "/src/site""#,
+                r#"screen -t "dev""#,
+                r#"stuff "nvim\015""#,
+                "split -v",
+                "focus next",
+                r#"chdir "/src/site""#,
+                r#"screen -t "dev""#,
+                "split",
+                "focus next",
+                r#"chdir "/src/site""#,
+                r#"setenv PORT "3000""#,
+                r#"screen -t "dev""#,
+                "unsetenv PORT",
+                r#"stuff "echo \"\$PORT\"\015""#,
+                "focus top",
+            ]
+        );
+    }
+}
@@ -2,11 +2,12 @@ use anyhow::Result;
 use std::io::Write;
 use std::path::PathBuf;
 use std::process::{Command, Output, Stdio};
+use std::str::FromStr;
 use walkdir::{DirEntry, WalkDir};
 
 fn is_git_dir(entry: &DirEntry) -> bool {
     if let Some(file_name) = entry.file_name().to_str() {
-        file_name.contains(&"git")
+        file_name.contains("git")
     } else {
         false
     }
@@ -18,35 +19,137 @@ fn all_dirs_in_path(search_dir: &PathBuf) -> String {
         .max_depth(4)
         .into_iter()
         .filter_entry(|e| e.file_type().is_dir() && !is_git_dir(e))
+        .flatten()
     {
-        if let Ok(path) = entry {
-            path_input.push_str("\n");
-            path_input.push_str(path.path().to_str().unwrap());
-        }
+        path_input.push('\n');
+        path_input.push_str(entry.path().to_str().unwrap());
     }
     path_input
 }
 
-pub struct Selector {
-    search_dir: PathBuf,
-    use_fd: bool,
+/// What picks from a list: dmux's own fuzzy finder, or a command like
+/// `fzf-tmux` that's given the list on stdin and prints the one picked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Finder {
+    Builtin,
+    Command(String),
 }
 
-fn output_to_pathbuf(output: Output) -> Option<PathBuf> {
-    if output.status.success() {
-        let mut stdout = output.stdout;
-        // removes trailing newline, probably a better way to do this
-        stdout.pop();
-        let path: PathBuf = String::from_utf8(stdout).unwrap().parse().unwrap();
-        Some(path)
-    } else {
-        // Err(anyhow!("Couldn't parse path from {:?}", output.stdout))
-        None
+impl From<&str> for Finder {
+    fn from(finder: &str) -> Finder {
+        match finder {
+            "builtin" => Finder::Builtin,
+            command => Finder::Command(command.to_owned()),
+        }
     }
 }
 
+impl Finder {
+    // The finder's command run with `sh`, with the list piped in
+    fn spawn(command: &str, stdin: Stdio) -> Result<std::process::Child> {
+        Ok(Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .spawn()?)
+    }
+
+    // What the finder printed, or nothing when it was cancelled, telling a
+    // finder that isn't installed apart from one that was cancelled
+    fn picked(command: &str, output: Output) -> Result<Option<String>> {
+        if output.status.code() == Some(127) {
+            return Err(anyhow!("Couldn't run the finder {:?}", command));
+        }
+        if !output.status.success() {
+            return Ok(None);
+        }
+        Ok(Some(
+            String::from_utf8(output.stdout)?.trim_end().to_owned(),
+        ))
+    }
+
+    /// Picks one of `items`, or nothing when it's cancelled.
+    pub fn pick(&self, items: &[String]) -> Result<Option<String>> {
+        let command = match self {
+            Finder::Builtin => return crate::finder::pick(items),
+            Finder::Command(command) => command,
+        };
+        let mut finder = Finder::spawn(command, Stdio::piped())?;
+        finder
+            .stdin
+            .as_mut()
+            .ok_or_else(|| anyhow!("{} couldn't take stdin", command))?
+            .write_all(items.join("\n").as_bytes())?;
+        Finder::picked(command, finder.wait_with_output()?)
+    }
+}
+
+/// Where the directories to pick from come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirSource {
+    /// Every directory under the search dir.
+    Search,
+    /// The directories zoxide knows, most frecent first.
+    Zoxide,
+    /// zoxide's directories, followed by the rest of the search dir's.
+    Both,
+}
+
+impl FromStr for DirSource {
+    type Err = anyhow::Error;
+
+    fn from_str(source: &str) -> Result<DirSource> {
+        match source {
+            "search" => Ok(DirSource::Search),
+            "zoxide" => Ok(DirSource::Zoxide),
+            "both" => Ok(DirSource::Both),
+            _ => Err(anyhow!(
+                "Unknown dir source {:?}, expected search, zoxide or both",
+                source
+            )),
+        }
+    }
+}
+
+// `zoxide query --list`, which ranks the directories by frecency
+fn zoxide_dirs() -> Result<String> {
+    let output = Command::new("zoxide")
+        .args(["query", "--list"])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|_| anyhow!("zoxide is not installed."))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Couldn't list zoxide's directories: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+// `first`'s directories and then those of `rest` that aren't in it, a line
+// each. fd ends directories with a slash, so they're compared without one.
+fn merged_dirs(first: &str, rest: &str) -> String {
+    let mut seen = std::collections::HashSet::new();
+    first
+        .lines()
+        .chain(rest.lines())
+        .filter(|dir| !dir.is_empty())
+        .filter(|dir| seen.insert(dir.trim_end_matches('/').to_owned()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub struct Selector {
+    search_dir: PathBuf,
+    use_fd: bool,
+    source: DirSource,
+    finder: Finder,
+}
+
 impl Selector {
-    pub fn new(search_dir: &PathBuf) -> Selector {
+    pub fn new(search_dir: &PathBuf, source: DirSource, finder: Finder) -> Selector {
         let use_fd = Command::new("fd")
             .arg("--version")
             .stdout(Stdio::null())
@@ -55,10 +158,13 @@ impl Selector {
         Selector {
             search_dir: search_dir.to_owned(),
             use_fd,
+            source,
+            finder,
         }
     }
 
-    fn select_with_fd(&self) -> Result<Option<PathBuf>> {
+    // Streams fd's dirs into the finder's command as they're found
+    fn select_with_fd(&self, command: &str) -> Result<Option<PathBuf>> {
         let mut fd = Command::new("fd")
             .arg("-td")
             .arg(".")
@@ -74,39 +180,62 @@ impl Selector {
             .stdout
             .take()
             .ok_or_else(|| anyhow!("FD command's stdout could not be read"))?;
-        let fzf = Command::new("fzf-tmux")
-            .stdin(pipe)
-            .stdout(Stdio::piped())
-            .spawn()?;
-        let output = fzf.wait_with_output()?;
+        let output = Finder::spawn(command, Stdio::from(pipe))?.wait_with_output()?;
         fd.kill()?;
-        Ok(output_to_pathbuf(output))
+        Ok(Finder::picked(command, output)?.map(PathBuf::from))
     }
 
-    fn select_with_walk_dir(&self) -> Result<Option<PathBuf>> {
-        let files = all_dirs_in_path(&self.search_dir);
-        let mut fzf = Command::new("fzf-tmux")
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn()?;
-
-        // this should be converted to an async stream so that
-        // selection doesn't have to wait for dir traversal
-        fzf.stdin
-            .as_mut()
-            .ok_or_else(|| anyhow!("fzf couldn't take stdin"))?
-            .write_all(files.as_bytes())?;
-
-        let output = fzf.wait_with_output()?;
+    // Everything fd or the walk finds under the search dir, all at once
+    fn search_dirs(&self) -> Result<String> {
+        if !self.use_fd {
+            return Ok(all_dirs_in_path(&self.search_dir));
+        }
+        let output = Command::new("fd")
+            .arg("-td")
+            .arg(".")
+            .arg(&self.search_dir)
+            .stdin(Stdio::null())
+            .output()?;
+        Ok(String::from_utf8(output.stdout)?)
+    }
 
-        Ok(output_to_pathbuf(output))
+    // this should be converted to an async stream so that
+    // selection doesn't have to wait for dir traversal
+    fn select_from(&self, files: &str) -> Result<Option<PathBuf>> {
+        let dirs: Vec<String> = files
+            .lines()
+            .filter(|dir| !dir.is_empty())
+            .map(str::to_owned)
+            .collect();
+        Ok(self.finder.pick(&dirs)?.map(PathBuf::from))
     }
 
     pub fn select_dir(&self) -> Result<Option<PathBuf>> {
-        if self.use_fd {
-            self.select_with_fd()
-        } else {
-            self.select_with_walk_dir()
+        match self.source {
+            DirSource::Zoxide => self.select_from(&zoxide_dirs()?),
+            DirSource::Both => {
+                self.select_from(&merged_dirs(&zoxide_dirs()?, &self.search_dirs()?))
+            }
+            DirSource::Search => match &self.finder {
+                Finder::Command(command) if self.use_fd => self.select_with_fd(command),
+                _ => self.select_from(&self.search_dirs()?),
+            },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zoxide_dirs_come_first_without_repeats() {
+        let zoxide = "/src/site\n/home/me/notes\n";
+        let search = "/src/\n/src/site/\n/src/api/\n";
+        assert_eq!(
+            merged_dirs(zoxide, search),
+            "/src/site\n/home/me/notes\n/src/\n/src/api/"
+        );
+        assert!("fzf".parse::<DirSource>().is_err());
+    }
+}
@@ -0,0 +1,216 @@
+// snapshot
+// to_config
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::tmux::{PaneSpec, Session, Window, WindowSpec, WorkSpace};
+
+/// The workspace that opens `session` again the way it's laid out now: its
+/// first window becomes the workspace's own and the rest its `windows`, each
+/// with its layout checksum. Panes keep their directories and the program
+/// running in them, by name alone since tmux doesn't report arguments, and
+/// panes sitting at a prompt get no command.
+pub fn snapshot(session: &Session) -> Result<WorkSpace> {
+    let mut windows = session.iter_windows();
+    let first = windows
+        .next()
+        .ok_or_else(|| anyhow!("Session {} has no windows", session.name))?;
+    let path = pane_paths(first)
+        .into_iter()
+        .flatten()
+        .next()
+        .ok_or_else(|| anyhow!("Couldn't read the directories of {}", session.name))?;
+
+    let mut panes: Vec<PaneSpec> = pane_paths(first)
+        .into_iter()
+        .map(|dir| PaneSpec {
+            dir: dir
+                .filter(|dir| *dir != path)
+                .map(|dir| relative(&dir, &path)),
+            ..Default::default()
+        })
+        .collect();
+    while panes.last() == Some(&PaneSpec::default()) {
+        panes.pop();
+    }
+
+    Ok(WorkSpace {
+        session_name: session.name.clone(),
+        window_name: Some(first.name.clone()),
+        format_checksum: first.layout().unwrap_or("tiled").to_owned(),
+        number_of_panes: pane_count(first),
+        commands: commands(first),
+        panes,
+        windows: windows
+            .map(|window| WindowSpec {
+                name: window.name.clone(),
+                dir: pane_paths(window)
+                    .into_iter()
+                    .flatten()
+                    .next()
+                    .filter(|dir| *dir != path)
+                    .map(|dir| relative(&dir, &path)),
+                layout: window.layout().map(str::to_owned),
+                commands: commands(window),
+                number_of_panes: pane_count(window),
+            })
+            .collect(),
+        path,
+        ..Default::default()
+    })
+}
+
+/// The workspace as a profile named `profile` for the config file, in
+/// TOML. Only what `snapshot` fills in is written.
+pub fn to_config(profile: &str, workspace: &WorkSpace) -> String {
+    let profile = key(profile);
+    let mut config = vec![
+        format!("# dmux --profile {} {}", profile, workspace.path.display()),
+        format!("[{}]", profile),
+        format!("session_name = {}", string(&workspace.session_name)),
+    ];
+    if let Some(name) = &workspace.window_name {
+        config.push(format!("window_name = {}", string(name)));
+    }
+    config.push(format!("layout = {}", string(&workspace.format_checksum)));
+    config.push(format!("number_of_panes = {}", workspace.number_of_panes));
+    config.push(format!("commands = {}", strings(&workspace.commands)));
+    if !workspace.panes.is_empty() {
+        let panes: Vec<String> = workspace
+            .panes
+            .iter()
+            .map(|pane| match &pane.dir {
+                Some(dir) => format!("{{ dir = {} }}", string(&dir.to_string_lossy())),
+                None => "{}".to_owned(),
+            })
+            .collect();
+        config.push(format!("panes = [{}]", panes.join(", ")));
+    }
+
+    for window in &workspace.windows {
+        config.push(String::new());
+        config.push(format!("[[{}.windows]]", profile));
+        config.push(format!("name = {}", string(&window.name)));
+        if let Some(dir) = &window.dir {
+            config.push(format!("dir = {}", string(&dir.to_string_lossy())));
+        }
+        if let Some(layout) = &window.layout {
+            config.push(format!("layout = {}", string(layout)));
+        }
+        config.push(format!("number_of_panes = {}", window.number_of_panes));
+        config.push(format!("commands = {}", strings(&window.commands)));
+    }
+    config.push(String::new());
+    config.join("\n")
+}
+
+fn pane_paths(window: &Window) -> Vec<Option<PathBuf>> {
+    window
+        .iter_panes()
+        .map(|pane| pane.current_path().map(Path::to_owned))
+        .collect()
+}
+
+fn pane_count(window: &Window) -> u8 {
+    window.iter_panes().count().try_into().unwrap_or(u8::MAX)
+}
+
+// The program running in each pane, up to the last pane running one
+fn commands(window: &Window) -> Vec<String> {
+    let mut commands: Vec<String> = window
+        .iter_panes()
+        .map(|pane| match pane.current_command() {
+            Some(command) if !pane.is_idle() => command.to_owned(),
+            _ => String::new(),
+        })
+        .collect();
+    while commands.last().is_some_and(String::is_empty) {
+        commands.pop();
+    }
+    commands
+}
+
+// `dir` relative to the workspace's directory when it's inside it, since
+// pane directories are joined onto it
+fn relative(dir: &Path, path: &Path) -> PathBuf {
+    dir.strip_prefix(path).unwrap_or(dir).to_owned()
+}
+
+// A TOML key, quoted unless it's a bare one
+fn key(key: &str) -> String {
+    let bare = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+    if !key.is_empty() && key.chars().all(bare) {
+        key.to_owned()
+    } else {
+        string(key)
+    }
+}
+
+// A TOML basic string
+fn string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn strings(texts: &[String]) -> String {
+    let texts: Vec<String> = texts.iter().map(|text| string(text)).collect();
+    format!("[{}]", texts.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tmux::{sessions_from, windows_with_panes};
+
+    #[test]
+    fn sessions_are_written_back_as_profiles() {
+        let windows = windows_with_panes(
+            "site|:|0|:|@1|:|80|:|24|:|5e09,80x24,0,0{40x24,0,0,1,39x24,41,0,2}|:|dev\n\
+             site|:|1|:|@2|:|80|:|24|:|b25d,80x24,0,0,3|:|logs\n",
+            "@1|:|0|:|%1|:|0|:|0|:|40|:|24|:|10|:|nvim|:||:|/src/site\n\
+             @1|:|1|:|%2|:|41|:|0|:|39|:|24|:|11|:|-zsh|:||:|/src/site/api\n\
+             @2|:|0|:|%3|:|0|:|0|:|80|:|24|:|12|:|tail|:|logs|:|/var/log\n",
+        )
+        .unwrap();
+        let session = &sessions_from("1|:|site\n", windows)[0];
+        let workspace = snapshot(session).unwrap();
+        assert_eq!(workspace.path, PathBuf::from("/src/site"));
+        assert_eq!(workspace.commands, ["nvim"]);
+
+        assert_eq!(
+            to_config("my site", &workspace),
+            r#"# dmux --profile "my site" /src/site
+["my site"]
+session_name = "site"
+window_name = "dev"
+layout = "5e09,80x24,0,0{40x24,0,0,1,39x24,41,0,2}"
+number_of_panes = 2
+commands = ["nvim"]
+panes = [{}, { dir = "api" }]
+
+[["my site".windows]]
+name = "logs"
+dir = "/var/log"
+layout = "b25d,80x24,0,0,3"
+number_of_panes = 1
+commands = ["tail"]
+"#
+        );
+    }
+}
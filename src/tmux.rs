@@ -1,231 +1,6868 @@
-// setup_workspace
-// generate_layout
+// Tmux::setup_workspace
+// Tmux::generate_layout
 // in_tmux
 // has_tmux
 
+use std::borrow::Cow;
 use std::cmp::max;
-use std::path::PathBuf;
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 
+use crate::control::{command_line, exit_status, ControlClient, ControlError};
 use anyhow::Result;
-use tmux_interface::{TargetSession, TmuxCommand, Windows};
+use colored::*;
+use tmux_interface::{PaneSize, RespawnPane, RespawnWindow, TmuxCommand, TmuxOutput};
 
 pub fn has_tmux() -> bool {
-    std::process::Command::new("tmux")
-        .arg("-V")
-        .output()
-        .unwrap()
-        .status
-        .success()
+    Tmux::new().has_tmux()
 }
 
 pub fn in_tmux() -> bool {
     std::env::var("TMUX").is_ok()
 }
 
-pub fn setup_workspace(workspace: WorkSpace) {
-    let tmux = TmuxCommand::new();
-    let session_with_right_name_exists = tmux
-        .has_session()
-        .target_session(&workspace.session_name)
-        .output()
-        .unwrap()
-        .success();
+#[derive(Debug)]
+pub enum TmuxError {
+    /// tmux didn't finish within `TmuxConfig::timeout` and was killed.
+    Timeout { command: String, after: Duration },
+    /// A layout that's neither a preset nor a well formed checksum.
+    LayoutInvalid { layout: String, reason: String },
+    /// A new session still wasn't listed with a window after
+    /// `TmuxConfig::timeout`.
+    SessionNotReady { session: String, after: Duration },
+}
 
-    if session_with_right_name_exists {
-        let target_session = TargetSession::Raw(&workspace.session_name);
-        let window_with_right_name_exists =
-            Windows::get(&target_session, tmux_interface::WINDOW_ALL)
-                .unwrap()
-                .into_iter()
-                .any(|w| w.name.as_ref().unwrap() == &workspace.window_name());
+impl fmt::Display for TmuxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TmuxError::Timeout { command, after } => {
+                write!(f, "`{}` timed out after {:?}", command, after)
+            }
+            TmuxError::LayoutInvalid { layout, reason } => {
+                write!(f, "invalid layout {:?}: {}", layout, reason)
+            }
+            TmuxError::SessionNotReady { session, after } => {
+                write!(f, "session {} wasn't ready after {:?}", session, after)
+            }
+        }
+    }
+}
 
-        if window_with_right_name_exists {
-            attach_to_window(&workspace, &tmux);
-        } else {
-            // create window
-            tmux.new_window()
-                .window_name(workspace.window_name())
-                .start_directory(workspace.path_str())
-                // first command goes in defaut pane
-                .detached()
-                .output()
-                .unwrap();
+impl std::error::Error for TmuxError {}
 
-            // one already exists from when the window was created
-            setup_panes_with_commands(&workspace, &tmux);
+#[derive(Debug)]
+pub struct TmuxConfig {
+    /// How long a single tmux invocation may take before it's killed. A hung
+    /// server would otherwise block dmux forever.
+    pub timeout: Duration,
+    /// Passed to every invocation as `tmux -f`, so server options like
+    /// `base-index` don't depend on the user's `~/.tmux.conf`. It's only read
+    /// when the server starts.
+    pub config_file: Option<PathBuf>,
+    /// The server every invocation talks to, when it's not the default one,
+    /// so workspaces can live on a dedicated or sandboxed server.
+    pub socket: Option<Socket>,
+    /// Runs commands over a single `tmux -C` client that stays attached,
+    /// rather than starting tmux for each one, which adds up when opening
+    /// many panes. The client is started on first use and again if it drops.
+    /// Until there's a session to attach to, commands are run as usual.
+    /// Only `Tmux` uses it, `AsyncTmux` always starts tmux.
+    pub control_mode: bool,
+    /// Prints the commands that would change anything on the server instead
+    /// of running them, while the ones that only look at it still run, so
+    /// setting a workspace up shows what it would do. Only `Tmux` uses it.
+    pub dry_run: bool,
+    /// Runs tmux on this host through `ssh` instead of here, each command
+    /// quoted for the shell there, and attaches with `ssh -t`. dmux is never
+    /// in a client of that server, so it always attaches rather than
+    /// switching. Control mode isn't used with it, and only `Tmux` uses it.
+    pub host: Option<String>,
+    /// How listing and creating sessions retry while the server can't be
+    /// reached, which happens for a moment right after a kill or while it
+    /// starts up. Errors like a bad target are never retried.
+    pub retry: RetryPolicy,
+}
+
+impl Default for TmuxConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            config_file: None,
+            socket: None,
+            control_mode: false,
+            dry_run: false,
+            host: None,
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+/// A tmux server other than the default one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Socket {
+    /// A socket in tmux's own socket directory, like `tmux -L`.
+    Name(String),
+    /// A socket anywhere, like `tmux -S`.
+    Path(PathBuf),
+}
+
+/// How many times a tmux call is made while the server is unreachable, and
+/// how long to wait in between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Calls made in all, the first one included, so 1 never retries.
+    pub attempts: u32,
+    /// The wait before the first retry, doubled for each one after it.
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub(crate) fn wait(&self, retry: u32) -> Duration {
+        self.backoff.saturating_mul(2u32.saturating_pow(retry))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: Duration::from_millis(10),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Tmux {
+    pub(crate) command: TmuxCommand<'static>,
+    pub(crate) config: TmuxConfig,
+    // with `TmuxConfig::control_mode`, the connection once one is made
+    control: Mutex<Option<ControlClient>>,
+    // the server's index bases once they've been read
+    pub(crate) index_bases: Mutex<Option<IndexBases>>,
+}
+
+impl Tmux {
+    pub fn new() -> Tmux {
+        Tmux::with_config(TmuxConfig::default())
+    }
+
+    pub fn with_config(config: TmuxConfig) -> Tmux {
+        Tmux {
+            command: TmuxCommand::new(),
+            config,
+            control: Mutex::new(None),
+            index_bases: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn process(&self, command: &TmuxCommand) -> Command {
+        // builders don't carry over the arguments that go before the tmux
+        // command, so they're added here
+        let mut command = command.clone();
+        if let Some(socket) = &self.config.socket {
+            let (flag, socket) = match socket {
+                Socket::Name(name) => ("-L", name.clone()),
+                Socket::Path(path) => ("-S", path.to_string_lossy().into_owned()),
+            };
+            command
+                .bin_args
+                .get_or_insert_with(Vec::new)
+                .extend([flag.into(), socket.into()]);
+        }
+        if let Some(config_file) = &self.config.config_file {
+            command.bin_args.get_or_insert_with(Vec::new).extend([
+                "-f".into(),
+                config_file.to_string_lossy().into_owned().into(),
+            ]);
+        }
+        let mut process = Command::from(&command);
+        if let Some(host) = &self.config.host {
+            let attaching = matches!(command.cmd.as_deref(), Some("attach" | "attach-session"));
+            process = over_ssh(host, &process, attaching);
+        }
+        // tmux fails with `not a terminal` when it can't get at the tty
+        process.stdin(Stdio::inherit());
+        process
+    }
+
+    /// Runs a tmux command, killing it if it outlives the configured timeout.
+    fn run(&self, command: &TmuxCommand) -> Result<TmuxOutput> {
+        if let Some(output) = self.dry_run(command) {
+            return Ok(output);
+        }
+        // commands with arguments before the tmux command, like `-V`, or
+        // some other binary can't go over a control connection
+        if self.config.control_mode
+            && self.config.host.is_none()
+            && command.bin == "tmux"
+            && command.bin_args.is_none()
+        {
+            if let Some(output) = self.run_controlled(command)? {
+                return Ok(output);
+            }
+        }
+        self.spawn(command)
+    }
+
+    // Runs a command that's safe to make again, retrying it while the
+    // server can't be reached.
+    fn run_retrying(&self, command: &TmuxCommand) -> Result<TmuxOutput> {
+        let retry = self.config.retry;
+        let mut retries = 0;
+        loop {
+            let output = self.run(command)?;
+            if retries + 1 >= retry.attempts || !transient(&output) {
+                return Ok(output);
+            }
+            debug!(
+                "tmux server unreachable, retrying in {:?}",
+                retry.wait(retries)
+            );
+            thread::sleep(retry.wait(retries));
+            retries += 1;
+        }
+    }
 
-            attach_to_window(&workspace, &tmux);
+    // Runs the command over the control connection, connecting first if
+    // there isn't one. A dropped connection is made again once for a command
+    // tmux never saw. Nothing is returned when there's no server to connect
+    // to, so the command is spawned as usual.
+    fn run_controlled(&self, command: &TmuxCommand) -> Result<Option<TmuxOutput>> {
+        let mut control = self.control.lock().unwrap_or_else(|e| e.into_inner());
+        for _ in 0..2 {
+            if control.is_none() {
+                let mut attach = self.command.attach_session().0;
+                attach.bin_args = Some(vec!["-C".into()]);
+                match ControlClient::connect(self.process(&attach), self.config.timeout) {
+                    Ok(client) => *control = Some(client),
+                    Err(_) => return Ok(None),
+                }
+            }
+            let client = control.as_mut().expect("connected above");
+            debug!(
+                "running {} over the control connection",
+                command_line(command)
+            );
+            match client.run(command, self.config.timeout) {
+                Ok(output) => return Ok(Some(output)),
+                Err(ControlError::Disconnected { sent: false }) => *control = None,
+                Err(ControlError::Disconnected { sent: true }) => {
+                    *control = None;
+                    return Err(anyhow!(
+                        "The tmux control client went away while running {}",
+                        command_line(command)
+                    ));
+                }
+                Err(ControlError::TimedOut) => {
+                    *control = None;
+                    return Err(TmuxError::Timeout {
+                        command: command_line(command),
+                        after: self.config.timeout,
+                    }
+                    .into());
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // With `TmuxConfig::dry_run`, prints a command that changes something
+    // and stands in for its output, which is empty and successful.
+    pub(crate) fn dry_run(&self, command: &TmuxCommand) -> Option<TmuxOutput> {
+        if !self.config.dry_run || read_only(command) {
+            return None;
+        }
+        println!("{}", shell_line(&self.process(command)));
+        Some(TmuxOutput(Output {
+            status: exit_status(true),
+            stdout: vec![],
+            stderr: vec![],
+        }))
+    }
+
+    // Runs the command in a tmux process of its own.
+    pub(crate) fn spawn(&self, command: &TmuxCommand) -> Result<TmuxOutput> {
+        if let Some(output) = self.dry_run(command) {
+            return Ok(output);
+        }
+        let mut process = self.process(command);
+        debug!("running {:?}", process);
+        let mut child = process
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // drain the pipes on their own threads so a chatty command can't
+        // fill them up and stall before it exits
+        let mut stdout = child.stdout.take();
+        let mut stderr = child.stderr.take();
+        let stdout = thread::spawn(move || read_all(stdout.as_mut()));
+        let stderr = thread::spawn(move || read_all(stderr.as_mut()));
+
+        let deadline = Instant::now() + self.config.timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                warn!("killing {:?} after {:?}", process, self.config.timeout);
+                child.kill()?;
+                child.wait()?;
+                return Err(TmuxError::Timeout {
+                    command: format!("{:?}", self.process(command)),
+                    after: self.config.timeout,
+                }
+                .into());
+            }
+            thread::sleep(Duration::from_millis(2));
         };
-    } else {
-        // No existing tmux session
 
-        // Create a new session
-        tmux.new_session()
-            .session_name(&workspace.session_name)
-            .start_directory(&workspace.path_str())
-            .detached()
-            .window_name(workspace.window_name())
-            .output()
-            .unwrap();
+        Ok(TmuxOutput(Output {
+            status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        }))
+    }
 
-        setup_panes_with_commands(&workspace, &tmux);
+    /// The visible contents of a pane, with wrapped lines joined back up.
+    pub fn capture_pane(&self, window: &Window, pane: &Pane) -> Result<String> {
+        let output = self.run(
+            &self
+                .command
+                .capture_pane()
+                .stdout()
+                .join()
+                .target_pane(pane.target(window))
+                .0,
+        )?;
+        Ok(checked(
+            output,
+            format!("Couldn't capture pane {} of {}", pane.index, window.name),
+        )?
+        .to_string())
+    }
 
-        attach_to_window(&workspace, &tmux);
-    };
+    /// Checks that each command, sent to the pane at its index, shows up in
+    /// that pane, waiting up to `wait` for slow shells to echo them. Returns
+    /// the pane index and command of the ones that never did.
+    pub fn verify_commands(
+        &self,
+        window: &Window,
+        commands: &[String],
+        wait: Duration,
+    ) -> Result<Vec<(u8, String)>> {
+        let panes = self.panes(&window.target()).unwrap_or_default();
+        let base = self.index_bases(&window.target()).pane;
+        let sent = commands
+            .iter()
+            .enumerate()
+            .map(|(i, command)| (pane_at(&panes, i, base), command))
+            .collect();
+        self.verify_sent(window, sent, wait)
+    }
+
+    /// Where the server counts windows and panes from for `target`, going
+    /// by its `base-index` and `pane-base-index` options. They're read once
+    /// and kept, and taken to be 0 while they can't be read, like before
+    /// there's a server.
+    pub fn index_bases(&self, target: &str) -> IndexBases {
+        let mut known = self.index_bases.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(bases) = *known {
+            return bases;
+        }
+        let read = self
+            .run(&index_bases_command(target, self))
+            .ok()
+            .and_then(first_line)
+            .and_then(|line| IndexBases::from_interface(&line));
+        debug!("index bases for {}: {:?}", target, read);
+        *known = read;
+        read.unwrap_or_default()
+    }
+
+    // Same as `verify_commands`, with each command already paired up with
+    // the pane it was sent to.
+    pub(crate) fn verify_sent(
+        &self,
+        window: &Window,
+        mut pending: Vec<(Pane, &String)>,
+        wait: Duration,
+    ) -> Result<Vec<(u8, String)>> {
+        let deadline = Instant::now() + wait;
+        loop {
+            let mut still_pending = vec![];
+            for (pane, command) in pending {
+                if !command_landed(&self.capture_pane(window, &pane)?, command) {
+                    still_pending.push((pane, command));
+                }
+            }
+            pending = still_pending;
+            if pending.is_empty() || Instant::now() >= deadline {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        Ok(pending
+            .into_iter()
+            .map(|(pane, command)| (pane.index, command.clone()))
+            .collect())
+    }
+
+    // Waits up to `wait` for each pane to show something, which for a pane
+    // that was just opened is its shell's prompt, or whatever the shell
+    // prints before it. Returns the indexes of the panes that stayed blank.
+    pub(crate) fn wait_for_prompts(
+        &self,
+        window: &Window,
+        mut pending: Vec<Pane>,
+        wait: Duration,
+    ) -> Result<Vec<u8>> {
+        let deadline = Instant::now() + wait;
+        loop {
+            let mut still_pending = vec![];
+            for pane in pending {
+                if !prompt_shown(&self.capture_pane(window, &pane)?) {
+                    still_pending.push(pane);
+                }
+            }
+            pending = still_pending;
+            if pending.is_empty() || Instant::now() >= deadline {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        Ok(pending.into_iter().map(|pane| pane.index).collect())
+    }
+
+    /// Whether tmux can be run at all.
+    pub fn has_tmux(&self) -> bool {
+        let mut version = self.command.clone();
+        version.bin_args = Some(vec!["-V".into()]);
+        self.run(&version)
+            .is_ok_and(|output| output.0.status.success())
+    }
+
+    /// Whether `target` names something that's currently open: a session
+    /// like `dev`, a window like `dev:2` or `@3`, or a pane like `dev:2.1`
+    /// or `%12`.
+    pub fn target_exists(&self, target: &str) -> bool {
+        self.run(&target_exists_command(target, self))
+            .is_ok_and(|output| output.0.status.success())
+    }
+
+    /// Sends keys to the given pane of a window.
+    pub fn send_keys(&self, window: &Window, pane: &Pane, keys: Vec<&str>) -> Result<TmuxOutput> {
+        self.send_keys_to_target(&pane.target(window), keys)
+    }
+
+    /// Sends keys to an already formed target, such as `session:window.1`
+    /// or a pane id like `%12` handed over by another tool.
+    pub fn send_keys_to_target(&self, target: &str, keys: Vec<&str>) -> Result<TmuxOutput> {
+        debug!("sending {:?} to {}", keys, target);
+        let mut send_keys = self.command.send_keys();
+        send_keys.target_pane(target);
+        for key in keys {
+            send_keys.key(key);
+        }
+        self.run(&send_keys.0)
+    }
+
+    /// Sends keys spelled out as `Key`s to the given pane of a window.
+    pub fn send_keys_typed(&self, window: &Window, pane: &Pane, keys: Vec<Key>) -> Result<()> {
+        let target = pane.target(window);
+        debug!("sending {:?} to {}", keys, target);
+        for send_keys in typed_keys_commands(&target, &keys, self) {
+            checked(
+                self.run(&send_keys)?,
+                format!("Couldn't send keys to {}", target),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Lists the panes of the window `target` points at.
+    pub fn panes(&self, target: &str) -> Result<Vec<Pane>> {
+        let output = self.run_retrying(
+            &self
+                .command
+                .list_panes()
+                .target(target)
+                .format(PANE_FORMAT)
+                .0,
+        )?;
+        checked(output, format!("Couldn't list panes for {}", target))?
+            .to_string()
+            .lines()
+            .map(Pane::from_interface)
+            .collect()
+    }
+
+    /// Every session on the server, with their windows and panes. No
+    /// sessions are reported when the server isn't running.
+    pub fn sessions(&self) -> Result<Vec<Session>> {
+        let output = self.run_retrying(&self.command.list_sessions().format(SESSION_FORMAT).0)?;
+        if no_server(&output) {
+            return Ok(vec![]);
+        }
+        let listed = checked(output, "Couldn't list sessions".to_string())?.to_string();
+        Ok(sessions_from(&listed, self.list_windows(None)?))
+    }
+
+    pub fn iter_sessions(&self) -> Result<impl Iterator<Item = Session>> {
+        Ok(self.sessions()?.into_iter())
+    }
+
+    /// `sessions` with their windows and panes as JSON. They're taken as
+    /// already listed, so a caller showing them other ways too lists them
+    /// only once.
+    #[cfg(feature = "serde")]
+    pub fn to_json(sessions: &[Session]) -> Result<String> {
+        Ok(serde_json::to_string(sessions)?)
+    }
+
+    /// Finds the session with exactly this name.
+    pub fn find_session(&self, name: &str) -> Result<Option<Session>> {
+        Ok(self.iter_sessions()?.find(|s| s.name == name))
+    }
+
+    /// Waits for a session that was just created to be listed along with
+    /// its first window. On a cold start the server can still be settling
+    /// when `new-session` returns. Gives up after `TmuxConfig::timeout`.
+    pub fn wait_for_session(&self, name: &str) -> Result<Session> {
+        let deadline = Instant::now() + self.config.timeout;
+        loop {
+            if let Some(session) = self.find_session(name)?.filter(|s| s.window_count() > 0) {
+                return Ok(session);
+            }
+            if Instant::now() >= deadline {
+                return Err(TmuxError::SessionNotReady {
+                    session: name.to_owned(),
+                    after: self.config.timeout,
+                }
+                .into());
+            }
+            debug!("waiting for session {}", name);
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    /// Attaches to the session with exactly this name, at whichever window
+    /// was active in it. From inside tmux the client switches over instead.
+    /// Attaching blocks until the client detaches.
+    pub fn attach(&self, session_name: &str) -> Result<()> {
+        if self.find_session(session_name)?.is_none() {
+            return Err(anyhow!("There's no tmux session named {}", session_name));
+        }
+        self.attach_to(session_name)
+    }
+
+    /// Every window on the server as `session:window`, with the window name
+    /// cleaned up the way dmux names windows, ready to be piped into a fuzzy
+    /// finder. `attach_target` takes one of them back.
+    pub fn pickable_targets(&self) -> Result<Vec<String>> {
+        Ok(self
+            .sessions()?
+            .iter()
+            .flat_map(Session::iter_windows)
+            .map(pickable_target)
+            .collect())
+    }
+
+    /// Attaches to a target a fuzzy finder picked out of `pickable_targets`.
+    /// Anything else is used as a tmux target as it is. When window names
+    /// are shared, the first of them is picked.
+    pub fn attach_target(&self, selected: &str) -> Result<()> {
+        let selected = selected.trim();
+        let target = self
+            .sessions()?
+            .iter()
+            .flat_map(Session::iter_windows)
+            .find(|w| pickable_target(w) == selected)
+            .map_or_else(|| selected.to_owned(), Window::target);
+        if !self.target_exists(&target) {
+            return Err(anyhow!("There's nothing open at {}", selected));
+        }
+        self.attach_to(&target)
+    }
+
+    /// Kills a target a fuzzy finder picked out of `pickable_targets`, or a
+    /// whole session by its name. Anything else is used as a tmux target,
+    /// killing the window it points into.
+    pub fn kill_target(&self, selected: &str) -> Result<()> {
+        let selected = selected.trim();
+        let sessions = self.sessions()?;
+        if let Some(window) = sessions
+            .iter()
+            .flat_map(Session::iter_windows)
+            .find(|w| pickable_target(w) == selected)
+        {
+            return window.kill(self);
+        }
+        if sessions.iter().any(|s| s.name == selected) {
+            return self.kill_session(selected);
+        }
+        if !self.target_exists(selected) {
+            return Err(anyhow!("There's nothing open at {}", selected));
+        }
+        info!("killing window {}", selected);
+        checked(
+            self.run(&kill_window_command(selected, self))?,
+            format!("Couldn't kill window {}", selected),
+        )?;
+        Ok(())
+    }
+
+    /// The session of the pane dmux runs in, when it's run from inside tmux.
+    pub fn current_session(&self) -> Option<String> {
+        pane_session(self)
+    }
+
+    /// Kills the session with exactly this name, along with its windows.
+    pub fn kill_session(&self, name: &str) -> Result<()> {
+        info!("killing session {}", name);
+        checked(
+            self.run(&kill_session_command(name, self))?,
+            format!("Couldn't kill session {}", name),
+        )?;
+        Ok(())
+    }
+
+    fn attach_to(&self, target: &str) -> Result<()> {
+        let client = self.config.host.is_none()
+            && (in_client(&self.run(&server_pid_command(self)).ok())
+                || nested_attach(pane_session(self).as_deref(), target));
+        let attach = attach_command(target, client, self);
+        info!("attaching to {}", target);
+        // switching over a control connection would switch the control client
+        if client {
+            checked(
+                self.spawn(&attach)?,
+                format!("Couldn't switch to {}", target),
+            )?;
+        } else if !self.process(&attach).status()?.success() {
+            return Err(anyhow!("Couldn't attach to {}", target));
+        }
+        Ok(())
+    }
+
+    /// Lists the windows of a session, along with their panes.
+    pub fn windows(&self, session_name: &str) -> Result<Vec<Window>> {
+        self.list_windows(Some(session_name))
+    }
+
+    /// Finds every window of a session with the given name, ordered by index.
+    /// tmux doesn't keep window names unique, so there can be more than one.
+    pub fn find_windows(&self, session_name: &str, name: &str) -> Result<Vec<Window>> {
+        Ok(self
+            .find_session(session_name)?
+            .map(|session| session.find_windows(name).into_iter().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    // windows of a single session, or of all of them
+    fn list_windows(&self, session_name: Option<&str>) -> Result<Vec<Window>> {
+        let (list_windows, list_panes) = self.list_windows_commands(session_name);
+        let target = session_name.unwrap_or("all sessions");
+        let windows = checked(
+            self.run_retrying(&list_windows)?,
+            format!("Couldn't list windows for {}", target),
+        )?;
+        let panes = checked(
+            self.run_retrying(&list_panes)?,
+            format!("Couldn't list panes for {}", target),
+        )?;
+        windows_with_panes(&windows.to_string(), &panes.to_string())
+    }
+
+    pub(crate) fn list_windows_commands<'a>(
+        &'a self,
+        session_name: Option<&'a str>,
+    ) -> (TmuxCommand<'a>, TmuxCommand<'a>) {
+        let mut list_windows = self.command.list_windows();
+        list_windows.format(WINDOW_FORMAT);
+        let mut list_panes = self.command.list_panes();
+        list_panes.format(format!("#{{window_id}}{}{}", FIELD_SEPARATOR, PANE_FORMAT));
+        match session_name {
+            Some(name) => {
+                list_windows.target_session(name);
+                list_panes.session().target(name);
+            }
+            None => {
+                list_windows.all();
+                list_panes.all();
+            }
+        }
+        (list_windows.0, list_panes.0)
+    }
+
+    /// The layout checksum of the window holding `target`.
+    pub fn layout(&self, target: &str) -> Result<String> {
+        let output = checked(
+            self.run_retrying(
+                &self
+                    .command
+                    .list_panes()
+                    .target(target)
+                    .format("#{window_layout}")
+                    .0,
+            )?,
+            format!("Couldn't read the layout of {}", target),
+        )?;
+        // every pane reports the same window layout
+        output
+            .to_string()
+            .lines()
+            .next()
+            .map(str::to_owned)
+            .ok_or_else(|| anyhow!("Couldn't read the layout of {}", target))
+    }
+
+    /// Compacts the window indices of a session, closing the gaps left by
+    /// killed windows, and returns the renumbered windows. Window ids don't
+    /// change, so targets built from them still resolve afterwards.
+    pub fn renumber_windows(&self, session_name: &str) -> Result<Vec<Window>> {
+        let output = self.run(&renumber_windows_command(session_name, self))?;
+        checked(
+            output,
+            format!("Couldn't renumber windows of {}", session_name),
+        )?;
+        self.windows(session_name)
+    }
+
+    /// Kills the tmux server along with every session on it, not just the
+    /// ones dmux created. Nothing calls this implicitly, it's meant for
+    /// test harnesses and deliberate "reset everything" workflows.
+    pub fn kill_server(&self) -> Result<()> {
+        info!("killing the tmux server");
+        let output = self.run(&self.command.kill_server().0)?;
+        checked(output, "Couldn't kill the tmux server".to_string())?;
+        Ok(())
+    }
 }
 
-fn setup_panes_with_commands(workspace: &WorkSpace, tmux: &TmuxCommand) {
-    for _ in 0..workspace.number_of_panes() - 1 {
-        tmux.split_window()
-            .start_directory(workspace.path_str())
-            .target_pane(workspace.target_session(None))
-            .output()
-            .unwrap();
+// tmux reports most failures through its exit status rather than an `Err`
+pub(crate) fn checked(output: TmuxOutput, action: String) -> Result<TmuxOutput> {
+    if output.success() {
+        Ok(output)
+    } else {
+        Err(anyhow!(
+            "{}: {}",
+            action,
+            String::from_utf8_lossy(&output.0.stderr).trim()
+        ))
     }
+}
 
-    tmux.select_layout()
-        .target_pane(workspace.target_session(Some(0)))
-        .layout_name(&workspace.format_checksum)
-        .output()
-        .unwrap();
+// Commands that only look at the server, under their names and aliases.
+// Dry runs still make them, since what's done next depends on what's open.
+const READ_ONLY_COMMANDS: &[&str] = &[
+    "capture-pane",
+    "capturep",
+    "display-message",
+    "display",
+    "has-session",
+    "has",
+    "list-clients",
+    "lsc",
+    "list-panes",
+    "lsp",
+    "list-sessions",
+    "ls",
+    "list-windows",
+    "lsw",
+    "show-environment",
+    "showenv",
+    "show-options",
+    "show",
+    "show-window-options",
+    "showw",
+];
 
-    workspace.commands.iter().enumerate().for_each(|(i, com)| {
-        tmux.send_keys()
-            .target_pane(workspace.target_session(Some(i as u8)))
-            .key(format!("{}\r", com))
-            .output()
-            .unwrap();
-    });
+// whether a command leaves the server as it is, like `-V` with no command
+pub(crate) fn read_only(command: &TmuxCommand) -> bool {
+    command
+        .cmd
+        .as_deref()
+        .is_none_or(|cmd| READ_ONLY_COMMANDS.contains(&cmd))
 }
 
-fn attach_to_window(workspace: &WorkSpace, tmux: &TmuxCommand) {
-    if in_tmux() {
-        // switch to the window which exists
-        tmux.switch_client()
-            .target_session(workspace.target_session(None))
-            .output()
-            .unwrap();
+// The command as a line to paste into a shell. Arguments with anything
+// the shell would read into are single quoted, and ones with control
+// characters, like the carriage return ending a sent line, are written
+// with `$'...'` so they show up.
+pub(crate) fn shell_line(process: &Command) -> String {
+    std::iter::once(process.get_program())
+        .chain(process.get_args())
+        .map(|arg| {
+            let arg = arg.to_string_lossy();
+            if arg.chars().any(char::is_control) {
+                let mut quoted = String::from("$'");
+                for c in arg.chars() {
+                    match c {
+                        '\\' | '\'' => {
+                            quoted.push('\\');
+                            quoted.push(c);
+                        }
+                        '\r' => quoted.push_str("\\r"),
+                        '\n' => quoted.push_str("\\n"),
+                        '\t' => quoted.push_str("\\t"),
+                        c if c.is_control() => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+                        _ => quoted.push(c),
+                    }
+                }
+                quoted.push('\'');
+                quoted
+            } else {
+                shell_word(&arg)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// `arg` as one word for a POSIX shell, as it is when there's nothing in it
+// the shell would read into and single quoted otherwise
+fn shell_word(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./:%@=,+".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.to_owned()
     } else {
-        // attach to the window in the session
-        tmux.attach_session()
-            .target_session(workspace.target_session(None))
-            .output()
-            .unwrap();
-    };
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
 }
 
-pub fn generate_layout() -> Result<()> {
-    let tmux = TmuxCommand::new();
+// `process` run on `host` with ssh, which hands the shell there a single
+// line, so each argument is quoted for it. The shell might not know `$'..'`,
+// so control characters go in single quotes as they are. An attach gets a
+// terminal there and draws on this one.
+fn over_ssh(host: &str, process: &Command, attaching: bool) -> Command {
+    let line: Vec<String> = std::iter::once(process.get_program())
+        .chain(process.get_args())
+        .map(|arg| shell_word(&arg.to_string_lossy()))
+        .collect();
+    let mut ssh = Command::new("ssh");
+    if attaching {
+        ssh.arg("-t")
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit());
+    }
+    ssh.arg(host).arg(line.join(" "));
+    ssh
+}
 
-    let stdout = tmux
-        .list_windows()
-        .format("#{window_active} #{window_layout}")
-        .output()?
-        .0
-        .stdout;
+fn read_all(pipe: Option<&mut impl Read>) -> Vec<u8> {
+    let mut buf = vec![];
+    if let Some(pipe) = pipe {
+        pipe.read_to_end(&mut buf).ok();
+    }
+    buf
+}
 
-    let layout = match std::str::from_utf8(&stdout)?
-        .split('\n')
-        .find(|l| l.starts_with('1'))
-    {
-        Some(layout) => Ok(layout),
-        None => Err(anyhow!("Uh-oh, looks like you're not in a tmux session!")),
-    }?;
+// Reads `list-windows` output in `WINDOW_FORMAT`, then hands each window its
+// panes from `list-panes` output prefixed with the window id.
+pub(crate) fn windows_with_panes(windows: &str, panes: &str) -> Result<Vec<Window>> {
+    let mut windows = windows
+        .lines()
+        .map(Window::from_interface)
+        .collect::<Result<Vec<Window>>>()?;
+    for line in panes.lines() {
+        let (window_id, pane) = line
+            .split_once(FIELD_SEPARATOR)
+            .ok_or_else(|| anyhow!("Couldn't read a pane from {:?}", line))?;
+        if let Some(window) = windows.iter_mut().find(|w| w.id() == Some(window_id)) {
+            window.panes.push(Pane::from_interface(pane)?);
+        }
+    }
+    Ok(windows)
+}
 
-    println!(
-        "{}",
-        layout
-            .split_whitespace()
-            .last()
-            .ok_or_else(|| anyhow!("layout invalid"))?
-    );
-    Ok(())
+// Reads `list-sessions` output in `SESSION_FORMAT`, handing each session
+// its windows.
+pub(crate) fn sessions_from(listed: &str, windows: Vec<Window>) -> Vec<Session> {
+    listed
+        .lines()
+        .map(|line| {
+            let (attached, name) = line.split_once(FIELD_SEPARATOR).unwrap_or(("0", line));
+            Session {
+                name: name.to_owned(),
+                attached: attached.parse().unwrap_or_default(),
+                windows: windows
+                    .iter()
+                    .filter(|w| w.session_name == name)
+                    .cloned()
+                    .collect(),
+            }
+        })
+        .collect()
 }
 
-#[derive(Debug, Clone)]
-pub struct WorkSpace {
-    pub path: PathBuf,
-    pub session_name: String,
-    pub format_checksum: String,
-    pub commands: Vec<String>,
-    pub window_name: Option<String>,
-    pub number_of_panes: u8,
+// listing sessions fails when there's no server, which just means there
+// aren't any
+pub(crate) fn no_server(output: &TmuxOutput) -> bool {
+    let stderr = String::from_utf8_lossy(&output.0.stderr);
+    stderr.contains("no server running") || stderr.contains("error connecting to")
 }
 
-fn clean_str(string: &str) -> String {
-    string.replace(".", "-").replace(" ", "-")
+// Whether the call failed only because the server couldn't be reached. A
+// server that's being killed or started can't be told apart from one that
+// isn't there, so that's retried too.
+pub(crate) fn transient(output: &TmuxOutput) -> bool {
+    let stderr = String::from_utf8_lossy(&output.0.stderr);
+    !output.success()
+        && (no_server(output)
+            || stderr.contains("server exited unexpectedly")
+            || stderr.contains("lost server"))
 }
 
-impl WorkSpace {
-    fn target_session(&self, pane: Option<u8>) -> String {
-        if let Some(pane) = pane {
-            format!(
-                "{}:{}.{}",
-                clean_str(&self.session_name),
-                self.window_name(),
-                pane
-            )
-        } else {
-            format!("{}:{}", clean_str(&self.session_name), self.window_name())
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+pub struct Session {
+    pub name: String,
+    attached: usize,
+    windows: Vec<Window>,
+}
+
+impl Session {
+    /// How many clients were attached to the session when it was listed.
+    pub fn attached(&self) -> usize {
+        self.attached
+    }
+
+    pub fn iter_windows(&self) -> impl Iterator<Item = &Window> {
+        self.windows.iter()
+    }
+
+    /// The session's windows as they were when it was last read from tmux.
+    pub fn windows(&self) -> &[Window] {
+        &self.windows
+    }
+
+    pub fn window_names(&self) -> Vec<&str> {
+        self.windows.iter().map(|w| w.name.as_str()).collect()
+    }
+
+    pub fn window_count(&self) -> usize {
+        self.windows.len()
+    }
+
+    /// Every window with the given name, ordered by index. tmux doesn't keep
+    /// window names unique, so there can be more than one.
+    pub fn find_windows(&self, name: &str) -> Vec<&Window> {
+        let mut windows: Vec<&Window> = self.iter_windows().filter(|w| w.name == name).collect();
+        windows.sort_by_key(|w| w.index);
+        windows
+    }
+
+    /// Links a window from another session into this one, at `dest_index` or
+    /// at the next free index. tmux refuses when `dest_index` is taken unless
+    /// `kill` is set, in which case the window there is replaced.
+    pub fn link_window(
+        &mut self,
+        tmux: &Tmux,
+        source_target: &str,
+        dest_index: Option<usize>,
+        kill: bool,
+    ) -> Result<()> {
+        let dest = match dest_index {
+            Some(index) => format!("{}:{}", self.name, index),
+            None => format!("{}:", self.name),
+        };
+        let mut link_window = tmux.command.link_window();
+        link_window
+            .detached()
+            .src_window(source_target)
+            .dst_window(&dest);
+        if kill {
+            link_window.kill();
         }
+        debug!("linking {} into {}", source_target, dest);
+        checked(
+            tmux.run(&link_window.0)?,
+            format!("Couldn't link {} into {}", source_target, dest),
+        )?;
+
+        self.windows = tmux.windows(&self.name)?;
+        Ok(())
     }
 
-    fn window_name(&self) -> String {
-        if let Some(name) = &self.window_name {
-            name.to_owned()
-        } else {
-            clean_str(
-                &self
-                    .path
-                    .file_name()
-                    .unwrap()
-                    .to_owned()
-                    .into_string()
-                    .unwrap(),
-            )
+    /// Compacts the indexes of the session's windows, closing the gaps left
+    /// by killed windows, and reads its windows again. Window ids don't
+    /// change, so targets built from them still resolve afterwards.
+    pub fn renumber_windows(&mut self, tmux: &Tmux) -> Result<()> {
+        debug!("renumbering the windows of {}", self.name);
+        checked(
+            tmux.run(&renumber_windows_command(&self.name, tmux))?,
+            format!("Couldn't renumber windows of {}", self.name),
+        )?;
+
+        self.windows = tmux.windows(&self.name)?;
+        Ok(())
+    }
+
+    /// Unlinks the window at `index` from this session. tmux refuses to unlink
+    /// a window that isn't linked into any other session.
+    pub fn unlink_window(&mut self, tmux: &Tmux, index: usize) -> Result<()> {
+        let target = format!("{}:{}", self.name, index);
+        debug!("unlinking {}", target);
+        checked(
+            tmux.run(&tmux.command.unlink_window().target_window(&target).0)?,
+            format!("Couldn't unlink {}", target),
+        )?;
+
+        self.windows = tmux.windows(&self.name)?;
+        Ok(())
+    }
+
+    /// Creates a window called `name` in `dir`, or in the session's
+    /// directory without one, at `index` or at the next free index. Gaps
+    /// before `index` are left as they are. tmux refuses when `index` is
+    /// taken unless `replace` is set, in which case the window there is
+    /// killed and the new one takes its place.
+    pub fn create_window(
+        &mut self,
+        tmux: &Tmux,
+        name: &str,
+        dir: Option<&Path>,
+        index: Option<usize>,
+        replace: bool,
+    ) -> Result<Window> {
+        let target = match index {
+            Some(index) => format!("{}:{}", self.name, index),
+            None => format!("{}:", self.name),
+        };
+        // tmux would otherwise start the window wherever dmux was run from
+        let dir = dir.map_or("#{session_path}".into(), Path::to_string_lossy);
+        debug!("creating window {} at {}", name, target);
+        let output = checked(
+            tmux.run(&new_window_command(&target, name, &dir, replace, tmux))?,
+            format!("Couldn't create window {} at {}", name, target),
+        )?;
+        let mut window = Window::from_interface(output.to_string().trim_end())?;
+        window.panes = tmux.panes(&window.target())?;
+
+        self.windows = tmux.windows(&self.name)?;
+        Ok(window)
+    }
+
+    /// Changes the directory the session's new windows start in when they
+    /// aren't given one, as with `create_window`. A leading `~` and any
+    /// variables in `dir` are expanded.
+    pub fn set_default_directory(&self, tmux: &Tmux, dir: &Path) -> Result<()> {
+        let dir = expand_path(dir);
+        if !dir.is_dir() {
+            return Err(anyhow!("{} isn't a directory", dir.display()));
         }
+        // only attaching changes a session's directory, so a control client
+        // attaches with the new one and is closed again right after
+        let mut attach = tmux.command.attach_session();
+        attach
+            .target_session(self.name.as_str())
+            .working_directory(dir.to_string_lossy().into_owned());
+        let mut attach = attach.0;
+        attach.bin_args = Some(vec!["-C".into()]);
+        debug!(
+            "setting the directory of {} to {}",
+            self.name,
+            dir.display()
+        );
+        ControlClient::connect(tmux.process(&attach), tmux.config.timeout).map_err(|_| {
+            anyhow!(
+                "Couldn't set the directory of session {} to {}",
+                self.name,
+                dir.display()
+            )
+        })?;
+        Ok(())
     }
 
-    fn path_str(&self) -> String {
-        self.path.as_os_str().to_owned().into_string().unwrap()
+    /// The clients attached to the session. With the default `window-size`,
+    /// the smallest of them is what a window's size gets clamped to.
+    pub fn clients(&self, tmux: &Tmux) -> Result<Vec<ClientInfo>> {
+        let output = tmux.run(
+            &tmux
+                .command
+                .list_clients()
+                .target_session(self.name.as_str())
+                .format(CLIENT_FORMAT)
+                .0,
+        )?;
+        checked(output, format!("Couldn't list clients of {}", self.name))?
+            .to_string()
+            .lines()
+            .map(ClientInfo::from_interface)
+            .collect()
     }
+}
 
-    fn number_of_panes(&self) -> u8 {
-        max(self.commands.len() as u8, self.number_of_panes)
+const CLIENT_FORMAT: &str = "#{client_width}|:|#{client_height}|:|#{client_name}";
+
+/// The index the first window of a session and the first pane of a window
+/// get, which are tmux's `base-index` and `pane-base-index` options.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IndexBases {
+    pub window: usize,
+    pub pane: u8,
+}
+
+impl IndexBases {
+    pub(crate) fn from_interface(line: &str) -> Option<IndexBases> {
+        let (window, pane) = line.trim().split_once(FIELD_SEPARATOR)?;
+        Some(IndexBases {
+            window: window.parse().ok()?,
+            pane: pane.parse().ok()?,
+        })
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    #[test]
-    fn clean_str_removes_dots_n_stuff() {
-        assert_eq!(clean_str("foo.bar"), "foo-bar")
+// options read as formats, once for each of the target's panes
+pub(crate) fn index_bases_command<'a>(target: &'a str, tmux: &'a Tmux) -> TmuxCommand<'a> {
+    let mut list_panes = tmux.command.list_panes();
+    list_panes
+        .target(target)
+        .format("#{base-index}|:|#{pane-base-index}");
+    list_panes.0
+}
+
+/// A client attached to a session, as `list-clients` reports it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientInfo {
+    /// Usually the client's tty, like `/dev/pts/3`.
+    pub name: String,
+    /// Control clients don't always report a size.
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+}
+
+impl ClientInfo {
+    fn from_interface(line: &str) -> Result<ClientInfo> {
+        let fields: Vec<&str> = line.splitn(3, FIELD_SEPARATOR).collect();
+        if let [width, height, name] = fields[..] {
+            Ok(ClientInfo {
+                name: name.to_owned(),
+                width: width.parse().ok(),
+                height: height.parse().ok(),
+            })
+        } else {
+            Err(anyhow!("Couldn't read a client from {:?}", line))
+        }
     }
+}
 
-    #[test]
-    fn workplace_window_name_replaces_dots_n_spaces() {
-        let wp = WorkSpace {
-            path: PathBuf::from("/Users/zacharythomas/dev/foo.bar/"),
-            session_name: "dev".to_owned(),
-            format_checksum: "34ed,230x56,0,0{132x56,0,0,3,97x56,133,0,222}".to_owned(),
-            commands: vec!["nvim".to_owned(), "fish".to_owned()],
-            window_name: None,
-            number_of_panes: 3,
-        };
-        assert_eq!(wp.window_name(), "foo-bar")
+pub(crate) fn new_window_command<'a>(
+    target: &'a str,
+    name: &'a str,
+    dir: &'a str,
+    replace: bool,
+    tmux: &'a Tmux,
+) -> TmuxCommand<'a> {
+    let mut new_window = tmux.command.new_window();
+    new_window
+        .detached()
+        .print()
+        .format(WINDOW_FORMAT)
+        .target_window(target)
+        .window_name(name)
+        .start_directory(dir);
+    if replace {
+        new_window.kill();
     }
+    new_window.0
+}
 
-    #[test]
-    fn workplace_window_name_returns_window_name_from_path() {
-        let wp = WorkSpace {
-            path: PathBuf::from("/Users/zacharythomas/dev/some_name/"),
+// tmux replaces control characters like tabs in its output, so fields are
+// split on a printable separator that's unlikely to show up in names
+const FIELD_SEPARATOR: &str = "|:|";
+
+pub(crate) const SESSION_FORMAT: &str = "#{session_attached}|:|#{session_name}";
+
+const WINDOW_FORMAT: &str = "#{session_name}|:|#{window_index}|:|#{window_id}|:|#{window_width}|:|#{window_height}|:|#{window_layout}|:|#{window_name}";
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+pub struct Window {
+    pub session_name: String,
+    pub index: usize,
+    id: Option<String>,
+    pub name: String,
+    width: Option<usize>,
+    height: Option<usize>,
+    layout: Option<String>,
+    panes: Vec<Pane>,
+}
+
+impl Window {
+    /// Builds a window from a line of `list-windows` output in `WINDOW_FORMAT`.
+    fn from_interface(line: &str) -> Result<Window> {
+        let fields: Vec<&str> = line.splitn(7, FIELD_SEPARATOR).collect();
+        if let [session_name, index, id, width, height, layout, name] = fields[..] {
+            Ok(Window {
+                session_name: session_name.to_owned(),
+                index: index.parse()?,
+                id: Some(id.to_owned()).filter(|id| id.starts_with('@')),
+                name: name.to_owned(),
+                width: width.parse().ok(),
+                height: height.parse().ok(),
+                layout: Some(layout.to_owned()).filter(|l| !l.is_empty()),
+                panes: vec![],
+            })
+        } else {
+            Err(anyhow!("Couldn't read a window from {:?}", line))
+        }
+    }
+
+    /// Switches to the next of tmux's preset layouts, returning the checksum
+    /// of the window's new layout.
+    pub fn next_layout(&self, tmux: &Tmux) -> Result<String> {
+        self.cycle_layout(tmux, true)
+    }
+
+    /// Switches to the previous of tmux's preset layouts, returning the
+    /// checksum of the window's new layout.
+    pub fn previous_layout(&self, tmux: &Tmux) -> Result<String> {
+        self.cycle_layout(tmux, false)
+    }
+
+    fn cycle_layout(&self, tmux: &Tmux, forward: bool) -> Result<String> {
+        let mut select_layout = tmux.command.select_layout();
+        select_layout.target_pane(self.target());
+        if forward {
+            select_layout.next_layout();
+        } else {
+            select_layout.previous_layout();
+        }
+        checked(
+            tmux.run(&select_layout.0)?,
+            format!("Couldn't change the layout of {}", self.name),
+        )?;
+        tmux.layout(&self.target())
+    }
+
+    pub fn iter_panes(&self) -> impl Iterator<Item = &Pane> {
+        self.panes.iter()
+    }
+
+    /// For each position on screen, counted along rows from the top left,
+    /// the index of the pane there. See `panes_by_position`.
+    pub fn position_map(&self) -> Vec<u8> {
+        panes_by_position(&self.panes)
+            .iter()
+            .map(|p| p.index)
+            .collect()
+    }
+
+    /// The index, current command and current path of each pane, as of when
+    /// the window's panes were last listed.
+    pub fn panes_summary(&self) -> Vec<(u8, Option<&str>, Option<&Path>)> {
+        self.panes
+            .iter()
+            .map(|p| (p.index, p.current_command(), p.current_path()))
+            .collect()
+    }
+
+    /// Swaps panes `a` and `b` with `swap-pane`, then re-reads the panes.
+    /// Indexes go by position, so each pane ends up with the other's index,
+    /// which is how commands can be lined up with panes after manual splits.
+    pub fn swap_panes(&mut self, tmux: &Tmux, a: u8, b: u8) -> Result<()> {
+        debug!("swapping panes {} and {} of {}", a, b, self.target());
+        checked(
+            tmux.run(
+                &tmux
+                    .command
+                    .swap_pane()
+                    .detached()
+                    .src_pane(Pane::from_index(a).target(self))
+                    .dst_pane(Pane::from_index(b).target(self))
+                    .0,
+            )?,
+            format!("Couldn't swap panes {} and {} of {}", a, b, self.name),
+        )?;
+
+        self.panes = tmux.panes(&self.target())?;
+        Ok(())
+    }
+
+    /// Restarts everything running in the window with `respawn-window -k`,
+    /// running `command` or the default shell. tmux closes all but one pane
+    /// along the way, so the window comes back as a single pane and any
+    /// layout has to be set up again, which `reset` does for a workspace.
+    pub fn respawn(&mut self, tmux: &Tmux, command: Option<&str>) -> Result<()> {
+        // TmuxCommand has no shorthand for respawn-window
+        let mut respawn_window = RespawnWindow::from(&tmux.command);
+        respawn_window.kill().target_window(self.target());
+        if let Some(command) = command {
+            respawn_window.shell_command(command);
+        }
+        info!("respawning {}", self.target());
+        checked(
+            tmux.run(&respawn_window.0)?,
+            format!("Couldn't respawn {}", self.name),
+        )?;
+
+        self.panes = tmux.panes(&self.target())?;
+        Ok(())
+    }
+
+    /// Starts the window over as `workspace` describes it, without killing
+    /// it, so its id and index stay the same. Every pane but the first is
+    /// killed, the first gets a fresh shell in the workspace's directory,
+    /// and then the panes are split, laid out and sent their commands again.
+    pub fn reset(&mut self, tmux: &Tmux, workspace: &WorkSpace) -> Result<()> {
+        let workspace = workspace.clone().normalized()?;
+        let first = tmux
+            .panes(&self.target())?
+            .into_iter()
+            .min_by_key(|p| p.index)
+            .ok_or_else(|| anyhow!("{} has no panes", self.name))?;
+
+        info!("resetting {} to {}", self.target(), workspace.path_str());
+        checked(
+            tmux.run(
+                &tmux
+                    .command
+                    .kill_pane()
+                    .all()
+                    .target_pane(first.target(self))
+                    .0,
+            )?,
+            format!("Couldn't clear the panes of {}", self.name),
+        )?;
+
+        // TmuxCommand has no shorthand for respawn-pane
+        let mut respawn_pane = RespawnPane::from(&tmux.command);
+        respawn_pane
+            .kill()
+            .start_directory(workspace.pane_dir(0))
+            .target_pane(first.target(self));
+        with_env(&mut respawn_pane.0, workspace.pane_env(0));
+        if let Some(shell) = workspace.shell_command() {
+            respawn_pane.shell_command(shell);
+        }
+        checked(
+            tmux.run(&respawn_pane.0)?,
+            format!("Couldn't restart the first pane of {}", self.name),
+        )?;
+
+        populate_window(&workspace, self, tmux, &mut |_| {})?;
+        self.panes = tmux.panes(&self.target())?;
+        Ok(())
+    }
+
+    /// Moves `pane` out of `source` and into this window, beside the active
+    /// pane when `direction` is horizontal or below it when vertical. Both
+    /// windows' panes are re-read, since the moved pane gets a new index.
+    pub fn join_pane(
+        &mut self,
+        tmux: &Tmux,
+        source: &mut Window,
+        pane: &Pane,
+        direction: SplitDirection,
+    ) -> Result<()> {
+        let mut join_pane = tmux.command.join_pane();
+        join_pane
+            .detached()
+            .src_pane(pane.target(source))
+            .dst_pane(self.target());
+        match direction {
+            SplitDirection::Horizontal => join_pane.horizontal(),
+            SplitDirection::Vertical => join_pane.vertical(),
+        };
+        debug!("joining {} into {}", pane.target(source), self.target());
+        checked(
+            tmux.run(&join_pane.0)?,
+            format!("Couldn't join pane {} into {}", pane.index, self.name),
+        )?;
+
+        self.panes = tmux.panes(&self.target())?;
+        // the source window closes once its last pane leaves
+        source.panes = tmux.panes(&source.target()).unwrap_or_default();
+        Ok(())
+    }
+
+    /// Splits the window's active pane, or the whole window with
+    /// `Split::full`, and returns the new pane. The panes are re-read after,
+    /// since a pane placed before others shifts their indexes.
+    pub fn split(&mut self, tmux: &Tmux, split: Split) -> Result<Pane> {
+        let mut split_window = tmux.command.split_window();
+        split_window
+            .detached()
+            .print()
+            .format(PANE_FORMAT)
+            .target_pane(self.target());
+        match split.direction {
+            SplitDirection::Horizontal => split_window.horizontal(),
+            SplitDirection::Vertical => split_window.vertical(),
+        };
+        if split.before {
+            split_window.before();
+        }
+        if split.full {
+            split_window.full();
+        }
+        debug!("splitting {} with {:?}", self.target(), split);
+        let output = checked(
+            tmux.run(&split_window.0)?,
+            format!("Couldn't split {}", self.name),
+        )?;
+        let pane = Pane::from_interface(output.to_string().trim_end())?;
+
+        self.panes = tmux.panes(&self.target())?;
+        Ok(pane)
+    }
+
+    /// Gives the window the layout `source` has right now, so a window laid
+    /// out by hand can serve as a template. The window is split until it has
+    /// as many panes as the layout, and one with more panes is refused.
+    pub fn apply_layout_from(&mut self, tmux: &Tmux, source: &str) -> Result<()> {
+        let layout = Layout::from_checksum(&tmux.layout(source)?)?;
+        let wanted = layout
+            .pane_count()
+            .ok_or_else(|| anyhow!("{} has no layout checksum to copy", source))?;
+        self.panes = tmux.panes(&self.target())?;
+        if self.panes.len() > wanted {
+            return Err(anyhow!(
+                "{} has {} panes, more than the {} of {}",
+                self.name,
+                self.panes.len(),
+                wanted,
+                source
+            ));
+        }
+
+        debug!("splitting {} into {} panes", self.target(), wanted);
+        for _ in self.panes.len()..wanted {
+            // evening out after each split leaves room for the next one
+            checked(
+                tmux.run(
+                    &tmux
+                        .command
+                        .split_window()
+                        .detached()
+                        .target_pane(self.target())
+                        .0,
+                )?,
+                format!("Couldn't split {}", self.name),
+            )?;
+            tmux.run(
+                &tmux
+                    .command
+                    .select_layout()
+                    .target_pane(self.target())
+                    .layout_name("tiled")
+                    .0,
+            )?;
+        }
+        debug!("copying the layout of {} to {}", source, self.target());
+        self.select_layout(tmux, &layout)?;
+
+        self.panes = tmux.panes(&self.target())?;
+        Ok(())
+    }
+
+    /// Lays the window out with `select-layout`, setting the main pane size
+    /// of an automatic layout first. tmux refuses a checksum with fewer
+    /// panes than the window has, which comes back as an error.
+    pub fn select_layout(&self, tmux: &Tmux, layout: &Layout) -> Result<()> {
+        if let Some((name, value)) = layout.main_pane_option() {
+            self.set_option(tmux, name, &value)?;
+        }
+        debug!("applying layout {} to {}", layout, self.target());
+        checked(
+            tmux.run(
+                &tmux
+                    .command
+                    .select_layout()
+                    .target_pane(self.target())
+                    .layout_name(layout.as_str())
+                    .0,
+            )?,
+            format!("Couldn't apply layout {} to {}", layout, self.name),
+        )?;
+        Ok(())
+    }
+
+    /// Kills the window and its panes. tmux takes the session down with its
+    /// last window.
+    pub fn kill(&self, tmux: &Tmux) -> Result<()> {
+        info!("killing window {}", self.target());
+        checked(
+            tmux.run(&kill_window_command(&self.target(), tmux))?,
+            format!("Couldn't kill window {}", self.name),
+        )?;
+        Ok(())
+    }
+
+    /// Flags the window in the status line when there's output in it, with
+    /// the window's own `monitor-activity`, so other windows are unaffected.
+    pub fn monitor_activity(&self, tmux: &Tmux, on: bool) -> Result<()> {
+        self.set_option(tmux, "monitor-activity", if on { "on" } else { "off" })
+    }
+
+    /// Sends what's typed in one of the window's panes to all of them, with
+    /// `synchronize-panes`.
+    pub fn synchronize_panes(&self, tmux: &Tmux, on: bool) -> Result<()> {
+        self.set_option(tmux, "synchronize-panes", if on { "on" } else { "off" })
+    }
+
+    /// Flags the window once it's had no output for `seconds`, or stops
+    /// watching for silence with `None`.
+    pub fn monitor_silence(&self, tmux: &Tmux, seconds: Option<u32>) -> Result<()> {
+        self.set_option(tmux, "monitor-silence", &seconds.unwrap_or(0).to_string())
+    }
+
+    fn set_option(&self, tmux: &Tmux, name: &str, value: &str) -> Result<()> {
+        let target = self.target();
+        debug!(
+            "setting window option {} to {:?} on {}",
+            name, value, target
+        );
+        checked(
+            tmux.run(&set_option_command(
+                Scope::Window,
+                name,
+                value,
+                &target,
+                tmux,
+            ))?,
+            format!("Couldn't set {} to {:?} on {}", name, value, self.name),
+        )?;
+        Ok(())
+    }
+
+    pub fn width(&self) -> Option<usize> {
+        self.width
+    }
+
+    pub fn height(&self) -> Option<usize> {
+        self.height
+    }
+
+    /// The window's layout checksum as it was when the window was read, the
+    /// same as `Tmux::layout` without asking tmux again.
+    pub fn layout(&self) -> Option<&str> {
+        self.layout.as_deref()
+    }
+
+    /// The stable `@<id>` of the window, if tmux reported one.
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    // names are only for matching against the workspace, the id is used
+    // for targeting so renames and duplicate names don't matter
+    pub(crate) fn target(&self) -> String {
+        match &self.id {
+            Some(id) => id.to_owned(),
+            None => format!("{}:{}", self.session_name, self.index),
+        }
+    }
+}
+
+// what a pane sitting at a prompt reports as its command
+const SHELLS: &[&str] = &[
+    "bash", "zsh", "fish", "sh", "dash", "ksh", "tcsh", "csh", "nu", "elvish", "xonsh",
+];
+
+// a pane's title is the host's name until something sets it, which leaves
+// it out
+pub(crate) const PANE_FORMAT: &str = "#{pane_index}|:|#{pane_id}|:|#{pane_left}|:|#{pane_top}|:|#{pane_width}|:|#{pane_height}|:|#{pane_pid}|:|#{pane_current_command}|:|#{?#{==:#{pane_title},#{host}},,#{pane_title}}|:|#{pane_current_path}";
+
+/// Which way a pane is placed next to the existing ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitDirection {
+    /// Side by side, like `join-pane -h`.
+    Horizontal,
+    /// Stacked, like `join-pane -v`.
+    Vertical,
+}
+
+/// Where `Window::split` puts the new pane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Split {
+    pub direction: SplitDirection,
+    /// Left of or above the split pane rather than right of or below it,
+    /// like `split-window -b`.
+    pub before: bool,
+    /// Spans the whole width or height of the window instead of splitting
+    /// one pane, like `split-window -f`. A log pane along the bottom is a
+    /// full vertical split.
+    pub full: bool,
+}
+
+/// Signals that can be sent to the process running in a pane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Hangup,
+    Interrupt,
+    Kill,
+    Terminate,
+}
+
+#[cfg(unix)]
+impl Signal {
+    fn number(self) -> libc::c_int {
+        match self {
+            Signal::Hangup => libc::SIGHUP,
+            Signal::Interrupt => libc::SIGINT,
+            Signal::Kill => libc::SIGKILL,
+            Signal::Terminate => libc::SIGTERM,
+        }
+    }
+}
+
+/// A key as `send-keys` understands it, so callers don't need to know how
+/// tmux spells them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Key {
+    /// The character with control held, like `C-b`.
+    Ctrl(char),
+    /// The character with alt (meta) held, like `M-x`.
+    Alt(char),
+    /// A function key, `F1` to `F12`.
+    F(u8),
+    Enter,
+    Escape,
+    /// Text typed as is, even when it looks like a key name such as `Enter`.
+    Literal(String),
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Key::Ctrl(c) => write!(f, "C-{}", c),
+            Key::Alt(c) => write!(f, "M-{}", c),
+            Key::F(n) => write!(f, "F{}", n),
+            Key::Enter => f.write_str("Enter"),
+            Key::Escape => f.write_str("Escape"),
+            Key::Literal(text) => f.write_str(text),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+pub struct Pane {
+    pub index: u8,
+    id: Option<String>,
+    left: Option<usize>,
+    top: Option<usize>,
+    width: Option<usize>,
+    height: Option<usize>,
+    pid: Option<u32>,
+    current_command: Option<String>,
+    title: Option<String>,
+    current_path: Option<PathBuf>,
+}
+
+impl Pane {
+    /// A pane known only by its index, targeted through its window.
+    pub fn from_index(index: u8) -> Pane {
+        Pane {
+            index,
+            ..Default::default()
+        }
+    }
+
+    /// Builds a pane from a line of `list-panes` output in `PANE_FORMAT`.
+    pub(crate) fn from_interface(line: &str) -> Result<Pane> {
+        let fields: Vec<&str> = line.split(FIELD_SEPARATOR).collect();
+        let number = |i: usize| fields.get(i).and_then(|f| f.parse().ok());
+        Ok(Pane {
+            index: fields[0]
+                .parse()
+                .map_err(|_| anyhow!("No pane index in {:?}", line))?,
+            id: fields
+                .get(1)
+                .filter(|id| id.starts_with('%'))
+                .map(|id| id.to_string()),
+            left: number(2),
+            top: number(3),
+            width: number(4),
+            height: number(5),
+            pid: fields.get(6).and_then(|f| f.parse().ok()),
+            current_command: fields
+                .get(7)
+                .filter(|c| !c.is_empty())
+                .map(|c| c.to_string()),
+            title: fields
+                .get(8)
+                .filter(|t| !t.is_empty())
+                .map(|t| t.to_string()),
+            // the path goes last, so a separator in it stays part of it
+            current_path: Some(fields.get(9..).unwrap_or_default().join(FIELD_SEPARATOR))
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from),
+        })
+    }
+
+    /// Column of the pane's left edge within its window.
+    pub fn left(&self) -> Option<usize> {
+        self.left
+    }
+
+    /// Row of the pane's top edge within its window.
+    pub fn top(&self) -> Option<usize> {
+        self.top
+    }
+
+    pub fn width(&self) -> Option<usize> {
+        self.width
+    }
+
+    pub fn height(&self) -> Option<usize> {
+        self.height
+    }
+
+    /// The pid of the process tmux started in the pane, usually a shell.
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    /// The name of what's running in the pane as of the last listing, which
+    /// is the shell's name again once a command finishes.
+    pub fn current_command(&self) -> Option<&str> {
+        self.current_command.as_deref()
+    }
+
+    /// Whether the pane is sitting at a shell prompt as of the last listing,
+    /// going by the name of what's running in it.
+    pub fn is_idle(&self) -> bool {
+        self.current_command
+            .as_deref()
+            .is_some_and(|command| SHELLS.contains(&command.trim_start_matches('-')))
+    }
+
+    /// The pane's title as of the last listing, like one given to it with
+    /// `PaneSpec::title`. None while it's still the host's name, which is
+    /// what tmux starts it out as.
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// The pane's working directory as of the last listing.
+    pub fn current_path(&self) -> Option<&Path> {
+        self.current_path.as_deref()
+    }
+
+    /// Sends a signal to the pane's foreground process group, which is
+    /// whatever is running in the shell, or the shell itself when it's idle.
+    /// Unlike sending `C-c`, this works on programs that put the terminal in
+    /// raw mode.
+    #[cfg(unix)]
+    pub fn send_signal(&self, signal: Signal) -> Result<()> {
+        let pid = self
+            .pid
+            .ok_or_else(|| anyhow!("tmux didn't report a pid for pane {}", self.index))?;
+        // without procfs there's no telling what's in the foreground, so
+        // the pane's own process gets the signal
+        let target = std::fs::read_to_string(format!("/proc/{}/stat", pid))
+            .ok()
+            .and_then(|stat| foreground_group(&stat))
+            .map_or(pid as libc::pid_t, |group| -group);
+        debug!("sending {:?} to {} for pane {}", signal, target, self.index);
+
+        if unsafe { libc::kill(target, signal.number()) } == 0 {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Couldn't send {:?} to pane {}: {}",
+                signal,
+                self.index,
+                std::io::Error::last_os_error()
+            ))
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn send_signal(&self, signal: Signal) -> Result<()> {
+        Err(anyhow!(
+            "Sending {:?} to a pane isn't supported on this platform",
+            signal
+        ))
+    }
+
+    /// Moves the pane out of `window` into a new window of its own called
+    /// `name`, in the same session. Returns the new window, and re-reads
+    /// `window`'s panes since the rest of them may be renumbered.
+    pub fn break_to_window(&self, tmux: &Tmux, window: &mut Window, name: &str) -> Result<Window> {
+        debug!("breaking {} out into {}", self.target(window), name);
+        let output = checked(
+            tmux.run(
+                &tmux
+                    .command
+                    .break_pane()
+                    .detached()
+                    .print()
+                    .format(WINDOW_FORMAT)
+                    .window_name(name)
+                    .src_pane(self.target(window))
+                    .dst_window(format!("{}:", window.session_name))
+                    .0,
+            )?,
+            format!("Couldn't break pane {} out of {}", self.index, window.name),
+        )?;
+        let mut broken_out = Window::from_interface(output.to_string().trim_end())?;
+        broken_out.panes = tmux.panes(&broken_out.target())?;
+
+        // a window's last pane takes the whole window along with it
+        window.panes = tmux.panes(&window.target()).unwrap_or_default();
+        Ok(broken_out)
+    }
+
+    /// Sets the pane's title with `select-pane -T`, which shows up in its
+    /// border when the window's `pane-border-status` is on.
+    pub fn set_title(&self, tmux: &Tmux, window: &Window, title: &str) -> Result<()> {
+        debug!("titling {} {:?}", self.target(window), title);
+        checked(
+            tmux.run(&pane_title_command(&self.target(window), title, tmux))?,
+            format!(
+                "Couldn't set the title of pane {} of {}",
+                self.index, window.name
+            ),
+        )?;
+        Ok(())
+    }
+
+    /// The stable `%<id>` of the pane, if tmux reported one.
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    // pane ids survive renames and reordering, so they're preferred over
+    // the `window.pane` target
+    pub(crate) fn target(&self, window: &Window) -> String {
+        match &self.id {
+            Some(id) => id.to_owned(),
+            None => format!("{}.{}", window.target(), self.index),
+        }
+    }
+}
+
+impl Tmux {
+    /// Opens the workspace and attaches to it. Running it again for the same
+    /// workspace reuses what's already open rather than doubling it up.
+    /// When setting up a session it created fails, that session is killed
+    /// again, while sessions that were already open are left as they are.
+    pub fn setup_workspace(&self, workspace: WorkSpace) -> Result<SetupOutcome> {
+        setup_workspace(workspace, self, &mut |_| {}, &mut || Ok(()))
+    }
+
+    /// Same as `setup_workspace`, reporting each step to `observer` as it
+    /// completes so a UI can show progress.
+    pub fn setup_workspace_with(
+        &self,
+        workspace: WorkSpace,
+        observer: &mut dyn FnMut(SetupEvent),
+    ) -> Result<SetupOutcome> {
+        setup_workspace(workspace, self, observer, &mut || Ok(()))
+    }
+
+    /// Same as `setup_workspace`, calling `ready` once the workspace is set
+    /// up and before attaching to it, which outside of tmux blocks until
+    /// the client detaches. It's called for a detached workspace too, and
+    /// when it fails the workspace is left open without attaching.
+    pub fn setup_workspace_then(
+        &self,
+        workspace: WorkSpace,
+        ready: &mut dyn FnMut() -> Result<()>,
+    ) -> Result<SetupOutcome> {
+        setup_workspace(workspace, self, &mut |_| {}, ready)
+    }
+
+    /// Kills the windows `setup_workspace` opened for the workspace, the
+    /// way it would find them again. A session is killed along with the
+    /// last of its windows. Fails when none of them are open.
+    pub fn kill_workspace(&self, workspace: WorkSpace) -> Result<()> {
+        let workspace = workspace.normalized()?;
+        let session = self
+            .find_session(&workspace.session_name)?
+            .ok_or_else(|| anyhow!("There's no tmux session named {}", workspace.session_name))?;
+        let mut open = vec![];
+        for window in std::iter::once(workspace.clone()).chain(workspace.window_workspaces()?) {
+            open.extend(window.window_in(&session, false).cloned());
+        }
+        if open.is_empty() {
+            return Err(anyhow!(
+                "{} isn't open in session {}",
+                workspace.window_name(),
+                workspace.session_name
+            ));
+        }
+        for window in open {
+            window.kill(self)?;
+        }
+        Ok(())
+    }
+
+    /// The layout checksum of the active window of the current session.
+    pub fn generate_layout(&self) -> Result<String> {
+        generate_layout(self)
+    }
+}
+
+/// Whether setting up a workspace created its session and window or found
+/// them already open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetupOutcome {
+    pub session_created: bool,
+    pub window_created: bool,
+    /// The session's name as tmux has it, which is what `-t` finds it by.
+    pub session: String,
+    /// What was attached to, the same as in `SetupEvent::Attaching`, or
+    /// what would have been for a detached workspace.
+    pub target: String,
+}
+
+/// Steps of setting up a workspace, in the order they happen.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SetupEvent {
+    SessionCreated {
+        session: String,
+    },
+    /// The workspace's window exists, either freshly created or as the first
+    /// window of a new session.
+    WindowCreated {
+        session: String,
+        window: String,
+        index: usize,
+    },
+    /// The window now has `panes` of the `of` panes it's getting.
+    PaneSplit {
+        window: String,
+        panes: u8,
+        of: u8,
+    },
+    LayoutApplied {
+        window: String,
+        layout: String,
+    },
+    CommandSent {
+        window: String,
+        pane: u8,
+        command: String,
+    },
+    /// With `WorkSpace::verify_commands`, a command that never showed up in
+    /// its pane.
+    CommandUnverified {
+        window: String,
+        pane: u8,
+        command: String,
+    },
+    /// Sent right before attaching, which outside of tmux blocks until the
+    /// client detaches.
+    Attaching {
+        target: String,
+    },
+}
+
+fn setup_workspace(
+    workspace: WorkSpace,
+    tmux: &Tmux,
+    observer: &mut dyn FnMut(SetupEvent),
+    ready: &mut dyn FnMut() -> Result<()>,
+) -> Result<SetupOutcome> {
+    let mut workspace = workspace.normalized()?;
+    let replaced = if workspace.fresh {
+        replace_session(&workspace, tmux)?
+    } else {
+        None
+    };
+    // a dry run never killed the session it's replacing
+    let open = if workspace.fresh && tmux.config.dry_run {
+        None
+    } else {
+        tmux.find_session(&workspace.session_name)?
+    };
+    let outcome = if let Some(mut session) = open {
+        if workspace.renumber_windows {
+            session.renumber_windows(tmux)?;
+        }
+        if let Some(window) = workspace.window_in(&session, false) {
+            info!("{} is already open", window.target());
+            if workspace.reapply {
+                info!("reapplying the workspace to {}", window.target());
+                reapply_window(&workspace, window, tmux, observer)?;
+            } else if workspace.force_commands
+                && window.id().is_some()
+                && pane_window(tmux).as_deref() == window.id()
+            {
+                info!("sending the commands to {} again", window.target());
+                send_commands(&workspace, window, tmux, observer)?;
+            }
+            setup_other_windows(&workspace, tmux, observer)?;
+            SetupOutcome {
+                session_created: false,
+                window_created: false,
+                session: workspace.session_name.clone(),
+                target: attach_to_window(&workspace, window, tmux, observer, ready)?,
+            }
+        } else {
+            info!("creating window {}", workspace.window_slot());
+            let created = create_window(&workspace, tmux)?;
+
+            // one already exists from when the window was created
+            let window = setup_panes_with_commands(&workspace, created, tmux, observer)?;
+            setup_other_windows(&workspace, tmux, observer)?;
+
+            SetupOutcome {
+                session_created: false,
+                window_created: true,
+                session: workspace.session_name.clone(),
+                target: attach_to_window(&workspace, &window, tmux, observer, ready)?,
+            }
+        }
+    } else {
+        // No existing tmux session
+        info!(
+            "creating session {} in {}",
+            workspace.session_name,
+            workspace.path_str()
+        );
+        workspace.created_session(tmux.run_retrying(&new_session_command(&workspace, tmux))?)?;
+        let window = match setup_created_session(&workspace, tmux, observer) {
+            Ok(window) => window,
+            Err(err) => {
+                roll_back_session(&workspace.session_name, replaced.as_deref(), tmux);
+                return Err(err);
+            }
+        };
+
+        SetupOutcome {
+            session_created: true,
+            window_created: true,
+            session: workspace.session_name.clone(),
+            target: attach_to_window(&workspace, &window, tmux, observer, ready)?,
+        }
+    };
+
+    // when dmux runs inside the replaced session, this takes dmux down with
+    // it, so it's the very last thing done
+    if let Some(replaced) = replaced {
+        info!("killing session {}", replaced);
+        checked(
+            tmux.run(&kill_session_command(&replaced, tmux))?,
+            format!("Couldn't kill session {}", replaced),
+        )?;
+    }
+    Ok(outcome)
+}
+
+// Everything done to a session dmux just created, kept apart so a failure
+// can take the half built session down again.
+fn setup_created_session(
+    workspace: &WorkSpace,
+    tmux: &Tmux,
+    observer: &mut dyn FnMut(SetupEvent),
+) -> Result<Window> {
+    if !tmux.config.dry_run {
+        tmux.wait_for_session(&workspace.session_name)?;
+    }
+    observer(SetupEvent::SessionCreated {
+        session: workspace.session_name.clone(),
+    });
+    apply_options(
+        workspace,
+        &[Scope::Global, Scope::Session],
+        &workspace.session_name,
+        tmux,
+    )?;
+
+    let mut created = None;
+    if workspace.keep_initial_window {
+        info!("creating window {}", workspace.window_slot());
+        created = create_window(workspace, tmux)?;
+    } else if let Some(index) = workspace.window_index {
+        // the first window starts at base-index, which might not be the
+        // index the workspace asked for
+        let session = tmux.find_session(&workspace.session_name)?;
+        if let Some(first) = session
+            .as_ref()
+            .and_then(|s| s.iter_windows().find(|w| w.index != index))
+        {
+            debug!("moving {} to index {}", first.target(), index);
+            checked(
+                tmux.run(&move_window_command(workspace, first, tmux))?,
+                format!("Couldn't move {} to index {}", first.target(), index),
+            )?;
+        }
+    }
+
+    let window = setup_panes_with_commands(workspace, created, tmux, observer)?;
+    setup_other_windows(workspace, tmux, observer)?;
+    Ok(window)
+}
+
+// Opens the workspace's other windows that aren't open yet.
+fn setup_other_windows(
+    workspace: &WorkSpace,
+    tmux: &Tmux,
+    observer: &mut dyn FnMut(SetupEvent),
+) -> Result<()> {
+    for other in workspace.window_workspaces()? {
+        let session = tmux.find_session(&other.session_name)?;
+        if let Some(window) = session.as_ref().and_then(|s| other.window_in(s, false)) {
+            info!("{} is already open", window.target());
+            if other.reapply {
+                reapply_window(&other, window, tmux, observer)?;
+            }
+            continue;
+        }
+        info!("creating window {}", other.window_slot());
+        let created = create_window(&other, tmux)?;
+        setup_panes_with_commands(&other, created, tmux, observer)?;
+    }
+    Ok(())
+}
+
+// Kills a session dmux created but couldn't finish setting up, so the next
+// run starts over instead of finding it half built. A session `fresh` set
+// aside for it gets its name back. This is already cleaning up after an
+// error, so failing here only gets logged.
+fn roll_back_session(name: &str, replaced: Option<&str>, tmux: &Tmux) {
+    warn!("setting up session {} failed, killing it", name);
+    let killed = tmux.run(&kill_session_command(name, tmux));
+    if !killed.is_ok_and(|output| output.success()) {
+        warn!("couldn't kill session {}", name);
+    }
+    if let Some(replaced) = replaced {
+        info!("renaming session {} back to {}", replaced, name);
+        let renamed = tmux.run(&rename_session_command(replaced, name, tmux));
+        if !renamed.is_ok_and(|output| output.success()) {
+            warn!("couldn't rename session {} back to {}", replaced, name);
+        }
+    }
+}
+
+// Clears the way for a fresh session. A session that isn't in use by the
+// client dmux runs in is killed right away. The client's own session is
+// renamed instead and its new name returned, so it can be killed once the
+// client has moved over to the new session.
+fn replace_session(workspace: &WorkSpace, tmux: &Tmux) -> Result<Option<String>> {
+    let name = &workspace.session_name;
+    if tmux.find_session(name)?.is_none() {
+        return Ok(None);
+    }
+    if pane_session(tmux).as_ref() == Some(name) {
+        let replaced = replaced_session_name(name);
+        info!(
+            "renaming session {} to {} until it's replaced",
+            name, replaced
+        );
+        checked(
+            tmux.run(&rename_session_command(name, &replaced, tmux))?,
+            format!("Couldn't rename session {}", name),
+        )?;
+        Ok(Some(replaced))
+    } else {
+        info!("killing session {}", name);
+        checked(
+            tmux.run(&kill_session_command(name, tmux))?,
+            format!("Couldn't kill session {}", name),
+        )?;
+        Ok(None)
+    }
+}
+
+pub(crate) fn replaced_session_name(name: &str) -> String {
+    format!("{}-replaced-{}", name, std::process::id())
+}
+
+// The session of the pane dmux was started from, when that's on the server
+// `tmux` talks to.
+fn pane_session(tmux: &Tmux) -> Option<String> {
+    // $TMUX_PANE is a pane of this machine's server, not the host's
+    if tmux.config.host.is_some() {
+        return None;
+    }
+    let pane = std::env::var("TMUX_PANE").ok()?;
+    first_line(tmux.run(&current_session_command(&pane, tmux)).ok()?)
+}
+
+// The id of the window dmux was started from, like `pane_session`.
+fn pane_window(tmux: &Tmux) -> Option<String> {
+    if tmux.config.host.is_some() {
+        return None;
+    }
+    let pane = std::env::var("TMUX_PANE").ok()?;
+    first_line(tmux.run(&current_window_command(&pane, tmux)).ok()?)
+}
+
+pub(crate) fn current_window_command<'a>(pane: &'a str, tmux: &'a Tmux) -> TmuxCommand<'a> {
+    let mut list_panes = tmux.command.list_panes();
+    list_panes.target(pane).format("#{window_id}");
+    list_panes.0
+}
+
+// the session of the pane dmux was started from
+pub(crate) fn current_session_command<'a>(pane: &'a str, tmux: &'a Tmux) -> TmuxCommand<'a> {
+    let mut list_panes = tmux.command.list_panes();
+    list_panes.target(pane).format("#{session_name}");
+    list_panes.0
+}
+
+// what a successful lookup like `current_session_command` reported
+pub(crate) fn first_line(output: TmuxOutput) -> Option<String> {
+    if !output.success() {
+        return None;
+    }
+    output.to_string().lines().next().map(str::to_owned)
+}
+
+pub(crate) fn kill_session_command<'a>(name: &'a str, tmux: &'a Tmux) -> TmuxCommand<'a> {
+    let mut kill_session = tmux.command.kill_session();
+    kill_session.target_session(name);
+    kill_session.0
+}
+
+pub(crate) fn kill_window_command<'a>(target: &'a str, tmux: &'a Tmux) -> TmuxCommand<'a> {
+    let mut kill_window = tmux.command.kill_window();
+    kill_window.target_window(target);
+    kill_window.0
+}
+
+pub(crate) fn rename_session_command<'a>(
+    name: &'a str,
+    new_name: &'a str,
+    tmux: &'a Tmux,
+) -> TmuxCommand<'a> {
+    let mut rename_session = tmux.command.rename_session();
+    rename_session.target_session(name).new_name(new_name);
+    rename_session.0
+}
+
+// Creates a new session. Its first window becomes the workspace's window,
+// unless it's kept around as a scratch shell.
+pub(crate) fn new_session_command<'a>(workspace: &'a WorkSpace, tmux: &'a Tmux) -> TmuxCommand<'a> {
+    let mut new_session = tmux.command.new_session();
+    new_session
+        .session_name(&workspace.session_name)
+        .detached()
+        .print()
+        .format("#{session_name}");
+    if workspace.keep_initial_window {
+        new_session.start_directory(workspace.path_str());
+    } else {
+        new_session
+            .start_directory(workspace.pane_dir(0))
+            .window_name(workspace.window_name());
+        with_env(&mut new_session.0, workspace.pane_env(0));
+        // the shell command has to come last
+        if let Some(shell) = workspace.shell_command() {
+            new_session.shell_command(shell);
+        }
+    }
+    new_session.0
+}
+
+pub(crate) fn create_window_command<'a>(
+    workspace: &'a WorkSpace,
+    tmux: &'a Tmux,
+) -> TmuxCommand<'a> {
+    let mut new_window = tmux.command.new_window();
+    new_window
+        .target_window(workspace.window_slot())
+        .window_name(workspace.window_name())
+        .start_directory(workspace.pane_dir(0))
+        // first command goes in defaut pane
+        .detached()
+        .print()
+        .format(WINDOW_FORMAT);
+    with_env(&mut new_window.0, workspace.pane_env(0));
+    if let Some(shell) = workspace.shell_command() {
+        new_window.shell_command(shell);
+    }
+    new_window.0
+}
+
+// `-e` for each variable, which has to go ahead of the shell command
+fn with_env(command: &mut TmuxCommand, env: Vec<String>) {
+    for variable in env {
+        command.push_option("-e", variable);
+    }
+}
+
+// The window `create_window_command` reports back, which saves listing
+// every window to find it. Nothing comes back when tmux failed or printed
+// something else, and the window is looked up instead.
+pub(crate) fn created_window(new_window: TmuxOutput) -> Option<Window> {
+    if !new_window.success() {
+        return None;
+    }
+    Window::from_interface(new_window.to_string().lines().next()?).ok()
+}
+
+// Creates the workspace's window, failing with what tmux said when it
+// couldn't rather than going on without it
+fn create_window(workspace: &WorkSpace, tmux: &Tmux) -> Result<Option<Window>> {
+    Ok(created_window(checked(
+        tmux.run(&create_window_command(workspace, tmux))?,
+        format!("Couldn't create window {}", workspace.window_slot()),
+    )?))
+}
+
+pub(crate) fn renumber_windows_command<'a>(session_name: &str, tmux: &'a Tmux) -> TmuxCommand<'a> {
+    tmux.command
+        .move_window()
+        .renumber()
+        .dst_window(format!("{}:", session_name))
+        .0
+        .to_owned()
+}
+
+pub(crate) fn move_window_command<'a>(
+    workspace: &'a WorkSpace,
+    window: &Window,
+    tmux: &'a Tmux,
+) -> TmuxCommand<'a> {
+    tmux.command
+        .move_window()
+        .src_window(window.target())
+        .dst_window(workspace.window_slot())
+        .0
+        .to_owned()
+}
+
+// splits off the `nth` pane of the window, counting from 0
+pub(crate) fn split_window_command<'a>(
+    workspace: &'a WorkSpace,
+    window: &Window,
+    nth: u8,
+    tmux: &'a Tmux,
+) -> TmuxCommand<'a> {
+    let mut split_window = tmux.command.split_window();
+    split_window
+        .start_directory(workspace.pane_dir(nth))
+        .target_pane(window.target());
+    with_env(&mut split_window.0, workspace.pane_env(nth));
+    if let Some(shell) = workspace.shell_command() {
+        split_window.shell_command(shell);
+    }
+    split_window.0
+}
+
+// A split of one pane of a `SplitTree`, printing the id of the new pane.
+pub(crate) fn tree_split_command<'a>(
+    workspace: &'a WorkSpace,
+    step: &PlannedSplit,
+    target: &'a str,
+    tmux: &'a Tmux,
+) -> TmuxCommand<'a> {
+    let mut split_window = tmux.command.split_window();
+    split_window
+        .print()
+        .format("#{pane_id}")
+        .size(&PaneSize::Percentage(step.percent.into()))
+        .start_directory(workspace.pane_dir(step.pane as u8))
+        .target_pane(target);
+    match step.direction {
+        SplitDirection::Horizontal => split_window.horizontal(),
+        SplitDirection::Vertical => split_window.vertical(),
+    };
+    with_env(&mut split_window.0, workspace.pane_env(step.pane as u8));
+    if let Some(shell) = workspace.shell_command() {
+        split_window.shell_command(shell);
+    }
+    split_window.0
+}
+
+fn setup_panes_with_commands(
+    workspace: &WorkSpace,
+    created: Option<Window>,
+    tmux: &Tmux,
+    observer: &mut dyn FnMut(SetupEvent),
+) -> Result<Window> {
+    let window = match created {
+        Some(window) => window,
+        None if tmux.config.dry_run => {
+            planned_window(workspace, tmux.index_bases(&workspace.session_name))
+        }
+        // the window was just created, so it's the newest one with this name
+        None => tmux
+            .find_session(&workspace.session_name)?
+            .and_then(|session| workspace.window_in(&session, true).cloned())
+            .ok_or_else(|| anyhow!("Couldn't find window {}", workspace.target_session(None)))?,
+    };
+    observer(SetupEvent::WindowCreated {
+        session: window.session_name.clone(),
+        window: window.name.clone(),
+        index: window.index,
+    });
+    apply_options(workspace, &[Scope::Window], &window.target(), tmux)?;
+    let target = window.target();
+    for (name, value) in border_options(workspace) {
+        let command = set_option_command(Scope::Window, name, value, &target, tmux);
+        check_border_option(name, &tmux.run(&command).ok());
+    }
+
+    populate_window(workspace, &window, tmux, observer)?;
+    Ok(window)
+}
+
+// The window a dry run would have created, with nothing printed to read it
+// back from. It goes by its index, or the first one's, rather than an id.
+fn planned_window(workspace: &WorkSpace, bases: IndexBases) -> Window {
+    Window {
+        session_name: workspace.session_name.clone(),
+        index: workspace.window_index.unwrap_or(bases.window),
+        id: None,
+        name: workspace.window_name(),
+        width: None,
+        height: None,
+        layout: None,
+        panes: vec![],
+    }
+}
+
+// Sets the workspace's options in the given scopes on `target`.
+fn apply_options(workspace: &WorkSpace, scopes: &[Scope], target: &str, tmux: &Tmux) -> Result<()> {
+    for (scope, name, value) in workspace.options_in(scopes) {
+        debug!(
+            "setting {:?} option {} to {:?} on {}",
+            scope, name, value, target
+        );
+        checked(
+            tmux.run(&set_option_command(*scope, name, value, target, tmux))?,
+            format!("Couldn't set {} to {:?}", name, value),
+        )?;
+    }
+    Ok(())
+}
+
+pub(crate) fn pane_title_command<'a>(
+    target: &'a str,
+    title: &'a str,
+    tmux: &'a Tmux,
+) -> TmuxCommand<'a> {
+    tmux.command
+        .select_pane()
+        .title(title)
+        .target_pane(target)
+        .0
+        .to_owned()
+}
+
+pub(crate) fn set_option_command<'a>(
+    scope: Scope,
+    name: &'a str,
+    value: &'a str,
+    target: &'a str,
+    tmux: &'a Tmux,
+) -> TmuxCommand<'a> {
+    let mut set_option = tmux.command.set_option();
+    match scope {
+        Scope::Global => set_option.global(),
+        Scope::Session => set_option.target(target),
+        Scope::Window => set_option.window().target(target),
+    };
+    set_option.option(name).value(value);
+    set_option.0
+}
+
+// Splits a window with a single pane into the workspace's panes, lays them
+// out and sends each its command.
+fn populate_window(
+    workspace: &WorkSpace,
+    window: &Window,
+    tmux: &Tmux,
+    observer: &mut dyn FnMut(SetupEvent),
+) -> Result<()> {
+    if let Some(splits) = &workspace.splits {
+        let targets = split_by_tree(workspace, splits, window, tmux, observer)?;
+        title_panes(workspace, window, Some(targets), tmux)?;
+        return send_commands(workspace, window, tmux, observer);
+    }
+    debug!(
+        "splitting {} into {} panes",
+        window.target(),
+        workspace.number_of_panes()
+    );
+    for panes in 2..=workspace.number_of_panes() {
+        checked(
+            tmux.run(&split_window_command(workspace, window, panes - 1, tmux))?,
+            format!("Couldn't split {}", window.target()),
+        )?;
+        observer(SetupEvent::PaneSplit {
+            window: window.name.clone(),
+            panes,
+            of: workspace.number_of_panes(),
+        });
+    }
+
+    window.select_layout(tmux, &workspace.layout()?)?;
+    check_layout_fits(workspace, window);
+    observer(SetupEvent::LayoutApplied {
+        window: window.name.clone(),
+        layout: workspace.format_checksum.clone(),
+    });
+    title_panes(workspace, window, None, tmux)?;
+    send_commands(workspace, window, tmux, observer)
+}
+
+// Splits a window with a single pane the way `splits` says, returning the
+// target of each pane in the tree's order. Pane indexes shift as panes are
+// added, so each split targets its pane by id.
+fn split_by_tree(
+    workspace: &WorkSpace,
+    splits: &SplitTree,
+    window: &Window,
+    tmux: &Tmux,
+    observer: &mut dyn FnMut(SetupEvent),
+) -> Result<Vec<String>> {
+    let mut targets = vec![None; splits.pane_count()];
+    targets[0] = match tmux.panes(&window.target()) {
+        Ok(panes) => panes.first().map(|pane| pane.target(window)),
+        // a dry run's window was never made
+        Err(_) if tmux.config.dry_run => Some(window.target()),
+        Err(err) => return Err(err),
+    };
+    for (done, step) in splits.plan().iter().enumerate() {
+        let target = targets[step.target]
+            .clone()
+            .ok_or_else(|| anyhow!("Couldn't find pane {} of {}", step.target, window.name))?;
+        debug!("splitting {} with {:?}", target, step);
+        let output = checked(
+            tmux.run(&tree_split_command(workspace, step, &target, tmux))?,
+            format!("Couldn't split pane {} of {}", step.target, window.name),
+        )?;
+        let pane = output.to_string().trim_end().to_owned();
+        // a dry run prints no id, so the pane goes by where it ends up
+        targets[step.pane] = Some(if pane.is_empty() && tmux.config.dry_run {
+            let base = tmux.index_bases(&window.target()).pane;
+            format!("{}.{}", window.target(), base as usize + step.pane)
+        } else {
+            pane
+        });
+        observer(SetupEvent::PaneSplit {
+            window: window.name.clone(),
+            panes: done as u8 + 2,
+            of: workspace.number_of_panes(),
+        });
+    }
+    Ok(targets.into_iter().flatten().collect())
+}
+
+// Gives the panes the titles `PaneSpec::title` asks for. `targets` are the
+// panes in the order they were created, which are otherwise found by index.
+fn title_panes(
+    workspace: &WorkSpace,
+    window: &Window,
+    targets: Option<Vec<String>>,
+    tmux: &Tmux,
+) -> Result<()> {
+    if workspace.panes.iter().all(|pane| pane.title.is_none()) {
+        return Ok(());
+    }
+    let targets = targets.unwrap_or_else(|| {
+        let panes = tmux.panes(&window.target()).unwrap_or_default();
+        let base = tmux.index_bases(&window.target()).pane;
+        (0..workspace.panes.len())
+            .map(|nth| pane_at(&panes, nth, base).target(window))
+            .collect()
+    });
+    for (nth, title) in workspace.pane_titles() {
+        let Some(target) = targets.get(nth) else {
+            continue;
+        };
+        debug!("titling {} {:?}", target, title);
+        checked(
+            tmux.run(&pane_title_command(target, title, tmux))?,
+            format!("Couldn't set the title of pane {} of {}", nth, window.name),
+        )?;
+    }
+    Ok(())
+}
+
+// Brings a window that was already open in line with the workspace: the
+// panes it's missing are split off, the layout is applied again and the new
+// panes get their commands, as do the ones sitting at a prompt with
+// `reapply_commands`. Panes past the workspace's are left alone, and with
+// `splits` only a window that's still a single pane is split.
+fn reapply_window(
+    workspace: &WorkSpace,
+    window: &Window,
+    tmux: &Tmux,
+    observer: &mut dyn FnMut(SetupEvent),
+) -> Result<()> {
+    let open: Vec<u8> = tmux
+        .panes(&window.target())?
+        .iter()
+        .map(|pane| pane.index)
+        .collect();
+    let of = workspace.number_of_panes();
+    if let Some(splits) = &workspace.splits {
+        if open.len() == 1 && of > 1 {
+            let targets = split_by_tree(workspace, splits, window, tmux, observer)?;
+            title_panes(workspace, window, Some(targets), tmux)?;
+        } else if open.len() < of.into() {
+            warn(format!(
+                "{} is already split, so its splits aren't applied again",
+                window.target()
+            ));
+        }
+    } else {
+        for panes in open.len() as u8 + 1..=of {
+            checked(
+                tmux.run(&split_window_command(workspace, window, panes - 1, tmux))?,
+                format!("Couldn't split {}", window.target()),
+            )?;
+            observer(SetupEvent::PaneSplit {
+                window: window.name.clone(),
+                panes,
+                of,
+            });
+        }
+        window.select_layout(tmux, &workspace.layout()?)?;
+        check_layout_fits(workspace, window);
+        observer(SetupEvent::LayoutApplied {
+            window: window.name.clone(),
+            layout: workspace.format_checksum.clone(),
+        });
+        title_panes(workspace, window, None, tmux)?;
+    }
+    let resend =
+        |pane: &Pane| !open.contains(&pane.index) || (workspace.reapply_commands && pane.is_idle());
+    send_commands_where(workspace, window, tmux, observer, &resend)
+}
+
+// Sends each command to its pane, going by the panes the window has now.
+fn send_commands(
+    workspace: &WorkSpace,
+    window: &Window,
+    tmux: &Tmux,
+    observer: &mut dyn FnMut(SetupEvent),
+) -> Result<()> {
+    send_commands_where(workspace, window, tmux, observer, &|_| true)
+}
+
+// Same as `send_commands`, leaving out the panes `keep` turns down.
+fn send_commands_where(
+    workspace: &WorkSpace,
+    window: &Window,
+    tmux: &Tmux,
+    observer: &mut dyn FnMut(SetupEvent),
+    keep: &dyn Fn(&Pane) -> bool,
+) -> Result<()> {
+    // fall back to index based targets if the panes can't be listed
+    let (panes, commands): (Vec<Pane>, Vec<&String>) = command_panes(
+        workspace,
+        tmux.panes(&window.target()).unwrap_or_default(),
+        tmux.index_bases(&window.target()).pane,
+    )
+    .into_iter()
+    .zip(&workspace.commands)
+    .filter(|(pane, _)| keep(pane))
+    .unzip();
+    if let Some(wait) = workspace.wait_for_prompt.filter(|_| !tmux.config.dry_run) {
+        let waiting = prompt_panes(&panes, &commands);
+        for pane in tmux.wait_for_prompts(window, waiting, wait)? {
+            report_no_prompt(window, pane, wait);
+        }
+    }
+    // keys sent to one pane of a synchronized window go to all of them, so
+    // it's only synchronized once each pane has its own command
+    if workspace.synchronize && !commands.is_empty() {
+        window.synchronize_panes(tmux, false)?;
+    }
+    // a script has landed once its last line shows up
+    let mut last_lines = vec![];
+    for (pane, com) in panes.iter().zip(commands) {
+        let lines = command_lines(com)?;
+        for line in &lines {
+            debug!("sending {:?} to {}", line, pane.target(window));
+            let sent = tmux.run(&send_line_command(&pane.target(window), line, tmux))?;
+            check_sent(sent, window, pane, line)?;
+        }
+        observer(SetupEvent::CommandSent {
+            window: window.name.clone(),
+            pane: pane.index,
+            command: com.clone(),
+        });
+        last_lines.push(lines.last().cloned().unwrap_or_default());
+    }
+
+    if workspace.verify_commands && !tmux.config.dry_run {
+        let sent = panes.into_iter().zip(&last_lines).collect();
+        for (pane, command) in tmux.verify_sent(window, sent, VERIFY_WAIT)? {
+            report_unverified(window, pane, command, observer);
+        }
+    }
+    if workspace.synchronize {
+        window.synchronize_panes(tmux, true)?;
+    }
+    Ok(())
+}
+
+// The panes that have a command coming, which are the ones worth waiting on.
+pub(crate) fn prompt_panes(panes: &[Pane], commands: &[&String]) -> Vec<Pane> {
+    panes
+        .iter()
+        .zip(commands)
+        .filter(|(_, command)| !command.is_empty())
+        .map(|(pane, _)| pane.clone())
+        .collect()
+}
+
+pub(crate) fn report_no_prompt(window: &Window, pane: u8, wait: Duration) {
+    warn(format!(
+        "pane {} of {} didn't show a prompt within {:?}, sending its command anyway",
+        pane, window.name, wait
+    ));
+}
+
+// how long a freshly started shell gets to echo what it was sent
+pub(crate) const VERIFY_WAIT: Duration = Duration::from_secs(2);
+
+pub(crate) fn report_unverified(
+    window: &Window,
+    pane: u8,
+    command: String,
+    observer: &mut dyn FnMut(SetupEvent),
+) {
+    warn(format!(
+        "{:?} doesn't show up in pane {} of {}, the shell may not have been ready for it",
+        command, pane, window.name
+    ));
+    observer(SetupEvent::CommandUnverified {
+        window: window.name.clone(),
+        pane,
+        command,
+    });
+}
+
+// commands starting with this name a script whose lines are sent instead
+const SCRIPT_PREFIX: &str = "file:";
+
+// The lines to send for one of the workspace's commands. A script's lines
+// are each sent on their own rather than sourced, so they work in whatever
+// the pane runs, not just shells that have `source`. Blank lines are
+// skipped.
+pub(crate) fn command_lines(command: &str) -> Result<Vec<String>> {
+    match command.strip_prefix(SCRIPT_PREFIX) {
+        Some(script) => Ok(std::fs::read_to_string(script)
+            .map_err(|err| anyhow!("Couldn't read the script {}: {}", script, err))?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_owned)
+            .collect()),
+        None if command.contains(['\n', '\r']) => Ok(command
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_owned)
+            .collect()),
+        None => Ok(vec![command.to_owned()]),
+    }
+}
+
+// Keys are typed into the pane, so a line break would hit enter early and
+// other control characters do whatever the program in the pane makes of
+// them. Tabs are left alone.
+fn check_command(command: &str, multiline: bool) -> Result<()> {
+    if let Some(c) = command
+        .chars()
+        .find(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r'))
+    {
+        return Err(anyhow!(
+            "The command {:?} has a control character in it ({:?})",
+            command,
+            c
+        ));
+    }
+    if !multiline && command.contains(['\n', '\r']) {
+        return Err(anyhow!(
+            "The command {:?} spans several lines, set multiline_commands to send each line on its own",
+            command
+        ));
+    }
+    Ok(())
+}
+
+// Types a line of a command into the pane and hits enter. `-l` keeps tmux
+// from reading the line as key names, like `Enter` or `C-c`.
+pub(crate) fn send_line_command<'a>(
+    target: &'a str,
+    line: &str,
+    tmux: &'a Tmux,
+) -> TmuxCommand<'a> {
+    let mut send_keys = tmux.command.send_keys();
+    send_keys
+        .disable_lookup()
+        .target_pane(target)
+        .key(format!("{}\r", line));
+    send_keys.0
+}
+
+pub(crate) fn prompt_shown(capture: &str) -> bool {
+    !capture.trim().is_empty()
+}
+
+// whether a pane's contents show a command was typed into it
+pub(crate) fn command_landed(capture: &str, command: &str) -> bool {
+    let command = command.trim();
+    command.is_empty() || capture.contains(command)
+}
+
+// Warns about an applied layout that might not fit. A detached window has
+// tmux's default size rather than the client's, which a layout generated for
+// a particular terminal won't fit.
+pub(crate) fn check_layout_fits(workspace: &WorkSpace, window: &Window) {
+    if let (Some((width, height)), Some(window_width), Some(window_height)) = (
+        layout_size(&workspace.format_checksum),
+        window.width(),
+        window.height(),
+    ) {
+        if (width, height) != (window_width, window_height) {
+            warn(format!(
+                "layout {} is for a {}x{} window but {} is {}x{}, so it may look off once attached. \
+                 A preset like `tiled` fits any size.",
+                workspace.format_checksum, width, height, window.name, window_width, window_height
+            ));
+        }
+    }
+}
+
+// a failed send-keys otherwise looks just like one that landed
+pub(crate) fn check_sent(
+    output: TmuxOutput,
+    window: &Window,
+    pane: &Pane,
+    command: &str,
+) -> Result<()> {
+    checked(
+        output,
+        format!(
+            "Couldn't send {:?} to pane {} of {}",
+            command, pane.index, window.name
+        ),
+    )?;
+    Ok(())
+}
+
+// The pane each of the workspace's commands is sent to, in order. `base` is
+// the server's `pane-base-index`, for when the panes couldn't be listed.
+pub(crate) fn command_panes(workspace: &WorkSpace, panes: Vec<Pane>, base: u8) -> Vec<Pane> {
+    let count = workspace.commands.len();
+    if workspace.commands_by_position && panes.len() >= count {
+        return panes_by_position(&panes)
+            .into_iter()
+            .take(count)
+            .cloned()
+            .collect();
+    }
+    (0..count).map(|i| pane_at(&panes, i, base)).collect()
+}
+
+/// Orders panes the way they read on screen, left to right along each row
+/// and then row by row, which after a few splits is rarely index order.
+pub fn panes_by_position(panes: &[Pane]) -> Vec<&Pane> {
+    let mut ordered: Vec<&Pane> = panes.iter().collect();
+    ordered.sort_by_key(|p| (p.top, p.left, p.index));
+    ordered
+}
+
+// The `nth` of the panes in the order of their indexes, which start at the
+// server's `pane-base-index`. When it wasn't listed it's targeted by the
+// index it would have, counting from `base`.
+pub(crate) fn pane_at(panes: &[Pane], nth: usize, base: u8) -> Pane {
+    let mut ordered: Vec<&Pane> = panes.iter().collect();
+    ordered.sort_by_key(|p| p.index);
+    ordered
+        .get(nth)
+        .map(|p| (*p).clone())
+        .unwrap_or_else(|| Pane::from_index(base.saturating_add(nth as u8)))
+}
+
+/// One of tmux's built in layouts, which fit any window size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    EvenHorizontal,
+    EvenVertical,
+    MainHorizontal,
+    MainHorizontalMirrored,
+    MainVertical,
+    MainVerticalMirrored,
+    Tiled,
+}
+
+impl Preset {
+    const ALL: [Preset; 7] = [
+        Preset::EvenHorizontal,
+        Preset::EvenVertical,
+        Preset::MainHorizontal,
+        Preset::MainHorizontalMirrored,
+        Preset::MainVertical,
+        Preset::MainVerticalMirrored,
+        Preset::Tiled,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Preset::EvenHorizontal => "even-horizontal",
+            Preset::EvenVertical => "even-vertical",
+            Preset::MainHorizontal => "main-horizontal",
+            Preset::MainHorizontalMirrored => "main-horizontal-mirrored",
+            Preset::MainVertical => "main-vertical",
+            Preset::MainVerticalMirrored => "main-vertical-mirrored",
+            Preset::Tiled => "tiled",
+        }
+    }
+}
+
+impl FromStr for Preset {
+    type Err = anyhow::Error;
+
+    fn from_str(preset: &str) -> Result<Preset> {
+        Preset::ALL
+            .into_iter()
+            .find(|p| p.as_str() == preset)
+            .ok_or_else(|| {
+                let names: Vec<_> = Preset::ALL.iter().map(|p| p.as_str()).collect();
+                TmuxError::LayoutInvalid {
+                    layout: preset.to_owned(),
+                    reason: format!("expected one of {}", names.join(", ")),
+                }
+                .into()
+            })
+    }
+}
+
+/// The ways a layout can be written in config or on the command line,
+/// before it's resolved into a `Layout`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutSpec {
+    /// A layout string as printed by `dmux layout`.
+    Checksum(String),
+    Preset(Preset),
+    /// `auto`, or `auto:<preset>` to choose the preset but keep the main
+    /// pane sizing. See `Layout::auto`.
+    Auto {
+        panes: u8,
+        preset: Option<Preset>,
+    },
+}
+
+impl LayoutSpec {
+    /// Reads a layout for a window of `panes` panes, which only `auto`
+    /// needs.
+    pub fn parse(layout: &str, panes: u8) -> Result<LayoutSpec> {
+        let trimmed = layout.trim();
+        Ok(match trimmed.split_once(':') {
+            _ if trimmed == "auto" => LayoutSpec::Auto {
+                panes,
+                preset: None,
+            },
+            Some(("auto", preset)) => LayoutSpec::Auto {
+                panes,
+                preset: Some(preset.parse()?),
+            },
+            _ => match trimmed.parse() {
+                Ok(preset) => LayoutSpec::Preset(preset),
+                Err(_) => LayoutSpec::Checksum(layout.to_owned()),
+            },
+        })
+    }
+}
+
+impl TryFrom<LayoutSpec> for Layout {
+    type Error = anyhow::Error;
+
+    fn try_from(spec: LayoutSpec) -> Result<Layout> {
+        match spec {
+            LayoutSpec::Checksum(checksum) => Layout::from_checksum(&checksum),
+            LayoutSpec::Preset(preset) => Ok(Layout {
+                layout: preset.as_str().to_owned(),
+                main_pane_percent: None,
+            }),
+            LayoutSpec::Auto { panes, preset } => Ok(match preset {
+                Some(preset) => Layout::auto(panes).with_preset(preset),
+                None => Layout::auto(panes),
+            }),
+        }
+    }
+}
+
+// how much of the window the main pane of an automatic layout takes up
+const AUTO_MAIN_PANE_PERCENT: u8 = 60;
+
+/// A window layout, either one of tmux's presets or a checksum like
+/// `34ed,230x56,0,0{132x56,0,0,3,97x56,133,0,222}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    layout: String,
+    // percent of the window the main pane of a main-* preset gets
+    main_pane_percent: Option<u8>,
+}
+
+impl Layout {
+    /// A preset that looks reasonable for this many panes without a
+    /// checksum generated for them: `main-vertical` up to 3 panes, with the
+    /// main pane taking 60% of the width, and `tiled` past that.
+    pub fn auto(pane_count: u8) -> Layout {
+        let layout = if pane_count <= 3 {
+            "main-vertical"
+        } else {
+            "tiled"
+        };
+        Layout {
+            layout: layout.to_owned(),
+            main_pane_percent: Some(AUTO_MAIN_PANE_PERCENT),
+        }
+    }
+
+    /// Uses `preset` instead of the preset picked by `auto`, keeping the
+    /// main pane size for the main-* presets.
+    pub fn with_preset(self, preset: Preset) -> Layout {
+        Layout {
+            layout: preset.as_str().to_owned(),
+            ..self
+        }
+    }
+
+    /// The window option sizing the main pane, `main-pane-width` or
+    /// `main-pane-height` depending on which way the preset splits.
+    /// Older tmux versions only take a number of cells here.
+    pub fn main_pane_option(&self) -> Option<(&'static str, String)> {
+        let percent = format!("{}%", self.main_pane_percent?);
+        if self.layout.starts_with("main-vertical") {
+            Some(("main-pane-width", percent))
+        } else if self.layout.starts_with("main-horizontal") {
+            Some(("main-pane-height", percent))
+        } else {
+            None
+        }
+    }
+
+    /// Checks a layout before it gets anywhere near `select-layout`, which
+    /// only says "invalid layout". A `1 ` active flag left over from copying
+    /// a line of `list-windows` output is stripped.
+    pub fn from_checksum(layout: &str) -> Result<Layout> {
+        let trimmed = layout.trim();
+        let trimmed = trimmed
+            .strip_prefix("1 ")
+            .or_else(|| trimmed.strip_prefix("0 "))
+            .unwrap_or(trimmed)
+            .trim_start();
+        if let Ok(preset) = trimmed.parse() {
+            return Layout::try_from(LayoutSpec::Preset(preset));
+        }
+
+        let invalid = |reason: String| TmuxError::LayoutInvalid {
+            layout: layout.to_owned(),
+            reason,
+        };
+        let (checksum, body) = trimmed.split_once(',').ok_or_else(|| {
+            invalid("expected a preset or a checksum like `34ed,230x56,0,0{...}`".to_owned())
+        })?;
+        if checksum.len() != 4 || u16::from_str_radix(checksum, 16).is_err() {
+            return Err(invalid(format!(
+                "{:?} isn't a 4 digit hex checksum, it may have been cut off",
+                checksum
+            ))
+            .into());
+        }
+        LayoutCell::parse(body).map_err(invalid)?;
+        let expected = format!("{:04x}", layout_checksum(body));
+        if checksum != expected {
+            return Err(invalid(format!(
+                "the checksum should be {} for this layout, it may have been edited",
+                expected
+            ))
+            .into());
+        }
+        Ok(Layout {
+            layout: trimmed.to_owned(),
+            main_pane_percent: None,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.layout
+    }
+
+    /// How many panes a checksum lays out. Presets fit any number of panes,
+    /// so they don't have one.
+    pub fn pane_count(&self) -> Option<usize> {
+        self.tree().map(|tree| tree.pane_count())
+    }
+
+    /// The cells a checksum divides the window into. Presets are worked out
+    /// by tmux for whatever size the window is, so they don't have any.
+    pub fn tree(&self) -> Option<LayoutCell> {
+        let (_, body) = self.layout.split_once(',')?;
+        LayoutCell::parse(body).ok()
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.layout)
+    }
+}
+
+/// What a cell of a layout holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CellContent {
+    /// A pane, with the id it had when the layout was generated. Layouts
+    /// from tmux before 1.8 leave the id out.
+    Pane(Option<u32>),
+    /// Cells side by side, written `{...}`, or stacked, written `[...]`.
+    Split(SplitDirection, Vec<LayoutCell>),
+}
+
+/// A rectangle of a window from a layout checksum: its size and offset in
+/// cells, and either the pane in it or the cells it's split into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutCell {
+    pub width: usize,
+    pub height: usize,
+    pub x: usize,
+    pub y: usize,
+    pub content: CellContent,
+}
+
+impl LayoutCell {
+    // Reads everything after a checksum's first comma, like
+    // `230x56,0,0{132x56,0,0,3,97x56,133,0,222}`.
+    fn parse(body: &str) -> std::result::Result<LayoutCell, String> {
+        let mut rest = body;
+        let cell = parse_cell(&mut rest)?;
+        if !rest.is_empty() {
+            return Err(format!("unexpected {:?} after the layout", rest));
+        }
+        Ok(cell)
+    }
+
+    pub fn pane_count(&self) -> usize {
+        match &self.content {
+            CellContent::Pane(_) => 1,
+            CellContent::Split(_, cells) => cells.iter().map(LayoutCell::pane_count).sum(),
+        }
+    }
+
+    /// The cells holding panes, in the order tmux numbers the panes.
+    pub fn panes(&self) -> Vec<&LayoutCell> {
+        match &self.content {
+            CellContent::Pane(_) => vec![self],
+            CellContent::Split(_, cells) => cells.iter().flat_map(LayoutCell::panes).collect(),
+        }
+    }
+}
+
+// `WxH,X,Y` followed by `,ID` for a pane or by the cells it's split into
+fn parse_cell(rest: &mut &str) -> std::result::Result<LayoutCell, String> {
+    let at = *rest;
+    let size = |rest: &mut &str| -> Option<(usize, usize, usize, usize)> {
+        let width = parse_number(rest)?;
+        *rest = rest.strip_prefix('x')?;
+        let height = parse_number(rest)?;
+        *rest = rest.strip_prefix(',')?;
+        let x = parse_number(rest)?;
+        *rest = rest.strip_prefix(',')?;
+        Some((width, height, x, parse_number(rest)?))
+    };
+    let (width, height, x, y) =
+        size(rest).ok_or_else(|| format!("expected WIDTHxHEIGHT,X,Y at {:?}", at))?;
+
+    let content = match rest.chars().next() {
+        Some(open @ ('{' | '[')) => {
+            let (split, close) = if open == '{' {
+                (SplitDirection::Horizontal, '}')
+            } else {
+                (SplitDirection::Vertical, ']')
+            };
+            *rest = &rest[1..];
+            let mut cells = vec![parse_cell(rest)?];
+            loop {
+                match rest.chars().next() {
+                    Some(',') => {
+                        *rest = &rest[1..];
+                        cells.push(parse_cell(rest)?);
+                    }
+                    Some(c) if c == close => {
+                        *rest = &rest[1..];
+                        break;
+                    }
+                    _ => return Err(format!("expected {:?} to close {:?}", close, at)),
+                }
+            }
+            CellContent::Split(split, cells)
+        }
+        // a comma starts either this pane's id or the next cell's size,
+        // which has an `x` after its first number
+        Some(',') => {
+            let mut after = &rest[1..];
+            match parse_number(&mut after) {
+                Some(id) if !after.starts_with('x') => {
+                    *rest = after;
+                    CellContent::Pane(Some(id as u32))
+                }
+                _ => CellContent::Pane(None),
+            }
+        }
+        _ => CellContent::Pane(None),
+    };
+    Ok(LayoutCell {
+        width,
+        height,
+        x,
+        y,
+        content,
+    })
+}
+
+/// A layout written as the splits that make it, which unlike a checksum can
+/// be written by hand and fits any window size. A tree without `split` is
+/// a single pane.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SplitTree {
+    /// Which way the cell is divided between `children`.
+    pub split: Option<SplitDirection>,
+    /// Percent of the parent cell this one takes. Children without a size
+    /// share what their siblings leave evenly.
+    pub size: Option<u8>,
+    pub children: Vec<SplitTree>,
+}
+
+/// One `split-window` of a `SplitTree`. Panes are numbered in the order they
+/// end up in, which is the order tmux gives them indexes in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlannedSplit {
+    /// The pane that's split.
+    pub target: usize,
+    /// The pane the split makes, right of or below `target`.
+    pub pane: usize,
+    pub direction: SplitDirection,
+    /// How much of `target` goes to the new pane, like `split-window -p`.
+    pub percent: u8,
+}
+
+impl SplitTree {
+    /// A main pane on the left with the others stacked on its right, like
+    /// tmux's `main-vertical`, for `count` panes.
+    pub fn main_vertical(count: usize) -> SplitTree {
+        let pane = SplitTree::default();
+        let rest = match count {
+            0 | 1 => return pane,
+            2 => pane.clone(),
+            _ => SplitTree {
+                split: Some(SplitDirection::Vertical),
+                children: vec![pane.clone(); count - 1],
+                ..Default::default()
+            },
+        };
+        SplitTree {
+            split: Some(SplitDirection::Horizontal),
+            children: vec![pane, rest],
+            ..Default::default()
+        }
+    }
+
+    pub fn pane_count(&self) -> usize {
+        if self.children.is_empty() {
+            1
+        } else {
+            self.children.iter().map(SplitTree::pane_count).sum()
+        }
+    }
+
+    /// Checks that every split has a direction and children to split
+    /// between, and that sizes fit in their parent.
+    pub fn validate(&self) -> Result<()> {
+        match (self.split, self.children.len()) {
+            (None, 0) => return Ok(()),
+            (None, _) => return Err(anyhow!("cells with children need a split direction")),
+            (Some(_), 0 | 1) => return Err(anyhow!("a split needs at least two children")),
+            (Some(_), _) => {}
+        }
+        if let Some(size) = self
+            .children
+            .iter()
+            .filter_map(|c| c.size)
+            .find(|s| !(1..100).contains(s))
+        {
+            return Err(anyhow!(
+                "a split size of {}% leaves no room for its siblings",
+                size
+            ));
+        }
+        let sized: u32 = self
+            .children
+            .iter()
+            .filter_map(|c| c.size)
+            .map(u32::from)
+            .sum();
+        let unsized_children = self.children.iter().any(|c| c.size.is_none());
+        if sized > 100 || (unsized_children && sized >= 100) {
+            return Err(anyhow!(
+                "the sizes of a split add up to {}%, more than fits",
+                sized
+            ));
+        }
+        self.children.iter().try_for_each(SplitTree::validate)
+    }
+
+    /// The splits that make this layout out of one pane, in the order to
+    /// run them. Each cell is split between its children before they're
+    /// split in turn, so the sizes are of whole cells.
+    pub fn plan(&self) -> Vec<PlannedSplit> {
+        let mut steps = vec![];
+        self.plan_from(0, &mut steps);
+        steps
+    }
+
+    // `first` is the pane holding this cell, which stays the first of its
+    // panes since splits put the new pane after the split one
+    fn plan_from(&self, first: usize, steps: &mut Vec<PlannedSplit>) {
+        let Some(direction) = self.split else {
+            return;
+        };
+        let sized: u32 = self
+            .children
+            .iter()
+            .filter_map(|c| c.size)
+            .map(u32::from)
+            .sum();
+        let unsized_children = self.children.iter().filter(|c| c.size.is_none()).count();
+        let share = 100u32.saturating_sub(sized) as f64 / unsized_children.max(1) as f64;
+        let weights: Vec<f64> = self
+            .children
+            .iter()
+            .map(|c| c.size.map_or(share, f64::from))
+            .collect();
+
+        let mut firsts = vec![];
+        let mut next = first;
+        for child in &self.children {
+            firsts.push(next);
+            next += child.pane_count();
+        }
+        // the pane holding the children from k - 1 on gives the ones from
+        // k on their share of it
+        for k in 1..self.children.len() {
+            let rest: f64 = weights[k..].iter().sum();
+            let percent = (100.0 * rest / (rest + weights[k - 1])).round();
+            steps.push(PlannedSplit {
+                target: firsts[k - 1],
+                pane: firsts[k],
+                direction,
+                percent: percent.clamp(1.0, 99.0) as u8,
+            });
+        }
+        for (child, first) in self.children.iter().zip(firsts) {
+            child.plan_from(first, steps);
+        }
+    }
+}
+
+impl FromStr for SplitDirection {
+    type Err = anyhow::Error;
+
+    fn from_str(direction: &str) -> Result<SplitDirection> {
+        match direction {
+            "horizontal" => Ok(SplitDirection::Horizontal),
+            "vertical" => Ok(SplitDirection::Vertical),
+            _ => Err(anyhow!(
+                "{:?} isn't a split direction, expected horizontal or vertical",
+                direction
+            )),
+        }
+    }
+}
+
+fn parse_number(rest: &mut &str) -> Option<usize> {
+    let digits = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let number = rest[..digits].parse().ok()?;
+    *rest = &rest[digits..];
+    Some(number)
+}
+
+// tmux's own checksum over everything after the first comma
+fn layout_checksum(body: &str) -> u16 {
+    body.bytes().fold(0u16, |checksum, byte| {
+        ((checksum >> 1) | ((checksum & 1) << 15)).wrapping_add(byte as u16)
+    })
+}
+
+// The width and height a layout checksum was generated for. Presets like
+// `even-horizontal` don't have one.
+fn layout_size(layout: &str) -> Option<(usize, usize)> {
+    let (width, height) = layout.split(',').nth(1)?.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+pub(crate) fn warn(message: String) {
+    eprintln!("{}: {}", "Warning".yellow(), message);
+}
+
+// The terminal's foreground process group from a `/proc/<pid>/stat` line.
+// The command name can hold spaces and parens, so fields are counted from
+// its closing paren.
+#[cfg(unix)]
+fn foreground_group(stat: &str) -> Option<libc::pid_t> {
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields
+        .split_whitespace()
+        .nth(5)?
+        .parse()
+        .ok()
+        .filter(|group| *group > 0)
+}
+
+// attaching blocks until the client detaches, so it's the one call that
+// isn't held to the timeout, and `ready` is called before it
+fn attach_to_window(
+    workspace: &WorkSpace,
+    window: &Window,
+    tmux: &Tmux,
+    observer: &mut dyn FnMut(SetupEvent),
+    ready: &mut dyn FnMut() -> Result<()>,
+) -> Result<String> {
+    let own = workspace.window_target(window);
+    if let Some(size) = workspace.window_size {
+        check_window_size(size, &tmux.run(&window_size_command(size, &own, tmux)).ok());
+    }
+    let target = match workspace.other_active_window() {
+        Some(name) => tmux
+            .find_windows(&workspace.session_name, name)?
+            .first()
+            .map(Window::target)
+            // a dry run's windows were never made
+            .unwrap_or_else(|| format!("{}:{}", workspace.session_name, name)),
+        None => own,
+    };
+    // attaching to a window selects it too, but not for a detached workspace
+    // that's attached to later
+    checked(
+        tmux.run(&select_window_command(&target, tmux))?,
+        format!("Couldn't select window {}", target),
+    )?;
+    ready()?;
+    if workspace.detached {
+        info!("leaving {} detached", target);
+        return Ok(target);
+    }
+    info!("attaching to {}", target);
+    observer(SetupEvent::Attaching {
+        target: target.clone(),
+    });
+    let client = tmux.config.host.is_none()
+        && (in_client(&tmux.run(&server_pid_command(tmux)).ok())
+            || nested_attach(pane_session(tmux).as_deref(), &target));
+    let attach = with_post_attach(
+        attach_command(&target, client, tmux),
+        &workspace.post_attach,
+    );
+    if client {
+        // switching over a control connection would switch the control client
+        checked(
+            tmux.spawn(&attach)?,
+            format!("Couldn't switch to {}", target),
+        )?;
+    } else if tmux.dry_run(&attach).is_none() {
+        checked(
+            TmuxOutput(tmux.process(&attach).output()?),
+            format!("Couldn't attach to {}", target),
+        )?;
+    }
+    Ok(target)
+}
+
+// has-session is all a session or window needs, list-panes makes sure the
+// pane part of a target is resolved as well
+pub(crate) fn target_exists_command<'a>(target: &'a str, tmux: &'a Tmux) -> TmuxCommand<'a> {
+    if target.contains('.') || target.starts_with('%') {
+        let mut list_panes = tmux.command.list_panes();
+        list_panes.target(target).format("");
+        list_panes.0
+    } else {
+        let mut has_session = tmux.command.has_session();
+        has_session.target_session(target);
+        has_session.0
+    }
+}
+
+// One `send-keys` for each run of literal text or of named keys, since
+// literal text needs `-l` to keep tmux from looking up key names in it.
+pub(crate) fn typed_keys_commands<'a>(
+    target: &'a str,
+    keys: &[Key],
+    tmux: &'a Tmux,
+) -> Vec<TmuxCommand<'a>> {
+    keys.chunk_by(|a, b| matches!(a, Key::Literal(_)) == matches!(b, Key::Literal(_)))
+        .map(|run| {
+            let mut send_keys = tmux.command.send_keys();
+            send_keys.target_pane(target);
+            if matches!(run[0], Key::Literal(_)) {
+                send_keys.disable_lookup();
+            }
+            for key in run {
+                send_keys.key(key.to_string());
+            }
+            send_keys.0
+        })
+        .collect()
+}
+
+pub(crate) fn window_size_command<'a>(
+    size: WindowSize,
+    target: &'a str,
+    tmux: &'a Tmux,
+) -> TmuxCommand<'a> {
+    set_option_command(Scope::Window, "window-size", size.as_str(), target, tmux)
+}
+
+// an old tmux shouldn't keep the workspace from opening
+pub(crate) fn check_window_size(size: WindowSize, output: &Option<TmuxOutput>) {
+    if !output.as_ref().is_some_and(|o| o.success()) {
+        warn(format!(
+            "Couldn't set window-size to {}, it needs tmux 2.9 or later (3.1 for latest)",
+            size.as_str()
+        ));
+    }
+}
+
+// The pane border options the workspace asks for. They're kept out of
+// `options` so a tmux that's too old for them only gets a warning.
+pub(crate) fn border_options(workspace: &WorkSpace) -> Vec<(&'static str, &str)> {
+    let status = workspace
+        .pane_border_status
+        .map(|status| ("pane-border-status", status.as_str()));
+    let format = workspace
+        .pane_border_format
+        .as_deref()
+        .map(|format| ("pane-border-format", format));
+    status.into_iter().chain(format).collect()
+}
+
+pub(crate) fn check_border_option(name: &str, output: &Option<TmuxOutput>) {
+    if !output.as_ref().is_some_and(|o| o.success()) {
+        warn(format!("Couldn't set {}, it needs tmux 2.3 or later", name));
+    }
+}
+
+pub(crate) fn server_pid_command(tmux: &Tmux) -> TmuxCommand<'_> {
+    tmux.command.list_sessions().format("#{pid}").0.to_owned()
+}
+
+// Whether dmux runs in a client of the server that's up now, which
+// switch-client needs. $TMUX names the server by socket and pid, and it
+// outlives a server that dies under the shell. tmux follows $TMUX's socket,
+// so creating the workspace starts a new server on it, and only the pid
+// tells that server apart from the one this client belonged to.
+pub(crate) fn in_client(server_pid: &Option<TmuxOutput>) -> bool {
+    std::env::var("TMUX").is_ok_and(|env| same_server(&env, server_pid))
+}
+
+fn same_server(tmux_env: &str, server_pid: &Option<TmuxOutput>) -> bool {
+    let running = match server_pid {
+        Some(output) if output.success() => output.to_string(),
+        _ => return false,
+    };
+    tmux_env.split(',').nth(1) == running.lines().next()
+}
+
+// Whether `target` is in the session dmux is running in, even though it
+// didn't seem to be in a client. tmux refuses to attach a session inside
+// itself, so this switches the client over instead.
+pub(crate) fn nested_attach(current: Option<&str>, target: &str) -> bool {
+    let nested = current.is_some_and(|current| target.split(':').next() == Some(current));
+    if nested {
+        warn!(
+            "{} is in the session dmux is running in, switching to it instead of attaching",
+            target
+        );
+    }
+    nested
+}
+
+pub(crate) fn select_window_command<'a>(target: &str, tmux: &'a Tmux) -> TmuxCommand<'a> {
+    tmux.command
+        .select_window()
+        .target_window(target.to_owned())
+        .0
+        .to_owned()
+}
+
+pub(crate) fn attach_command<'a>(target: &str, client: bool, tmux: &'a Tmux) -> TmuxCommand<'a> {
+    if client {
+        // switch to the window which exists
+        tmux.command
+            .switch_client()
+            .target_session(target.to_owned())
+            .0
+            .to_owned()
+    } else {
+        // attach to the window in the session
+        tmux.command
+            .attach_session()
+            .target_session(target.to_owned())
+            .0
+            .to_owned()
+    }
+}
+
+// Chains the commands onto the attach, so they run in the attached client
+// and only once attaching has worked. `if-shell -F 1` is there to have tmux
+// parse each command the way it would in `tmux.conf`.
+pub(crate) fn with_post_attach<'a>(
+    mut attach: TmuxCommand<'a>,
+    commands: &'a [String],
+) -> TmuxCommand<'a> {
+    let args = attach.cmd_args.get_or_insert_with(Vec::new);
+    for command in commands {
+        args.extend([";", "if-shell", "-F", "1"].map(Cow::from));
+        args.push(command.as_str().into());
+    }
+    attach
+}
+
+fn generate_layout(tmux: &Tmux) -> Result<String> {
+    let stdout = tmux
+        .run(
+            &tmux
+                .command
+                .list_windows()
+                .format("#{window_active} #{window_layout}")
+                .0,
+        )?
+        .0
+        .stdout;
+
+    let layout = match std::str::from_utf8(&stdout)?
+        .split('\n')
+        .find(|l| l.starts_with('1'))
+    {
+        Some(layout) => Ok(layout),
+        None => Err(anyhow!("Uh-oh, looks like you're not in a tmux session!")),
+    }?;
+
+    layout
+        .split_whitespace()
+        .last()
+        .map(str::to_owned)
+        .ok_or_else(|| anyhow!("layout invalid"))
+}
+
+/// How tmux sizes a window that several clients of different sizes are
+/// looking at, the `window-size` option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowSize {
+    /// The size of the client that was used last, so attaching from a big
+    /// terminal brings the window up at full size. Needs tmux 3.1.
+    Latest,
+    /// The size of the largest attached client. Needs tmux 2.9.
+    Largest,
+    /// The size of the smallest attached client, what tmux did before 2.9.
+    Smallest,
+}
+
+/// Where tmux shows a line in each pane's border with its title, the
+/// `pane-border-status` option. Needs tmux 2.3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BorderStatus {
+    Off,
+    Top,
+    Bottom,
+}
+
+impl BorderStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BorderStatus::Off => "off",
+            BorderStatus::Top => "top",
+            BorderStatus::Bottom => "bottom",
+        }
+    }
+}
+
+impl FromStr for BorderStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(status: &str) -> Result<BorderStatus> {
+        match status {
+            "off" => Ok(BorderStatus::Off),
+            "top" => Ok(BorderStatus::Top),
+            "bottom" => Ok(BorderStatus::Bottom),
+            _ => Err(anyhow!(
+                "Unknown pane border status {:?}, expected off, top or bottom",
+                status
+            )),
+        }
+    }
+}
+
+impl WindowSize {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WindowSize::Latest => "latest",
+            WindowSize::Largest => "largest",
+            WindowSize::Smallest => "smallest",
+        }
+    }
+}
+
+impl FromStr for WindowSize {
+    type Err = anyhow::Error;
+
+    fn from_str(size: &str) -> Result<WindowSize> {
+        match size {
+            "latest" => Ok(WindowSize::Latest),
+            "largest" => Ok(WindowSize::Largest),
+            "smallest" => Ok(WindowSize::Smallest),
+            _ => Err(anyhow!(
+                "Unknown window size {:?}, expected latest, largest or smallest",
+                size
+            )),
+        }
+    }
+}
+
+/// Where an option set by a workspace applies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Every session, like `set-option -g`.
+    Global,
+    Session,
+    /// Just the workspace's window, like `set-option -w`.
+    Window,
+}
+
+impl FromStr for Scope {
+    type Err = anyhow::Error;
+
+    fn from_str(scope: &str) -> Result<Scope> {
+        match scope {
+            "global" => Ok(Scope::Global),
+            "session" => Ok(Scope::Session),
+            "window" => Ok(Scope::Window),
+            _ => Err(anyhow!(
+                "Unknown option scope {:?}, expected global, session or window",
+                scope
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WorkSpace {
+    pub path: PathBuf,
+    pub session_name: String,
+    /// Names the session instead of `session_name`, with the same
+    /// placeholders as `commands` filled in, like `{{git_repo}}-{{git_branch}}`.
+    /// It's filled in before the name is cleaned up for tmux, so a `.` in a
+    /// directory's name still becomes `_`.
+    pub session_name_template: Option<String>,
+    pub format_checksum: String,
+    /// What to run in each pane, in order. An entry like `file:boot.sh`
+    /// sends each line of that script instead, with the path relative to
+    /// the workspace's directory. `{{dir}}`, `{{dir_name}}`,
+    /// `{{parent_dir}}`, `{{session}}`, `{{window}}`, `{{git_repo}}` and
+    /// `{{git_branch}}` are filled in when it's normalized.
+    pub commands: Vec<String>,
+    pub window_name: Option<String>,
+    pub number_of_panes: u8,
+    /// Keep the window a new session starts with as a scratch shell
+    /// instead of turning it into the workspace's window.
+    pub keep_initial_window: bool,
+    /// Targets the window at this index in the session rather than looking
+    /// it up by name, for when the name is ambiguous or gets renamed.
+    pub window_index: Option<usize>,
+    /// tmux options to set, like `(Scope::Window, "pane-border-status", "top")`.
+    /// Global and session options are set when the session is created, and
+    /// window options once the window exists, before any commands run.
+    pub options: Vec<(Scope, String, String)>,
+    /// Sets the window's `window-size` right before attaching, so a smaller
+    /// client that's still attached doesn't leave the window cramped.
+    pub window_size: Option<WindowSize>,
+    /// After sending the commands, checks that each one shows up in its pane
+    /// and warns about the ones that don't, which usually means the shell
+    /// wasn't ready yet. It costs a capture per pane, and waits up to a couple
+    /// of seconds for shells that are slow to start, like login shells.
+    pub verify_commands: bool,
+    /// Before sending the commands, waits up to this long for each pane to
+    /// show its shell's prompt, for shells that take a while to start, like
+    /// ones loading nvm or oh-my-zsh, and can lose what's typed before
+    /// they're ready. Anything the shell prints first counts as its prompt.
+    /// A pane that stays blank is warned about and gets its command anyway.
+    pub wait_for_prompt: Option<Duration>,
+    /// Starts the workspace's panes in a login shell, so profile files have
+    /// set up `PATH` and friends before the commands run. Login shells are
+    /// slower to start, but commands sent early wait in the pane's input
+    /// until the shell reads them. A scratch initial window is left alone.
+    pub login_shell: bool,
+    /// Kills a session that's already open under `session_name` and starts
+    /// it over. When dmux is run from inside that session, the client is
+    /// switched over to the new session before the old one is killed.
+    pub fresh: bool,
+    /// Sends the commands to panes by where they are on screen rather than
+    /// by pane index, so the first command goes to the top left pane, the
+    /// next to the one right of it, and so on row by row.
+    pub commands_by_position: bool,
+    /// tmux commands, written as they would be in `tmux.conf`, run once the
+    /// client is on the workspace's window, like `resize-window -A`. They
+    /// run in order, after everything else dmux does, and only when
+    /// attaching or switching worked.
+    pub post_attach: Vec<String>,
+    /// When the workspace's windows are already open, brings them in line
+    /// with it rather than only switching to them: panes they're missing are
+    /// split off and get their commands, and the layout is applied again.
+    /// Panes they have on top of the workspace's are left alone.
+    pub reapply: bool,
+    /// With `reapply`, also sends the commands again to the panes sitting at
+    /// a shell prompt, so a server that was stopped starts up again while a
+    /// running one is left to it.
+    pub reapply_commands: bool,
+    /// When the session is already open, closes the gaps in its window
+    /// indexes that killed windows left before the workspace's windows are
+    /// looked for in it, so `select-window -t :N` goes by their order.
+    pub renumber_windows: bool,
+    /// When the workspace's window is already open and it's the window dmux
+    /// is run from, sends the commands to its panes again instead of only
+    /// switching to it. Nothing is split and the layout is left alone. The
+    /// pane dmux runs in gets its command once dmux exits.
+    pub force_commands: bool,
+    /// Sets everything up without attaching or switching to it, for
+    /// preparing sessions from scripts or where there's no terminal.
+    /// `post_attach` commands aren't run.
+    pub detached: bool,
+    /// Its directories are on the machine tmux runs on rather than this one,
+    /// like with `TmuxConfig::host`, so they aren't looked for up front and
+    /// `file:` scripts can't be read.
+    pub remote: bool,
+    /// Lets a command span several lines, each sent to the pane on its own
+    /// as if it were a script. Otherwise a command with a line break in it
+    /// is refused, rather than having the shell run some of it early.
+    pub multiline_commands: bool,
+    /// Flags the window when there's output in it, like a server that's
+    /// finished starting up.
+    pub monitor_activity: bool,
+    /// Turns on `synchronize-panes` for the window once its commands are
+    /// sent, so what's typed in one pane goes to all of them, like panes that
+    /// are each logged in to another host. It's off again while dmux sends
+    /// the commands, so each pane still gets only its own.
+    pub synchronize: bool,
+    /// Flags the window once it's been quiet for this many seconds, like a
+    /// long build that's done.
+    pub monitor_silence: Option<u32>,
+    /// Shows a line with each pane's title in its border, so a window with
+    /// several panes reads like a labeled layout. Only warns when tmux is
+    /// too old to have the option.
+    pub pane_border_status: Option<BorderStatus>,
+    /// What the border line shows, like `#{pane_index}: #{pane_title}`.
+    pub pane_border_format: Option<String>,
+    /// More windows to open in the session after the workspace's own one,
+    /// each set up the same way. One that's already open is left alone, and
+    /// the client ends up on `active_window`.
+    pub windows: Vec<WindowSpec>,
+    /// The window selected before attaching, by name: the workspace's own
+    /// window or one of `windows`. The workspace's own window, the first one
+    /// defined, when it's not given.
+    pub active_window: Option<String>,
+    /// How each pane differs from the rest, in the order the panes are
+    /// created, which is the order of their indexes. Panes past the end of
+    /// it start out like any other.
+    pub panes: Vec<PaneSpec>,
+    /// Environment variables, like `("RUST_LOG", "debug")`, set in every
+    /// pane of the workspace before its command runs. tmux sets them for
+    /// the pane's shell, which needs tmux 3.0 or later, or 3.2 for the first
+    /// pane of a new session.
+    pub env: Vec<(String, String)>,
+    /// The window's panes as the splits that make them, used instead of
+    /// `format_checksum`. The tree decides how many panes there are.
+    pub splits: Option<SplitTree>,
+}
+
+/// One of a workspace's panes, where it's set up differently from the rest.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PaneSpec {
+    /// Where the pane starts, relative to the workspace's directory, so the
+    /// panes of a monorepo can each start in their own project.
+    pub dir: Option<PathBuf>,
+    /// Environment variables for this pane alone, set after the workspace's
+    /// own so they take their place.
+    pub env: Vec<(String, String)>,
+    /// The pane's title, like `editor`, shown in its border with
+    /// `WorkSpace::pane_border_status` instead of the host's name. A shell
+    /// that sets the terminal's title from its prompt takes it over again.
+    pub title: Option<String>,
+}
+
+/// Another window for a workspace to open in its session.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WindowSpec {
+    pub name: String,
+    /// Where the window's panes start, relative to the workspace's
+    /// directory. The workspace's directory when it's not given.
+    pub dir: Option<PathBuf>,
+    /// A layout written like the workspace's `format_checksum`, which is
+    /// used when it's not given.
+    pub layout: Option<String>,
+    pub commands: Vec<String>,
+    pub number_of_panes: u8,
+}
+
+/// Expands a leading `~` and any `$VAR` or `${VAR}` components of a path.
+/// Variables that aren't set are left as they are.
+pub fn expand_path(path: &Path) -> PathBuf {
+    path.components()
+        .enumerate()
+        .map(|(i, component)| {
+            let part = component.as_os_str();
+            match part.to_str() {
+                Some("~") if i == 0 => dirs::home_dir().unwrap_or_else(|| PathBuf::from(part)),
+                Some(var) if var.starts_with('$') => {
+                    let name = var[1..].trim_start_matches('{').trim_end_matches('}');
+                    match std::env::var(name) {
+                        // an absolute value would otherwise replace everything before it
+                        Ok(value) if i > 0 => PathBuf::from(value.trim_start_matches('/')),
+                        Ok(value) => PathBuf::from(value),
+                        Err(_) => PathBuf::from(part),
+                    }
+                }
+                _ => PathBuf::from(part),
+            }
+        })
+        .collect()
+}
+
+// tmux won't have `.` or `:` in a session name since they separate the
+// parts of a target
+fn tmux_session_name(name: &str) -> String {
+    name.replace(['.', ':'], "_")
+}
+
+/// A session or window name cleaned up the way dmux does it, for building
+/// targets that match what dmux opens.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TargetName(String);
+
+impl TargetName {
+    /// A session name as tmux will have it, with the `.` and `:` that
+    /// separate the parts of a target replaced by `_`.
+    pub fn session(name: &str) -> TargetName {
+        TargetName(tmux_session_name(name))
+    }
+
+    /// A window name the way dmux names a window after its directory, with
+    /// `.` and spaces replaced by `-`. Window names given in a workspace are
+    /// used as they are.
+    pub fn window(name: &str) -> TargetName {
+        TargetName(clean_str(name))
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl fmt::Display for TargetName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for TargetName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+pub(crate) fn pickable_target(window: &Window) -> String {
+    format!("{}:{}", window.session_name, clean_str(&window.name))
+}
+
+// The branch checked out in `dir`, empty when it isn't in a git repository
+// or the HEAD is detached
+fn git_branch(dir: &Path) -> String {
+    git_line(dir, &["symbolic-ref", "--quiet", "--short", "HEAD"]).unwrap_or_else(|| {
+        warn(format!(
+            "{{{{git_branch}}}} is empty since {} has no branch checked out",
+            dir.display()
+        ));
+        String::new()
+    })
+}
+
+// the name of the repository's top directory, which for a worktree is the
+// worktree's own
+fn git_repo(dir: &Path) -> String {
+    git_line(dir, &["rev-parse", "--show-toplevel"])
+        .and_then(|top| Some(Path::new(&top).file_name()?.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| {
+            warn(format!(
+                "{{{{git_repo}}}} is empty since {} isn't in a git repository",
+                dir.display()
+            ));
+            String::new()
+        })
+}
+
+// What git prints for `args` run in `dir`, or None when it fails
+fn git_line(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+fn clean_str(string: &str) -> String {
+    string.replace(".", "-").replace(" ", "-")
+}
+
+impl WorkSpace {
+    // Normalizes the layout so a bad one fails before anything is created,
+    // and the session name the way tmux will, so lookups by name find it.
+    pub(crate) fn normalized(mut self) -> Result<WorkSpace> {
+        self.window_workspaces()?;
+        if let Some(active) = &self.active_window {
+            if *active != self.window_name() && !self.windows.iter().any(|w| w.name == *active) {
+                return Err(anyhow!(
+                    "active_window {} isn't one of the workspace's windows",
+                    active
+                ));
+            }
+        }
+        let layout = Layout::try_from(LayoutSpec::parse(
+            &self.format_checksum,
+            self.number_of_panes(),
+        )?)?;
+        if let Some((name, value)) = layout.main_pane_option() {
+            self.options.push((Scope::Window, name.to_owned(), value));
+        }
+        // a checksum only applies to as many panes as it lays out, so it's
+        // enough on its own to say how many to open
+        if let Some(count) = layout.pane_count() {
+            self.number_of_panes = max(self.number_of_panes, count.min(u8::MAX.into()) as u8);
+        }
+        if let Some(splits) = &self.splits {
+            splits.validate()?;
+            let count = splits.pane_count();
+            if self.commands.len() > count {
+                return Err(anyhow!(
+                    "splits lay out {} panes, too few for {} commands",
+                    count,
+                    self.commands.len()
+                ));
+            }
+            self.number_of_panes = count.min(u8::MAX.into()) as u8;
+        }
+        if self.monitor_activity {
+            let option = (
+                Scope::Window,
+                "monitor-activity".to_owned(),
+                "on".to_owned(),
+            );
+            self.options.push(option);
+        }
+        if let Some(seconds) = self.monitor_silence {
+            let option = (
+                Scope::Window,
+                "monitor-silence".to_owned(),
+                seconds.to_string(),
+            );
+            self.options.push(option);
+        }
+        // scripts, commands and pane directories are checked up front so a
+        // typo doesn't leave a half set up window behind
+        let dir = self.dir();
+        let panes_env = self.panes.iter().flat_map(|pane| &pane.env);
+        if let Some((name, _)) = self
+            .env
+            .iter()
+            .chain(panes_env)
+            .find(|(name, _)| name.is_empty() || name.contains('='))
+        {
+            return Err(anyhow!("{:?} isn't an environment variable name", name));
+        }
+        for nth in 0..self.panes.len() as u8 {
+            let pane_dir = self.pane_dir(nth);
+            if !self.remote && !Path::new(&pane_dir).is_dir() {
+                return Err(anyhow!(
+                    "Couldn't find the directory {} for pane {}",
+                    pane_dir,
+                    nth
+                ));
+            }
+        }
+        if let Some(template) = self.session_name_template.take() {
+            self.session_name = self.expand_placeholders(&template);
+        }
+        self.commands = self
+            .commands
+            .iter()
+            .map(|command| self.expand_placeholders(command))
+            .collect();
+        for command in self.commands.iter_mut() {
+            if let Some(script) = command.strip_prefix(SCRIPT_PREFIX) {
+                if self.remote {
+                    return Err(anyhow!(
+                        "Scripts like {} can't be read from another machine",
+                        command
+                    ));
+                }
+                let script = dir.join(expand_path(Path::new(script)));
+                if !script.is_file() {
+                    return Err(anyhow!("Couldn't find the script {}", script.display()));
+                }
+                *command = format!("{}{}", SCRIPT_PREFIX, script.display());
+            } else {
+                check_command(command, self.multiline_commands)?;
+            }
+        }
+        Ok(WorkSpace {
+            format_checksum: layout.to_string(),
+            session_name: tmux_session_name(&self.session_name),
+            ..self
+        })
+    }
+
+    // Each of `windows` as a workspace of its own in the same session, so it
+    // gets set up the way the workspace's own window does.
+    pub(crate) fn window_workspaces(&self) -> Result<Vec<WorkSpace>> {
+        self.windows
+            .iter()
+            .map(|spec| {
+                WorkSpace {
+                    path: match &spec.dir {
+                        Some(dir) => self.dir().join(expand_path(dir)),
+                        None => self.dir(),
+                    },
+                    window_name: Some(spec.name.clone()),
+                    format_checksum: spec
+                        .layout
+                        .clone()
+                        .unwrap_or_else(|| self.format_checksum.clone()),
+                    commands: spec.commands.clone(),
+                    number_of_panes: spec.number_of_panes,
+                    window_index: None,
+                    windows: vec![],
+                    active_window: None,
+                    panes: vec![],
+                    splits: None,
+                    ..self.clone()
+                }
+                .normalized()
+            })
+            .collect()
+    }
+
+    // tmux can still pick another name than asked for, the one it reports
+    // back is the one to use from here on
+    pub(crate) fn created_session(&mut self, new_session: TmuxOutput) -> Result<()> {
+        let new_session = checked(
+            new_session,
+            format!("Couldn't create session {}", self.session_name),
+        )?;
+        if let Some(name) = new_session.to_string().lines().next() {
+            self.session_name = name.to_owned();
+        }
+        Ok(())
+    }
+
+    // The workspace's window in `session`. When several windows share its
+    // name, the first or the newest one is picked.
+    pub(crate) fn window_in<'a>(&self, session: &'a Session, newest: bool) -> Option<&'a Window> {
+        match self.window_index {
+            Some(index) => session.iter_windows().find(|w| w.index == index),
+            None => {
+                let windows = session.find_windows(&self.window_name());
+                let window = if newest {
+                    windows.last()
+                } else {
+                    windows.first()
+                };
+                window.copied()
+            }
+        }
+    }
+
+    pub(crate) fn options_in<'a>(
+        &'a self,
+        scopes: &'a [Scope],
+    ) -> impl Iterator<Item = &'a (Scope, String, String)> {
+        self.options
+            .iter()
+            .filter(move |(scope, _, _)| scopes.contains(scope))
+    }
+
+    // what created panes run instead of tmux's default-command
+    pub(crate) fn shell_command(&self) -> Option<String> {
+        self.login_shell.then(|| {
+            let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_owned());
+            format!("{} -l", shell)
+        })
+    }
+
+    // where a new window goes, `session:` leaves the index up to tmux
+    pub(crate) fn window_slot(&self) -> String {
+        match self.window_index {
+            Some(index) => format!("{}:{}", self.session_name, index),
+            None => format!("{}:", self.session_name),
+        }
+    }
+
+    // `active_window` when it names one of `windows` rather than the
+    // workspace's own window
+    pub(crate) fn other_active_window(&self) -> Option<&str> {
+        self.active_window
+            .as_deref()
+            .filter(|name| *name != self.window_name())
+    }
+
+    // ids are stable, but a window asked for by index is targeted that way
+    pub(crate) fn window_target(&self, window: &Window) -> String {
+        match self.window_index {
+            Some(index) => format!("{}:{}", self.session_name, index),
+            None => window.target(),
+        }
+    }
+
+    // The session is named the way `normalized` names it when it's created,
+    // so the target finds it whether or not the workspace was normalized
+    pub(crate) fn target_session(&self, pane: Option<u8>) -> String {
+        let session = TargetName::session(&self.session_name);
+        if let Some(pane) = pane {
+            format!("{}:{}.{}", session, self.window_name(), pane)
+        } else {
+            format!("{}:{}", session, self.window_name())
+        }
+    }
+
+    // Fills in the `{{dir}}`, `{{dir_name}}`, `{{parent_dir}}`, `{{session}}`,
+    // `{{window}}`, `{{git_repo}}` and `{{git_branch}}` placeholders in a
+    // command. Anything else in braces is left alone, since tools like
+    // `docker --format` take `{{.Names}}`.
+    fn expand_placeholders(&self, command: &str) -> String {
+        if !command.contains("{{") {
+            return command.to_owned();
+        }
+        let dir = self.dir();
+        let name = |dir: Option<&Path>| {
+            dir.and_then(Path::file_name)
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+        let mut command = command
+            .replace("{{dir}}", &dir.display().to_string())
+            .replace("{{dir_name}}", &name(Some(&dir)))
+            .replace("{{parent_dir}}", &name(dir.parent()))
+            .replace("{{session}}", &tmux_session_name(&self.session_name))
+            .replace("{{window}}", &self.window_name());
+        // git only runs when a command asks for the repo or branch
+        if command.contains("{{git_repo}}") {
+            command = command.replace("{{git_repo}}", &git_repo(&dir));
+        }
+        if command.contains("{{git_branch}}") {
+            command = command.replace("{{git_branch}}", &git_branch(&dir));
+        }
+        command
+    }
+
+    // The layout `normalized` settled on, for select-layout
+    pub(crate) fn layout(&self) -> Result<Layout> {
+        Layout::try_from(LayoutSpec::parse(
+            &self.format_checksum,
+            self.number_of_panes(),
+        )?)
+    }
+
+    pub(crate) fn window_name(&self) -> String {
+        if let Some(name) = &self.window_name {
+            name.to_owned()
+        } else {
+            clean_str(
+                &self
+                    .dir()
+                    .file_name()
+                    .unwrap()
+                    .to_owned()
+                    .into_string()
+                    .unwrap(),
+            )
+        }
+    }
+
+    /// The workspace's directory, which every window and pane starts in.
+    pub fn dir(&self) -> PathBuf {
+        expand_path(&self.path)
+    }
+
+    pub(crate) fn path_str(&self) -> String {
+        self.dir().into_os_string().into_string().unwrap()
+    }
+
+    // the `NAME=value` pairs the `nth` pane is created with, counting from 0
+    pub(crate) fn pane_env(&self, nth: u8) -> Vec<String> {
+        let pane_env = self.panes.get(nth as usize).map(|pane| &pane.env);
+        self.env
+            .iter()
+            .chain(pane_env.into_iter().flatten())
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect()
+    }
+
+    // the titles of the panes that have one, with the pane's place in the
+    // order they're created
+    pub(crate) fn pane_titles(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.panes
+            .iter()
+            .enumerate()
+            .filter_map(|(nth, pane)| Some((nth, pane.title.as_deref()?)))
+    }
+
+    // where the `nth` pane created starts, counting from 0
+    pub(crate) fn pane_dir(&self, nth: u8) -> String {
+        match self
+            .panes
+            .get(nth as usize)
+            .and_then(|pane| pane.dir.as_ref())
+        {
+            Some(dir) => self.dir().join(expand_path(dir)),
+            None => self.dir(),
+        }
+        .into_os_string()
+        .into_string()
+        .unwrap()
+    }
+
+    pub(crate) fn number_of_panes(&self) -> u8 {
+        max(self.commands.len() as u8, self.number_of_panes)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    #[test]
+    fn clean_str_removes_dots_n_stuff() {
+        assert_eq!(clean_str("foo.bar"), "foo-bar")
+    }
+
+    #[test]
+    fn target_names_match_what_dmux_opens() {
+        let workspace = WorkSpace {
+            path: PathBuf::from("/home/zt/my site.v2"),
+            session_name: "work.site:2".to_owned(),
+            format_checksum: "tiled".to_owned(),
+            ..Default::default()
+        };
+        let session = TargetName::session(&workspace.session_name);
+        let window = TargetName::window("my site.v2");
+        assert_eq!(session.as_ref(), "work_site_2");
+        assert_eq!(window.to_string(), workspace.window_name());
+        assert_eq!(
+            format!("{}:{}", session, window),
+            workspace.normalized().unwrap().target_session(None)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dotted_dirs_are_targeted_by_the_session_they_open() {
+        with_server(|tmux, dir| {
+            let site = dir.join("my.site");
+            std::fs::create_dir_all(&site).unwrap();
+            let workspace = WorkSpace {
+                path: site,
+                session_name: "work:my.site".to_owned(),
+                format_checksum: "tiled".to_owned(),
+                number_of_panes: 2,
+                detached: true,
+                ..Default::default()
+            };
+            let target = workspace.target_session(None);
+            assert_eq!(target, "work_my_site:my-site");
+            tmux.setup_workspace(workspace.clone()).unwrap();
+            assert!(tmux.find_session("work_my_site").unwrap().is_some());
+            assert_eq!(
+                tmux.panes(&workspace.target_session(Some(0)))
+                    .unwrap()
+                    .len(),
+                2
+            );
+        });
+    }
+
+    #[test]
+    fn workplace_window_name_replaces_dots_n_spaces() {
+        let wp = WorkSpace {
+            path: PathBuf::from("/Users/zacharythomas/dev/foo.bar/"),
+            session_name: "dev".to_owned(),
+            format_checksum: "34ed,230x56,0,0{132x56,0,0,3,97x56,133,0,222}".to_owned(),
+            commands: vec!["nvim".to_owned(), "fish".to_owned()],
+            window_name: None,
+            number_of_panes: 3,
+            ..Default::default()
+        };
+        assert_eq!(wp.window_name(), "foo-bar")
+    }
+
+    #[test]
+    fn workplace_window_name_returns_window_name_from_path() {
+        let wp = WorkSpace {
+            path: PathBuf::from("/Users/zacharythomas/dev/some_name/"),
+            session_name: "dev".to_owned(),
+            format_checksum: "34ed,230x56,0,0{132x56,0,0,3,97x56,133,0,222}".to_owned(),
+            commands: vec!["nvim".to_owned(), "fish".to_owned()],
+            window_name: None,
+            number_of_panes: 3,
+            ..Default::default()
+        };
+        assert_eq!(wp.window_name(), "some_name")
+    }
+
+    #[test]
+    fn workspace_dir_expands_tilde_and_env_vars() {
+        std::env::set_var("DMUX_TEST_PROJECTS", "/srv/projects");
+        let wp = WorkSpace {
+            path: PathBuf::from("$DMUX_TEST_PROJECTS/foo.bar"),
+            session_name: "dev".to_owned(),
+            format_checksum: "34ed,230x56,0,0{132x56,0,0,3,97x56,133,0,222}".to_owned(),
+            commands: vec!["nvim".to_owned(), "fish".to_owned()],
+            window_name: None,
+            number_of_panes: 3,
+            ..Default::default()
+        };
+        // this is what the session and its first window get created in
+        assert_eq!(wp.path_str(), "/srv/projects/foo.bar");
+        assert_eq!(wp.window_name(), "foo-bar");
+        assert_eq!(
+            expand_path(Path::new("~/dev/${DMUX_TEST_PROJECTS}")),
+            dirs::home_dir().unwrap().join("dev/srv/projects")
+        );
+    }
+
+    #[test]
+    fn pane_reads_its_pid() {
+        let pane = Pane::from_interface("0|:|%1|:|0|:|0|:|80|:|24|:|4242").unwrap();
+        assert_eq!(pane.pid(), Some(4242));
+    }
+
+    #[test]
+    fn pane_reads_what_it_is_running_and_where() {
+        let pane = Pane::from_interface(
+            "1|:|%2|:|0|:|0|:|80|:|24|:|4242|:|cargo|:|build|:|/home/zt/odd|:|dir",
+        )
+        .unwrap();
+        assert_eq!(pane.current_command(), Some("cargo"));
+        assert_eq!(pane.title(), Some("build"));
+        assert_eq!(pane.current_path(), Some(Path::new("/home/zt/odd|:|dir")));
+
+        let mut window = Window::from_interface("dev|:|1|:|@1|:|80|:|24|:||:|dmux").unwrap();
+        window.panes = vec![pane, Pane::from_index(2)];
+        assert_eq!(
+            window.panes_summary(),
+            vec![
+                (1, Some("cargo"), Some(Path::new("/home/zt/odd|:|dir"))),
+                (2, None, None)
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn foreground_group_counts_fields_after_the_command() {
+        let stat = "4242 (npm run (dev)) S 4200 4242 4242 34817 4300 4194304 210 0";
+        assert_eq!(foreground_group(stat), Some(4300));
+        assert_eq!(foreground_group("4242 (sh) S 1 4242 4242 0 -1 0"), None);
+    }
+
+    #[test]
+    fn pane_targets_prefer_pane_id() {
+        let wp = WorkSpace {
+            path: PathBuf::from("/Users/zacharythomas/dev/some_name/"),
+            session_name: "dev".to_owned(),
+            format_checksum: "34ed,230x56,0,0{132x56,0,0,3,97x56,133,0,222}".to_owned(),
+            commands: vec!["nvim".to_owned(), "fish".to_owned()],
+            window_name: None,
+            number_of_panes: 3,
+            ..Default::default()
+        };
+        let window =
+            Window::from_interface(&format!("dev|:|1|:|@3|:|80|:|24|:||:|{}", wp.window_name()))
+                .unwrap();
+        let pane = Pane::from_interface("1|:|%12").unwrap();
+        assert_eq!(pane.index, 1);
+        assert_eq!(pane.id(), Some("%12"));
+        assert_eq!(pane.target(&window), "%12");
+        assert_eq!(Pane::from_index(1).target(&window), "@3.1");
+    }
+
+    #[test]
+    fn run_kills_commands_that_outlive_the_timeout() {
+        let tmux = Tmux::with_config(TmuxConfig {
+            timeout: Duration::from_millis(50),
+            ..Default::default()
+        });
+        let mut sleep = TmuxCommand::new();
+        sleep.bin("sleep").cmd("5");
+
+        let started = Instant::now();
+        let err = tmux.run(&sleep).unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(matches!(
+            err.downcast_ref::<TmuxError>(),
+            Some(TmuxError::Timeout { .. })
+        ));
+    }
+
+    #[test]
+    fn stale_tmux_env_falls_back_to_attaching() {
+        let tmux = Tmux::new();
+        let stale = "/nonexistent/tmux-0/default,4242,0";
+        let mut server_pid = tmux.process(&server_pid_command(&tmux));
+        server_pid
+            .env("TMUX", stale)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let unreachable = server_pid.output().ok().map(TmuxOutput);
+        assert!(!same_server(stale, &unreachable));
+
+        let restarted = Some(TmuxOutput(Output {
+            status: Command::new("true").status().unwrap(),
+            stdout: b"5151\n".to_vec(),
+            stderr: vec![],
+        }));
+        assert!(!same_server(stale, &restarted));
+        assert!(same_server("/tmp/tmux-0/default,5151,0", &restarted));
+
+        let args: Vec<_> = tmux
+            .process(&attach_command("dev:1", false, &tmux))
+            .get_args()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(args[0], "attach");
+    }
+
+    #[test]
+    fn post_attach_commands_are_chained_onto_the_attach() {
+        let tmux = Tmux::new();
+        let commands = vec![
+            "resize-window -A".to_string(),
+            "display \"a b\"".to_string(),
+        ];
+        let attach = with_post_attach(attach_command("dev:1", false, &tmux), &commands);
+        let args: Vec<_> = attach.cmd_args.iter().flatten().collect();
+        assert_eq!(
+            args,
+            vec![
+                "-t",
+                "dev:1",
+                ";",
+                "if-shell",
+                "-F",
+                "1",
+                "resize-window -A",
+                ";",
+                "if-shell",
+                "-F",
+                "1",
+                "display \"a b\""
+            ]
+        );
+    }
+
+    #[test]
+    fn created_windows_are_read_from_what_new_window_prints() {
+        let printed = |status: &str, stdout: &str| {
+            TmuxOutput(Output {
+                status: Command::new(status).status().unwrap(),
+                stdout: stdout.as_bytes().to_vec(),
+                stderr: vec![],
+            })
+        };
+        let window = created_window(printed("true", "dev|:|3|:|@7|:|80|:|24|:||:|dmux\n")).unwrap();
+        assert_eq!(
+            (window.index, window.id(), window.name.as_str()),
+            (3, Some("@7"), "dmux")
+        );
+
+        assert_eq!(created_window(printed("false", "")), None);
+        assert_eq!(created_window(printed("true", "@7\n")), None);
+    }
+
+    #[test]
+    fn clients_read_their_size_and_name() {
+        assert_eq!(
+            ClientInfo::from_interface("80|:|24|:|/dev/pts/3").unwrap(),
+            ClientInfo {
+                name: "/dev/pts/3".to_owned(),
+                width: Some(80),
+                height: Some(24),
+            }
+        );
+        assert!(ClientInfo::from_interface("/dev/pts/3").is_err());
+    }
+
+    #[test]
+    fn attaching_from_inside_the_target_session_switches() {
+        assert!(nested_attach(Some("dev"), "dev:1"));
+        assert!(nested_attach(Some("dev"), "dev"));
+        assert!(!nested_attach(Some("dev"), "devops:1"));
+        assert!(!nested_attach(None, "dev:1"));
+    }
+
+    #[test]
+    fn target_exists_follows_the_exit_status() {
+        let mut tmux = Tmux::new();
+        tmux.command.bin("true");
+        assert!(tmux.target_exists("dev:2.1"));
+        tmux.command.bin("false");
+        assert!(!tmux.target_exists("dev"));
+
+        let args = |target| -> Vec<String> {
+            let command = target_exists_command(target, &tmux);
+            tmux.process(&command)
+                .get_args()
+                .map(|a| a.to_string_lossy().into_owned())
+                .collect()
+        };
+        assert_eq!(args("dev:2"), ["has", "-t", "dev:2"]);
+        assert_eq!(args("%12")[..3], ["lsp", "-t", "%12"]);
+    }
+
+    #[test]
+    fn config_file_is_passed_before_the_command() {
+        let tmux = Tmux::with_config(TmuxConfig {
+            config_file: Some(PathBuf::from("/tmp/ci.tmux.conf")),
+            ..Default::default()
+        });
+        let process = tmux.process(&tmux.command.list_sessions().0);
+        let args: Vec<_> = process.get_args().collect();
+        assert_eq!(&args[..3], ["-f", "/tmp/ci.tmux.conf", "ls"]);
+    }
+
+    #[test]
+    fn sockets_are_passed_to_every_invocation() {
+        let socket = |socket| {
+            let tmux = Tmux::with_config(TmuxConfig {
+                socket: Some(socket),
+                config_file: Some(PathBuf::from("/tmp/ci.tmux.conf")),
+                ..Default::default()
+            });
+            let mut version = tmux.command.clone();
+            version.bin_args = Some(vec!["-V".into()]);
+            [tmux.command.list_sessions().0, version]
+                .iter()
+                .map(|command| {
+                    let process = tmux.process(command);
+                    let args: Vec<_> = process
+                        .get_args()
+                        .map(|arg| arg.to_string_lossy())
+                        .collect();
+                    args[..5].join(" ")
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            socket(Socket::Name("work".to_owned())),
+            [
+                "-L work -f /tmp/ci.tmux.conf ls",
+                "-V -L work -f /tmp/ci.tmux.conf"
+            ]
+        );
+        assert_eq!(
+            socket(Socket::Path(PathBuf::from("/tmp/sandbox.sock")))[0],
+            "-S /tmp/sandbox.sock -f /tmp/ci.tmux.conf ls"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failed_sends_name_the_command_and_pane() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let window = Window::from_interface("dev|:|1|:|@1|:|80|:|24|:||:|dmux").unwrap();
+        let failed = TmuxOutput(Output {
+            status: std::process::ExitStatus::from_raw(1 << 8),
+            stdout: vec![],
+            stderr: b"can't find pane: %9\n".to_vec(),
+        });
+        let err = check_sent(failed, &window, &Pane::from_index(2), "npm run dev").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Couldn't send \"npm run dev\" to pane 2 of dmux: can't find pane: %9"
+        );
+    }
+
+    #[test]
+    fn panes_are_ordered_row_by_row() {
+        let mut window = Window::from_interface("dev|:|1|:|@1|:|200|:|60|:||:|dmux").unwrap();
+        window.panes = [
+            "0|:|%1|:|0|:|0|:|99|:|29",
+            "1|:|%2|:|0|:|30|:|99|:|30",
+            "2|:|%3|:|100|:|0|:|100|:|60",
+        ]
+        .into_iter()
+        .map(|line| Pane::from_interface(line).unwrap())
+        .collect();
+        assert_eq!(window.position_map(), [0, 2, 1]);
+
+        let workspace = WorkSpace {
+            commands: vec!["vim".to_owned(), "ls".to_owned()],
+            commands_by_position: true,
+            ..Default::default()
+        };
+        let panes = command_panes(&workspace, window.panes.clone(), 0);
+        assert_eq!(panes.iter().map(|p| p.index).collect::<Vec<_>>(), [0, 2]);
+    }
+
+    #[test]
+    fn new_windows_can_replace_the_one_at_their_index() {
+        let tmux = Tmux::new();
+        let args = |replace| -> Vec<String> {
+            let command = new_window_command("dev:5", "logs", "/tmp", replace, &tmux);
+            tmux.process(&command)
+                .get_args()
+                .map(|a| a.to_string_lossy().into_owned())
+                .collect()
+        };
+        assert!(!args(false).contains(&"-k".to_owned()));
+        let replacing = args(true);
+        assert!(replacing.contains(&"-k".to_owned()));
+        assert!(replacing.windows(2).any(|pair| pair == ["-t", "dev:5"]));
+    }
+
+    #[test]
+    fn typed_keys_keep_literal_text_apart() {
+        let tmux = Tmux::new();
+        let keys = vec![
+            Key::Ctrl('b'),
+            Key::F(5),
+            Key::Literal("Enter".to_string()),
+            Key::Literal(" twice".to_string()),
+            Key::Alt('x'),
+            Key::Escape,
+            Key::Enter,
+        ];
+        let commands: Vec<Vec<String>> = typed_keys_commands("%3", &keys, &tmux)
+            .iter()
+            .map(|c| c.cmd_args.iter().flatten().map(|a| a.to_string()).collect())
+            .collect();
+        assert_eq!(
+            commands,
+            vec![
+                vec!["-t", "%3", "C-b", "F5"],
+                vec!["-t", "%3", "-l", "Enter", " twice"],
+                vec!["-t", "%3", "M-x", "Escape", "Enter"],
+            ]
+        );
+    }
+
+    #[test]
+    fn pane_reads_its_geometry() {
+        let pane = Pane::from_interface("2|:|%4|:|133|:|34|:|97|:|22").unwrap();
+        assert_eq!(pane.left(), Some(133));
+        assert_eq!(pane.top(), Some(34));
+        assert_eq!(pane.width(), Some(97));
+        assert_eq!(pane.height(), Some(22));
+        assert_eq!(Pane::from_index(2).width(), None);
+    }
+
+    #[test]
+    fn windows_sharing_a_cleaned_name_are_targeted_by_id() {
+        let first = Window::from_interface("dev|:|1|:|@3|:|80|:|24|:||:|foo-bar").unwrap();
+        let second = Window::from_interface("dev|:|2|:|@7|:|80|:|24|:||:|foo-bar").unwrap();
+        assert_eq!(first.name, second.name);
+        assert_eq!(first.target(), "@3");
+        assert_eq!(second.target(), "@7");
+
+        let without_id = Window::from_interface("dev|:|2|:||:|80|:|24|:||:|foo bar").unwrap();
+        assert_eq!(without_id.id(), None);
+        assert_eq!(without_id.name, "foo bar");
+        assert_eq!(without_id.target(), "dev:2");
+    }
+
+    #[test]
+    fn layout_accepts_presets_and_checksums() {
+        assert_eq!(Layout::from_checksum("tiled").unwrap().as_str(), "tiled");
+        assert_eq!(
+            Layout::from_checksum("1 34ed,230x56,0,0{132x56,0,0,3,97x56,133,0,222}\n")
+                .unwrap()
+                .as_str(),
+            "34ed,230x56,0,0{132x56,0,0,3,97x56,133,0,222}"
+        );
+        assert!(
+            Layout::from_checksum("f369,80x24,0,0[80x12,0,0,0,80x5,0,13,1,80x5,0,19,2]").is_ok()
+        );
+    }
+
+    #[test]
+    fn layout_counts_the_panes_of_a_checksum() {
+        let count = |body: &str| {
+            Layout::from_checksum(&format!("{:04x},{}", layout_checksum(body), body))
+                .unwrap()
+                .pane_count()
+        };
+        assert_eq!(count("80x24,0,0,0"), Some(1));
+        assert_eq!(
+            count("80x24,0,0[80x12,0,0,0,80x5,0,13,1,80x5,0,19,2]"),
+            Some(3)
+        );
+        assert_eq!(
+            count("80x24,0,0{40x24,0,0,1,39x24,41,0[39x12,41,0,2,39x11,41,13,3]}"),
+            Some(3)
+        );
+        assert_eq!(Layout::from_checksum("tiled").unwrap().pane_count(), None);
+    }
+
+    #[test]
+    fn split_trees_plan_their_splits() {
+        let pane = |size| SplitTree {
+            size,
+            ..Default::default()
+        };
+        let tree = SplitTree {
+            split: Some(SplitDirection::Horizontal),
+            children: vec![
+                pane(Some(70)),
+                SplitTree {
+                    split: Some(SplitDirection::Vertical),
+                    children: vec![pane(None), pane(None), pane(None)],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        assert!(tree.validate().is_ok());
+        assert_eq!(tree.pane_count(), 4);
+        let steps: Vec<_> = tree
+            .plan()
+            .iter()
+            .map(|s| (s.target, s.pane, s.direction, s.percent))
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                (0, 1, SplitDirection::Horizontal, 30),
+                (1, 2, SplitDirection::Vertical, 67),
+                (2, 3, SplitDirection::Vertical, 50),
+            ]
+        );
+        assert!(pane(None).plan().is_empty());
+
+        let too_big = SplitTree {
+            split: Some(SplitDirection::Vertical),
+            children: vec![pane(Some(80)), pane(Some(30))],
+            ..Default::default()
+        };
+        assert!(too_big.validate().is_err());
+        let no_direction = SplitTree {
+            children: vec![pane(None), pane(None)],
+            ..Default::default()
+        };
+        assert!(no_direction.validate().is_err());
+        assert_eq!(
+            "vertical".parse::<SplitDirection>().unwrap(),
+            SplitDirection::Vertical
+        );
+    }
+
+    #[test]
+    fn layout_checksums_parse_into_cells() {
+        let layout =
+            Layout::from_checksum("34ed,230x56,0,0{132x56,0,0,3,97x56,133,0,222}").unwrap();
+        let pane = |width, height, x, id| LayoutCell {
+            width,
+            height,
+            x,
+            y: 0,
+            content: CellContent::Pane(Some(id)),
+        };
+        assert_eq!(
+            layout.tree(),
+            Some(LayoutCell {
+                width: 230,
+                height: 56,
+                x: 0,
+                y: 0,
+                content: CellContent::Split(
+                    SplitDirection::Horizontal,
+                    vec![pane(132, 56, 0, 3), pane(97, 56, 133, 222)]
+                ),
+            })
+        );
+
+        let nested =
+            LayoutCell::parse("80x24,0,0{40x24,0,0,1,39x24,41,0[39x12,41,0,2,39x11,41,13,3]}")
+                .unwrap();
+        let sizes: Vec<_> = nested.panes().iter().map(|p| (p.width, p.height)).collect();
+        assert_eq!(sizes, [(40, 24), (39, 12), (39, 11)]);
+        assert!(matches!(
+            &nested.content,
+            CellContent::Split(SplitDirection::Horizontal, cells)
+                if matches!(cells[1].content, CellContent::Split(SplitDirection::Vertical, _))
+        ));
+
+        // before tmux 1.8 panes had no ids
+        let old = LayoutCell::parse("80x24,0,0[80x12,0,0,80x11,0,13]").unwrap();
+        assert_eq!(old.panes()[1].content, CellContent::Pane(None));
+        assert_eq!(old.pane_count(), 2);
+
+        let workspace = WorkSpace {
+            format_checksum: layout.to_string(),
+            number_of_panes: 1,
+            ..Default::default()
+        }
+        .normalized()
+        .unwrap();
+        assert_eq!(workspace.number_of_panes(), 2);
+
+        assert!(LayoutCell::parse("80x24,0,0{40x24,0,0,1").is_err());
+        assert!(LayoutCell::parse("80x24,0,0,1}").is_err());
+    }
+
+    #[test]
+    fn layout_specs_resolve_to_layouts() {
+        assert_eq!(
+            LayoutSpec::parse("tiled", 2).unwrap(),
+            LayoutSpec::Preset(Preset::Tiled)
+        );
+        assert_eq!(
+            LayoutSpec::parse("auto:even-vertical", 5).unwrap(),
+            LayoutSpec::Auto {
+                panes: 5,
+                preset: Some(Preset::EvenVertical)
+            }
+        );
+        let checksum = "34ed,230x56,0,0{132x56,0,0,3,97x56,133,0,222}";
+        let spec = LayoutSpec::parse(checksum, 2).unwrap();
+        assert_eq!(spec, LayoutSpec::Checksum(checksum.to_owned()));
+        assert_eq!(Layout::try_from(spec).unwrap().as_str(), checksum);
+        assert_eq!(
+            Layout::try_from(LayoutSpec::parse("auto", 6).unwrap())
+                .unwrap()
+                .as_str(),
+            "tiled"
+        );
+        assert!(Layout::try_from(LayoutSpec::Checksum("tiles".to_owned())).is_err());
+    }
+
+    #[test]
+    fn auto_layout_depends_on_the_pane_count() {
+        let few = Layout::auto(3);
+        assert_eq!(few.as_str(), "main-vertical");
+        assert_eq!(
+            few.main_pane_option(),
+            Some(("main-pane-width", "60%".to_owned()))
+        );
+        let many = Layout::auto(4);
+        assert_eq!(many.as_str(), "tiled");
+        assert_eq!(many.main_pane_option(), None);
+
+        let workspace = WorkSpace {
+            path: PathBuf::from("/tmp/dmux"),
+            format_checksum: "auto:main-horizontal".to_owned(),
+            number_of_panes: 2,
+            ..Default::default()
+        }
+        .normalized()
+        .unwrap();
+        assert_eq!(workspace.format_checksum, "main-horizontal");
+        assert_eq!(
+            workspace.options,
+            [(
+                Scope::Window,
+                "main-pane-height".to_owned(),
+                "60%".to_owned()
+            )]
+        );
+        assert!(LayoutSpec::parse("auto:tiles", 2).is_err());
+    }
+
+    #[test]
+    fn layout_rejects_mangled_checksums() {
+        let reason = |layout: &str| match Layout::from_checksum(layout)
+            .unwrap_err()
+            .downcast::<TmuxError>()
+        {
+            Ok(TmuxError::LayoutInvalid { reason, .. }) => reason,
+            other => panic!("expected LayoutInvalid, got {:?}", other),
+        };
+        assert!(reason("4ed,230x56,0,0{132x56,0,0,3,97x56,133,0,222}").contains("4 digit hex"));
+        assert!(reason("34ed,230x56,0,0{132x56,0,0,3,97x56,133,0,223}").contains("should be"));
+        assert!(reason("34ed,230,0,0").contains("WIDTHxHEIGHT"));
+        assert!(reason("tiles").contains("preset"));
+    }
+
+    #[test]
+    fn layout_size_reads_checksum_dimensions() {
+        assert_eq!(
+            layout_size("34ed,230x56,0,0{132x56,0,0,3,97x56,133,0,222}"),
+            Some((230, 56))
+        );
+        assert_eq!(layout_size("even-horizontal"), None);
+    }
+
+    #[test]
+    fn workspace_window_can_be_picked_by_index() {
+        let session = Session {
+            name: "dev".to_owned(),
+            attached: 0,
+            windows: vec![
+                Window::from_interface("dev|:|1|:|@1|:|80|:|24|:||:|dmux").unwrap(),
+                Window::from_interface("dev|:|3|:|@4|:|80|:|24|:||:|bash").unwrap(),
+            ],
+        };
+        let by_name = WorkSpace {
+            session_name: "dev".to_owned(),
+            window_name: Some("dmux".to_owned()),
+            ..Default::default()
+        };
+        let by_index = WorkSpace {
+            window_index: Some(3),
+            ..by_name.clone()
+        };
+
+        let window = by_name.window_in(&session, false).unwrap();
+        assert_eq!(window.id(), Some("@1"));
+        assert_eq!(by_name.window_target(window), "@1");
+        assert_eq!(by_name.window_slot(), "dev:");
+
+        let window = by_index.window_in(&session, false).unwrap();
+        assert_eq!(window.id(), Some("@4"));
+        assert_eq!(by_index.window_target(window), "dev:3");
+        assert_eq!(by_index.window_slot(), "dev:3");
+    }
+
+    #[test]
+    fn login_shell_is_passed_to_created_panes() {
+        let workspace = WorkSpace {
+            path: PathBuf::from("/tmp/dmux"),
+            session_name: "dev".to_owned(),
+            login_shell: true,
+            ..Default::default()
+        };
+        let window = Window::from_interface("dev|:|1|:|@1|:|80|:|24|:||:|dmux").unwrap();
+        let tmux = Tmux::new();
+        let shell = workspace.shell_command().unwrap();
+        assert!(shell.ends_with(" -l"));
+
+        for command in [
+            new_session_command(&workspace, &tmux),
+            create_window_command(&workspace, &tmux),
+            split_window_command(&workspace, &window, 1, &tmux),
+        ] {
+            let args = command.cmd_args.unwrap();
+            assert_eq!(args.last().map(|a| a.as_ref()), Some(shell.as_str()));
+        }
+
+        let plain = WorkSpace {
+            login_shell: false,
+            ..workspace
+        };
+        assert_eq!(plain.shell_command(), None);
+
+        // variables are options, so they go ahead of the shell
+        let with_env = WorkSpace {
+            env: vec![("EDITOR".to_owned(), "nvim".to_owned())],
+            login_shell: true,
+            ..plain
+        };
+        let args = split_window_command(&with_env, &window, 1, &tmux)
+            .cmd_args
+            .unwrap();
+        let args: Vec<&str> = args.iter().map(|a| a.as_ref()).collect();
+        assert_eq!(args[args.len() - 3..args.len() - 1], ["-e", "EDITOR=nvim"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn monitoring_sets_options_on_the_window_alone() {
+        let (tmux, dir, log) = fake_tmux("monitor", "");
+        let window = Window::from_interface("dev|:|1|:|@3|:|80|:|24|:||:|server").unwrap();
+        window.monitor_activity(&tmux, true).unwrap();
+        window.monitor_silence(&tmux, Some(30)).unwrap();
+        window.monitor_silence(&tmux, None).unwrap();
+
+        let written = std::fs::read_to_string(&log).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            written.lines().collect::<Vec<_>>(),
+            [
+                "set -w -t @3 monitor-activity on",
+                "set -w -t @3 monitor-silence 30",
+                "set -w -t @3 monitor-silence 0",
+            ]
+        );
+
+        let workspace = WorkSpace {
+            format_checksum: "tiled".to_owned(),
+            monitor_activity: true,
+            monitor_silence: Some(10),
+            ..Default::default()
+        }
+        .normalized()
+        .unwrap();
+        assert_eq!(
+            workspace
+                .options_in(&[Scope::Window])
+                .collect::<Vec<_>>()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn options_are_set_in_their_scope() {
+        let tmux = Tmux::new();
+        let args = |scope: Scope, target: &str| {
+            set_option_command(scope, "status", "off", target, &tmux)
+                .cmd_args
+                .unwrap()
+                .iter()
+                .map(|a| a.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(args(Scope::Global, "dev"), ["-g", "status", "off"]);
+        assert_eq!(args(Scope::Session, "dev"), ["-t", "dev", "status", "off"]);
+        assert_eq!(
+            args(Scope::Window, "@3"),
+            ["-w", "-t", "@3", "status", "off"]
+        );
+
+        assert_eq!("window".parse::<Scope>().unwrap(), Scope::Window);
+        assert_eq!(
+            window_size_command(WindowSize::Latest, "@3", &tmux)
+                .cmd_args
+                .unwrap()
+                .last()
+                .map(|a| a.to_string()),
+            Some("latest".to_owned())
+        );
+        assert!("pane".parse::<Scope>().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_an_unreachable_server_is_retried() {
+        let list_sessions = |tmux: &Tmux| tmux.command.list_sessions().0.to_owned();
+        let calls = |log: &Path| std::fs::read_to_string(log).unwrap().lines().count();
+
+        // the server drops out for the first two calls
+        let (tmux, dir, log) = fake_tmux(
+            "retry-lost",
+            "[ $(wc -l < $LOG) -ge 3 ] && exit 0\necho 'lost server' >&2\nexit 1\n",
+        );
+        assert!(tmux.run_retrying(&list_sessions(&tmux)).unwrap().success());
+        assert_eq!(calls(&log), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let (tmux, dir, log) = fake_tmux(
+            "retry-gone",
+            "echo 'no server running on /tmp/tmux-0/default' >&2\nexit 1\n",
+        );
+        assert!(no_server(
+            &tmux.run_retrying(&list_sessions(&tmux)).unwrap()
+        ));
+        assert_eq!(calls(&log), tmux.config.retry.attempts as usize);
+        assert_eq!(tmux.sessions().unwrap(), []);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let (tmux, dir, log) = fake_tmux(
+            "retry-target",
+            "echo \"can't find session: nope\" >&2\nexit 1\n",
+        );
+        assert!(tmux.panes("nope").is_err());
+        assert_eq!(calls(&log), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dry_runs_only_look_at_the_server() {
+        let (mut tmux, dir, log) = fake_tmux(
+            "dry-run",
+            "echo 'no server running on /tmp/tmux-0/default' >&2\nexit 1\n",
+        );
+        tmux.config.dry_run = true;
+        let outcome = tmux
+            .setup_workspace(WorkSpace {
+                path: dir.clone(),
+                session_name: "site".to_owned(),
+                format_checksum: "tiled".to_owned(),
+                window_name: Some("dev".to_owned()),
+                commands: vec!["nvim".to_owned(), "npm start".to_owned()],
+                splits: Some(SplitTree {
+                    split: Some(SplitDirection::Horizontal),
+                    size: None,
+                    children: vec![SplitTree::default(), SplitTree::default()],
+                }),
+                detached: true,
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(outcome.session_created);
+        assert_eq!(outcome.target, "site:0");
+
+        let calls = std::fs::read_to_string(&log).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(calls
+            .lines()
+            .all(|call| READ_ONLY_COMMANDS.contains(&call.split(' ').next().unwrap())));
+    }
+
+    #[test]
+    fn dry_run_lines_quote_what_the_shell_reads() {
+        let mut send_keys = Tmux::new().command.send_keys();
+        send_keys
+            .target_pane("site:0.1")
+            .key("echo 'hi' $HOME")
+            .key("")
+            .key("it's\r");
+        assert_eq!(
+            shell_line(&Tmux::new().process(&send_keys.0)),
+            r#"tmux send -t site:0.1 'echo '\''hi'\'' $HOME' '' $'it\'s\r'"#
+        );
+        assert!(read_only(&Tmux::new().command.list_panes().0));
+        assert!(!read_only(&Tmux::new().command.kill_session().0));
+    }
+
+    #[test]
+    fn commands_for_a_host_go_over_ssh() {
+        let tmux = Tmux::with_config(TmuxConfig {
+            host: Some("dev-box".to_owned()),
+            ..Default::default()
+        });
+        let mut send_keys = tmux.command.send_keys();
+        send_keys.target_pane("site:0.1").key("echo 'hi'\r");
+        let args = |process: &Command| -> Vec<String> {
+            std::iter::once(process.get_program())
+                .chain(process.get_args())
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect()
+        };
+        assert_eq!(
+            args(&tmux.process(&send_keys.0)),
+            [
+                "ssh",
+                "dev-box",
+                "tmux send -t site:0.1 'echo '\\''hi'\\''\r'"
+            ]
+        );
+        assert_eq!(
+            args(&tmux.process(&attach_command("site:0", false, &tmux))),
+            ["ssh", "-t", "dev-box", "tmux attach -t site:0"]
+        );
+    }
+
+    #[test]
+    fn borders_are_set_as_window_options() {
+        let workspace = WorkSpace {
+            pane_border_status: Some(BorderStatus::Top),
+            pane_border_format: Some("#{pane_index}: #{pane_title}".to_owned()),
+            ..Default::default()
+        };
+        let tmux = Tmux::new();
+        let commands: Vec<Vec<String>> = border_options(&workspace)
+            .into_iter()
+            .map(|(name, value)| {
+                let command = set_option_command(Scope::Window, name, value, "@3", &tmux);
+                assert_eq!(command.cmd.as_deref(), Some("set"));
+                command
+                    .cmd_args
+                    .unwrap()
+                    .iter()
+                    .map(|a| a.to_string())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                ["-w", "-t", "@3", "pane-border-status", "top"],
+                [
+                    "-w",
+                    "-t",
+                    "@3",
+                    "pane-border-format",
+                    "#{pane_index}: #{pane_title}"
+                ],
+            ]
+        );
+
+        assert!(border_options(&WorkSpace::default()).is_empty());
+        assert_eq!(
+            "bottom".parse::<BorderStatus>().unwrap(),
+            BorderStatus::Bottom
+        );
+        assert!("left".parse::<BorderStatus>().is_err());
+    }
+
+    #[test]
+    fn session_names_are_cleaned_like_tmux_does() {
+        let workspace = WorkSpace {
+            session_name: "my.site:2".to_owned(),
+            format_checksum: "tiled".to_owned(),
+            ..Default::default()
+        }
+        .normalized()
+        .unwrap();
+        assert_eq!(workspace.session_name, "my_site_2");
+    }
+
+    #[test]
+    fn script_commands_send_each_line() {
+        let dir = std::env::temp_dir().join(format!("dmux-script-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("boot.sh"), "cd src\n\ncargo check\n").unwrap();
+
+        let workspace = WorkSpace {
+            path: dir.clone(),
+            format_checksum: "tiled".to_owned(),
+            commands: vec!["vim".to_owned(), "file:boot.sh".to_owned()],
+            ..Default::default()
+        }
+        .normalized()
+        .unwrap();
+        assert_eq!(
+            command_lines(&workspace.commands[1]).unwrap(),
+            ["cd src", "cargo check"]
+        );
+        assert_eq!(command_lines("vim").unwrap(), ["vim"]);
+
+        let missing = WorkSpace {
+            path: dir.clone(),
+            format_checksum: "tiled".to_owned(),
+            commands: vec!["file:missing.sh".to_owned()],
+            ..Default::default()
+        }
+        .normalized();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(missing
+            .unwrap_err()
+            .to_string()
+            .starts_with("Couldn't find the script"));
+    }
+
+    #[test]
+    fn placeholders_are_filled_in_from_the_workspace() {
+        let dir = std::env::temp_dir().join(format!("dmux-placeholders-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("api")).unwrap();
+        let workspace = WorkSpace {
+            path: dir.clone(),
+            session_name: "my.site".to_owned(),
+            format_checksum: "tiled".to_owned(),
+            commands: vec![
+                "cd {{dir}} && echo {{dir_name}} {{session}}:{{window}}".to_owned(),
+                "docker ps --format '{{.Names}}'".to_owned(),
+            ],
+            windows: vec![WindowSpec {
+                name: "server".to_owned(),
+                dir: Some(PathBuf::from("api")),
+                commands: vec!["echo {{window}} in {{dir_name}}".to_owned()],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let dir_name = dir.file_name().unwrap().to_str().unwrap();
+        let normalized = workspace.normalized().unwrap();
+        assert_eq!(
+            normalized.commands,
+            [
+                format!(
+                    "cd {} && echo {} my_site:{}",
+                    dir.display(),
+                    dir_name,
+                    clean_str(dir_name)
+                ),
+                "docker ps --format '{{.Names}}'".to_owned(),
+            ]
+        );
+        assert_eq!(
+            normalized.window_workspaces().unwrap()[0].commands,
+            ["echo server in api"]
+        );
+
+        let branch = WorkSpace {
+            path: dir.clone(),
+            format_checksum: "tiled".to_owned(),
+            commands: vec!["echo {{git_branch}}".to_owned()],
+            ..Default::default()
+        };
+        assert_eq!(branch.clone().normalized().unwrap().commands, ["echo "]);
+        let git = |args: &[&str]| Command::new("git").arg("-C").arg(&dir).args(args).output();
+        if git(&["init", "--quiet"]).is_ok_and(|output| output.status.success()) {
+            git(&["symbolic-ref", "HEAD", "refs/heads/trunk"]).unwrap();
+            assert_eq!(branch.normalized().unwrap().commands, ["echo trunk"]);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn session_names_can_come_from_a_template() {
+        let parent = std::env::temp_dir().join(format!("dmux-template-{}", std::process::id()));
+        let dir = parent.join("my.site");
+        std::fs::create_dir_all(&dir).unwrap();
+        let workspace = WorkSpace {
+            path: dir.clone(),
             session_name: "dev".to_owned(),
-            format_checksum: "34ed,230x56,0,0{132x56,0,0,3,97x56,133,0,222}".to_owned(),
-            commands: vec!["nvim".to_owned(), "fish".to_owned()],
-            window_name: None,
-            number_of_panes: 3,
+            session_name_template: Some("{{parent_dir}}/{{dir_name}}".to_owned()),
+            format_checksum: "tiled".to_owned(),
+            commands: vec!["echo {{session}}".to_owned()],
+            ..Default::default()
         };
-        assert_eq!(wp.window_name(), "some_name")
+        let normalized = workspace.normalized().unwrap();
+        let parent_name = parent.file_name().unwrap().to_str().unwrap();
+        assert_eq!(normalized.session_name, format!("{}/my_site", parent_name));
+        assert_eq!(normalized.session_name_template, None);
+        assert_eq!(
+            normalized.commands,
+            [format!("echo {}/my_site", parent_name)]
+        );
+
+        let git = |args: &[&str]| Command::new("git").arg("-C").arg(&dir).args(args).output();
+        if git(&["init", "--quiet"]).is_ok_and(|output| output.status.success()) {
+            git(&["symbolic-ref", "HEAD", "refs/heads/trunk"]).unwrap();
+            let repo = WorkSpace {
+                path: dir.clone(),
+                session_name_template: Some("{{git_repo}}-{{git_branch}}".to_owned()),
+                format_checksum: "tiled".to_owned(),
+                ..Default::default()
+            };
+            assert_eq!(repo.normalized().unwrap().session_name, "my_site-trunk");
+        }
+        std::fs::remove_dir_all(&parent).unwrap();
+    }
+
+    #[test]
+    fn multi_line_commands_need_opting_into() {
+        let workspace = WorkSpace {
+            format_checksum: "tiled".to_owned(),
+            commands: vec!["cd src\ncargo check".to_owned()],
+            ..Default::default()
+        };
+        assert!(workspace
+            .clone()
+            .normalized()
+            .unwrap_err()
+            .to_string()
+            .contains("spans several lines"));
+
+        let multiline = WorkSpace {
+            multiline_commands: true,
+            ..workspace.clone()
+        }
+        .normalized()
+        .unwrap();
+        assert_eq!(
+            command_lines(&multiline.commands[0]).unwrap(),
+            ["cd src", "cargo check"]
+        );
+
+        let escape = WorkSpace {
+            commands: vec!["echo \u{1b}[2J".to_owned()],
+            multiline_commands: true,
+            ..workspace
+        };
+        assert!(escape.normalized().is_err());
+    }
+
+    #[test]
+    fn command_lines_are_sent_literally() {
+        let tmux = Tmux::new();
+        for (line, key) in [
+            ("echo a; echo b", "echo a; echo b\r"),
+            ("echo done;", "echo done;\r"),
+            ("Enter", "Enter\r"),
+        ] {
+            let args: Vec<_> = send_line_command("%1", line, &tmux)
+                .cmd_args
+                .unwrap()
+                .into_iter()
+                .map(|a| a.into_owned())
+                .collect();
+            assert_eq!(args, ["-l", "-t", "%1", key]);
+        }
+    }
+
+    #[test]
+    fn commands_land_when_they_show_up_in_the_capture() {
+        let capture = "~/dev/dmux $ npm run dev\n> dmux@1.0.0 dev\n";
+        assert!(command_landed(capture, "npm run dev"));
+        assert!(command_landed(capture, ""));
+        assert!(!command_landed(capture, "cargo watch"));
+    }
+
+    #[test]
+    fn sessions_are_read_with_their_clients() {
+        let windows = vec![Window::from_interface("dev|:|0|:|@1|:|80|:|24|:||:|editor").unwrap()];
+        let sessions = sessions_from("2|:|dev\n0|:|api\n", windows);
+        assert_eq!(
+            sessions
+                .iter()
+                .map(|s| (s.name.as_str(), s.attached(), s.window_count()))
+                .collect::<Vec<_>>(),
+            [("dev", 2, 1), ("api", 0, 0)]
+        );
+    }
+
+    #[test]
+    fn windows_named_finds_every_duplicate() {
+        let windows = vec![
+            Window::from_interface("dev|:|4|:|@9|:|80|:|24|:||:|server").unwrap(),
+            Window::from_interface("dev|:|0|:|@1|:|80|:|24|:||:|editor").unwrap(),
+            Window::from_interface("dev|:|2|:|@5|:|80|:|24|:||:|server").unwrap(),
+        ];
+        let session = Session {
+            name: "dev".to_owned(),
+            attached: 0,
+            windows,
+        };
+        assert_eq!(session.window_count(), 3);
+        assert_eq!(session.window_names(), ["server", "editor", "server"]);
+        let servers = session.find_windows("server");
+        assert_eq!(
+            servers.iter().map(|w| w.id()).collect::<Vec<_>>(),
+            vec![Some("@5"), Some("@9")]
+        );
+    }
+
+    // A stand in for tmux that writes down what it was asked to do, then
+    // runs `script`. Returns the directory it's in, to be removed after, and
+    // the file it writes to.
+    #[cfg(unix)]
+    fn fake_tmux(name: &str, script: &str) -> (Tmux, PathBuf, PathBuf) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("dmux-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (fake, log) = (dir.join("tmux"), dir.join("log"));
+        std::fs::write(
+            &fake,
+            format!(
+                "#!/bin/sh\necho \"$@\" >> '{}'\n{}",
+                log.display(),
+                script.replace("$LOG", &format!("'{}'", log.display()))
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut tmux = Tmux::new();
+        tmux.command.bin(fake.to_string_lossy().into_owned());
+        (tmux, dir, log)
+    }
+
+    // Runs `test` against a throwaway server, with $TMUX pointing at it so
+    // attaching is a harmless switch-client rather than taking over the
+    // terminal running the tests. The environment is shared, so these run
+    // one at a time, and not at all without tmux.
+    #[cfg(unix)]
+    pub(crate) fn with_server(test: impl FnOnce(&Tmux, &Path)) {
+        use std::os::unix::fs::DirBuilderExt;
+        use std::sync::Mutex;
+
+        static SERVER: Mutex<()> = Mutex::new(());
+        let _server = SERVER.lock().unwrap_or_else(|e| e.into_inner());
+        let tmux = Tmux::new();
+        if !tmux.has_tmux() {
+            return;
+        }
+
+        let dir = std::env::temp_dir().join(format!("dmux-test-{}", std::process::id()));
+        // tmux only makes its socket directory when $TMUX isn't set
+        let socket = dir.join(format!("tmux-{}", unsafe { libc::getuid() }));
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&socket)
+            .unwrap();
+        std::env::set_var("TMUX_TMPDIR", &dir);
+        std::env::set_var("TMUX", format!("{}/default,0,0", socket.display()));
+        std::env::remove_var("TMUX_PANE");
+        // $TMUX has to name the running server for attaching to switch
+        tmux.run(&tmux.command.new_session().detached().session_name("idle").0)
+            .unwrap();
+        let pid = tmux.run(&server_pid_command(&tmux)).unwrap().to_string();
+        std::env::set_var(
+            "TMUX",
+            format!("{}/default,{},0", socket.display(), pid.trim()),
+        );
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| test(&tmux, &dir)));
+        tmux.kill_server().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        if let Err(panic) = result {
+            std::panic::resume_unwind(panic);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn setup_reuses_what_is_open_unless_fresh() {
+        with_server(|tmux, dir| {
+            let workspace = WorkSpace {
+                path: dir.to_owned(),
+                session_name: "fresh".to_owned(),
+                format_checksum: "tiled".to_owned(),
+                window_name: Some("old".to_owned()),
+                number_of_panes: 1,
+                detached: true,
+                ..Default::default()
+            };
+            let created = tmux.setup_workspace(workspace.clone()).unwrap();
+            assert!(created.session_created && created.window_created);
+            let reused = tmux.setup_workspace(workspace.clone()).unwrap();
+            assert!(!reused.session_created && !reused.window_created);
+            assert_eq!(reused.target, created.target);
+            assert_eq!(tmux.windows("fresh").unwrap().len(), 1);
+
+            tmux.setup_workspace(WorkSpace {
+                window_name: Some("new".to_owned()),
+                fresh: true,
+                ..workspace
+            })
+            .unwrap();
+            let windows: Vec<String> = tmux
+                .windows("fresh")
+                .unwrap()
+                .into_iter()
+                .map(|w| w.name)
+                .collect();
+            assert_eq!(windows, ["new"]);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn the_active_window_is_selected_before_attaching() {
+        with_server(|tmux, dir| {
+            let workspace = WorkSpace {
+                path: dir.to_owned(),
+                session_name: "landing".to_owned(),
+                format_checksum: "tiled".to_owned(),
+                window_name: Some("editor".to_owned()),
+                number_of_panes: 1,
+                detached: true,
+                windows: vec![
+                    WindowSpec {
+                        name: "server".to_owned(),
+                        number_of_panes: 1,
+                        ..Default::default()
+                    },
+                    WindowSpec {
+                        name: "logs".to_owned(),
+                        number_of_panes: 1,
+                        ..Default::default()
+                    },
+                ],
+                active_window: Some("server".to_owned()),
+                ..Default::default()
+            };
+            let active = || {
+                let mut list_windows = tmux.command.list_windows();
+                list_windows
+                    .target_session("landing")
+                    .format("#{window_active} #{window_name}");
+                let listed = tmux.run(&list_windows.0).unwrap().to_string();
+                listed
+                    .lines()
+                    .find_map(|line| line.strip_prefix("1 "))
+                    .map(str::to_owned)
+            };
+
+            let outcome = tmux.setup_workspace(workspace.clone()).unwrap();
+            let windows = tmux.windows("landing").unwrap();
+            assert_eq!(outcome.target, windows[1].target());
+            assert_eq!(active().as_deref(), Some("server"));
+
+            // without it, the first window defined is landed on every time
+            let outcome = tmux
+                .setup_workspace(WorkSpace {
+                    active_window: None,
+                    ..workspace.clone()
+                })
+                .unwrap();
+            assert_eq!(outcome.target, windows[0].target());
+            assert_eq!(active().as_deref(), Some("editor"));
+
+            let err = tmux
+                .setup_workspace(WorkSpace {
+                    active_window: Some("nope".to_owned()),
+                    ..workspace
+                })
+                .unwrap_err();
+            assert!(err
+                .to_string()
+                .contains("isn't one of the workspace's windows"));
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sessions_start_in_the_expanded_dir() {
+        with_server(|tmux, dir| {
+            std::fs::create_dir(dir.join("project")).unwrap();
+            std::env::set_var("DMUX_TEST_WORKSPACES", dir);
+            let outcome = tmux
+                .setup_workspace(WorkSpace {
+                    path: PathBuf::from("$DMUX_TEST_WORKSPACES/project"),
+                    session_name: "expanded".to_owned(),
+                    format_checksum: "tiled".to_owned(),
+                    number_of_panes: 1,
+                    detached: true,
+                    ..Default::default()
+                })
+                .unwrap();
+            std::env::remove_var("DMUX_TEST_WORKSPACES");
+
+            let panes = tmux.panes(&outcome.target).unwrap();
+            let first = panes.iter().min_by_key(|p| p.index).unwrap();
+            assert_eq!(
+                first
+                    .current_path()
+                    .map(|path| path.canonicalize().unwrap()),
+                Some(dir.join("project").canonicalize().unwrap())
+            );
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn renumbering_closes_the_gap_a_killed_window_left() {
+        with_server(|tmux, dir| {
+            let workspace = WorkSpace {
+                path: dir.to_owned(),
+                session_name: "gappy".to_owned(),
+                format_checksum: "tiled".to_owned(),
+                window_name: Some("editor".to_owned()),
+                number_of_panes: 1,
+                detached: true,
+                windows: vec![
+                    WindowSpec {
+                        name: "server".to_owned(),
+                        number_of_panes: 1,
+                        ..Default::default()
+                    },
+                    WindowSpec {
+                        name: "logs".to_owned(),
+                        number_of_panes: 1,
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            };
+            tmux.setup_workspace(workspace.clone()).unwrap();
+            let mut session = tmux.find_session("gappy").unwrap().unwrap();
+            let indexes = |session: &Session| -> Vec<usize> {
+                session.iter_windows().map(|w| w.index).collect()
+            };
+            let first = indexes(&session)[0];
+            session.find_windows("server")[0].kill(tmux).unwrap();
+            assert_eq!(
+                tmux.windows("gappy").unwrap().last().unwrap().index,
+                first + 2
+            );
+
+            session.renumber_windows(tmux).unwrap();
+            assert_eq!(indexes(&session), [first, first + 1]);
+            assert_eq!(session.window_names(), ["editor", "logs"]);
+
+            // setup renumbers an open session before landing on its window
+            session
+                .create_window(tmux, "scratch", Some(dir), None, false)
+                .unwrap();
+            session.find_windows("logs")[0].kill(tmux).unwrap();
+            let outcome = tmux
+                .setup_workspace(WorkSpace {
+                    renumber_windows: true,
+                    active_window: Some("editor".to_owned()),
+                    ..workspace
+                })
+                .unwrap();
+            // the windows it opens again go after the ones that moved up
+            let session = tmux.find_session("gappy").unwrap().unwrap();
+            assert_eq!(indexes(&session), [first, first + 1, first + 2, first + 3]);
+            assert_eq!(&session.window_names()[..2], ["editor", "scratch"]);
+            assert!(tmux.target_exists(&outcome.target));
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn workspaces_open_their_other_windows_too() {
+        with_server(|tmux, dir| {
+            std::fs::create_dir(dir.join("backend")).unwrap();
+            let workspace = WorkSpace {
+                path: dir.to_owned(),
+                session_name: "multi".to_owned(),
+                format_checksum: "tiled".to_owned(),
+                window_name: Some("editor".to_owned()),
+                number_of_panes: 1,
+                detached: true,
+                windows: vec![
+                    WindowSpec {
+                        name: "server".to_owned(),
+                        dir: Some(PathBuf::from("backend")),
+                        layout: Some("even-horizontal".to_owned()),
+                        number_of_panes: 2,
+                        ..Default::default()
+                    },
+                    WindowSpec {
+                        name: "logs".to_owned(),
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            };
+            let outcome = tmux.setup_workspace(workspace.clone()).unwrap();
+            tmux.setup_workspace(workspace.clone()).unwrap();
+
+            let windows = tmux.windows("multi").unwrap();
+            let names: Vec<&str> = windows.iter().map(|w| w.name.as_str()).collect();
+            assert_eq!(names, ["editor", "server", "logs"]);
+            assert_eq!(outcome.target, windows[0].target());
+            let server = windows[1].iter_panes().collect::<Vec<_>>();
+            assert_eq!(server.len(), 2);
+            assert_eq!(
+                server[0].current_path().map(|p| p.canonicalize().unwrap()),
+                Some(dir.join("backend").canonicalize().unwrap())
+            );
+
+            // a mistake in any window stops everything before it's opened
+            let mut broken = WorkSpace {
+                session_name: "broken".to_owned(),
+                ..workspace
+            };
+            broken.windows[1].layout = Some("nonsense".to_owned());
+            assert!(tmux.setup_workspace(broken).is_err());
+            assert!(tmux.find_session("broken").unwrap().is_none());
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn split_trees_lay_panes_out_by_size() {
+        with_server(|tmux, dir| {
+            std::fs::create_dir(dir.join("api")).unwrap();
+            let workspace = WorkSpace {
+                path: dir.to_owned(),
+                session_name: "tree".to_owned(),
+                format_checksum: "tiled".to_owned(),
+                window_name: Some("dev".to_owned()),
+                detached: true,
+                panes: vec![
+                    PaneSpec::default(),
+                    PaneSpec::default(),
+                    PaneSpec {
+                        dir: Some(PathBuf::from("api")),
+                        ..Default::default()
+                    },
+                ],
+                splits: Some(SplitTree {
+                    split: Some(SplitDirection::Horizontal),
+                    children: vec![
+                        SplitTree {
+                            size: Some(70),
+                            ..Default::default()
+                        },
+                        SplitTree {
+                            split: Some(SplitDirection::Vertical),
+                            children: vec![SplitTree::default(), SplitTree::default()],
+                            ..Default::default()
+                        },
+                    ],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let outcome = tmux.setup_workspace(workspace).unwrap();
+            let panes = tmux.panes(&outcome.target).unwrap();
+            assert_eq!(panes.len(), 3);
+
+            // the two columns and the border between them
+            let width = panes[0].width().unwrap() + 1 + panes[1].width().unwrap();
+            let main = panes[0].width().unwrap() as f64 / width as f64;
+            assert!((0.65..0.75).contains(&main), "main pane is {} wide", main);
+            assert_eq!(panes[1].left(), panes[2].left());
+            assert!(panes[1].top() < panes[2].top());
+            assert_eq!(
+                panes[2].current_path().unwrap().canonicalize().unwrap(),
+                dir.join("api").canonicalize().unwrap()
+            );
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn panes_start_in_their_own_directories() {
+        with_server(|tmux, dir| {
+            for project in ["web", "api"] {
+                std::fs::create_dir(dir.join(project)).unwrap();
+            }
+            let workspace = WorkSpace {
+                path: dir.to_owned(),
+                session_name: "mono".to_owned(),
+                format_checksum: "tiled".to_owned(),
+                window_name: Some("dev".to_owned()),
+                number_of_panes: 3,
+                detached: true,
+                panes: vec![
+                    PaneSpec {
+                        dir: Some(PathBuf::from("web")),
+                        ..Default::default()
+                    },
+                    PaneSpec {
+                        dir: Some(PathBuf::from("api")),
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            };
+            let outcome = tmux.setup_workspace(workspace.clone()).unwrap();
+            let dirs: Vec<PathBuf> = tmux
+                .panes(&outcome.target)
+                .unwrap()
+                .iter()
+                .map(|p| p.current_path().unwrap().canonicalize().unwrap())
+                .collect();
+            let expected: Vec<PathBuf> = ["web", "api", ""]
+                .iter()
+                .map(|project| dir.join(project).canonicalize().unwrap())
+                .collect();
+            assert_eq!(dirs, expected);
+
+            let missing = WorkSpace {
+                session_name: "missing".to_owned(),
+                panes: vec![PaneSpec {
+                    dir: Some(PathBuf::from("nowhere")),
+                    ..Default::default()
+                }],
+                ..workspace
+            };
+            assert!(tmux.setup_workspace(missing).is_err());
+        });
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn panes_get_the_workspace_environment_and_their_own() {
+        with_server(|tmux, dir| {
+            let env = |name: &str, value: &str| (name.to_owned(), value.to_owned());
+            let workspace = WorkSpace {
+                path: dir.to_owned(),
+                session_name: "env".to_owned(),
+                format_checksum: "tiled".to_owned(),
+                window_name: Some("dev".to_owned()),
+                number_of_panes: 2,
+                detached: true,
+                env: vec![env("APP", "dmux"), env("RUST_LOG", "info")],
+                panes: vec![
+                    PaneSpec::default(),
+                    PaneSpec {
+                        env: vec![env("RUST_LOG", "debug")],
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            };
+            let outcome = tmux.setup_workspace(workspace.clone()).unwrap();
+
+            // what each pane's shell was started with
+            let started_with: Vec<Vec<String>> = tmux
+                .panes(&outcome.target)
+                .unwrap()
+                .iter()
+                .map(|pane| {
+                    let environ =
+                        std::fs::read(format!("/proc/{}/environ", pane.pid().unwrap())).unwrap();
+                    String::from_utf8_lossy(&environ)
+                        .split('\0')
+                        .filter(|v| v.starts_with("APP=") || v.starts_with("RUST_LOG="))
+                        .map(str::to_owned)
+                        .collect()
+                })
+                .collect();
+            assert_eq!(
+                started_with,
+                [
+                    ["APP=dmux", "RUST_LOG=info"],
+                    ["APP=dmux", "RUST_LOG=debug"]
+                ]
+            );
+
+            let bad = WorkSpace {
+                session_name: "bad-env".to_owned(),
+                env: vec![env("A=B", "c")],
+                ..workspace
+            };
+            assert!(tmux.setup_workspace(bad).is_err());
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failed_setup_kills_only_the_session_it_created() {
+        with_server(|tmux, dir| {
+            let broken = WorkSpace {
+                path: dir.to_owned(),
+                session_name: "broken".to_owned(),
+                format_checksum: "tiled".to_owned(),
+                window_name: Some("dev".to_owned()),
+                number_of_panes: 2,
+                options: vec![(Scope::Window, "no-such-option".to_owned(), "on".to_owned())],
+                detached: true,
+                ..Default::default()
+            };
+            assert!(tmux.setup_workspace(broken.clone()).is_err());
+            assert!(tmux.find_session("broken").unwrap().is_none());
+
+            assert!(tmux
+                .setup_workspace(WorkSpace {
+                    session_name: "idle".to_owned(),
+                    ..broken
+                })
+                .is_err());
+            assert!(tmux.find_session("idle").unwrap().is_some());
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ready_runs_before_attaching_and_can_stop_it() {
+        with_server(|tmux, dir| {
+            let workspace = WorkSpace {
+                path: dir.to_owned(),
+                session_name: "hooked".to_owned(),
+                format_checksum: "tiled".to_owned(),
+                window_name: Some("dev".to_owned()),
+                number_of_panes: 2,
+                detached: true,
+                ..Default::default()
+            };
+            let mut panes = None;
+            let outcome = tmux
+                .setup_workspace_then(workspace.clone(), &mut || {
+                    panes = Some(tmux.panes("hooked:dev")?.len());
+                    Ok(())
+                })
+                .unwrap();
+            assert_eq!(panes, Some(2));
+
+            let err = tmux
+                .setup_workspace_then(workspace, &mut || Err(anyhow!("hook failed")))
+                .unwrap_err();
+            assert_eq!(err.to_string(), "hook failed");
+            assert_eq!(tmux.panes(&outcome.target).unwrap().len(), 2);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failed_splits_roll_the_session_back() {
+        with_server(|tmux, dir| {
+            // far more panes than a detached 80x24 window has room for
+            let workspace = WorkSpace {
+                path: dir.to_owned(),
+                session_name: "cramped".to_owned(),
+                format_checksum: "tiled".to_owned(),
+                window_name: Some("dev".to_owned()),
+                number_of_panes: 200,
+                detached: true,
+                ..Default::default()
+            };
+            let err = tmux.setup_workspace(workspace).unwrap_err();
+            assert!(err.to_string().starts_with("Couldn't split"), "{}", err);
+            assert!(tmux.find_session("cramped").unwrap().is_none());
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejected_layouts_fail_setup() {
+        with_server(|tmux, dir| {
+            // a well formed checksum, but for a single pane
+            let body = "80x24,0,0,0";
+            let workspace = WorkSpace {
+                path: dir.to_owned(),
+                session_name: "crammed".to_owned(),
+                format_checksum: format!("{:04x},{}", layout_checksum(body), body),
+                window_name: Some("dev".to_owned()),
+                number_of_panes: 3,
+                detached: true,
+                ..Default::default()
+            };
+            let err = tmux.setup_workspace(workspace).unwrap_err();
+            assert!(err.to_string().starts_with("Couldn't apply layout"));
+            assert!(tmux.find_session("crammed").unwrap().is_none());
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn detached_workspaces_are_set_up_without_attaching() {
+        with_server(|tmux, dir| {
+            let mut events = vec![];
+            let outcome = tmux
+                .setup_workspace_with(
+                    WorkSpace {
+                        path: dir.to_owned(),
+                        session_name: "prepped.site".to_owned(),
+                        format_checksum: "tiled".to_owned(),
+                        window_name: Some("dev".to_owned()),
+                        number_of_panes: 3,
+                        detached: true,
+                        ..Default::default()
+                    },
+                    &mut |event| events.push(event),
+                )
+                .unwrap();
+            assert!(outcome.session_created);
+            assert_eq!(outcome.session, "prepped_site");
+            assert!(!events
+                .iter()
+                .any(|e| matches!(e, SetupEvent::Attaching { .. })));
+            assert_eq!(tmux.panes(&outcome.target).unwrap().len(), 3);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn workspaces_and_targets_are_killed() {
+        with_server(|tmux, dir| {
+            let workspace = WorkSpace {
+                path: dir.to_owned(),
+                session_name: "doomed".to_owned(),
+                format_checksum: "tiled".to_owned(),
+                window_name: Some("dev".to_owned()),
+                number_of_panes: 1,
+                keep_initial_window: true,
+                windows: vec![WindowSpec {
+                    name: "logs".to_owned(),
+                    ..Default::default()
+                }],
+                detached: true,
+                ..Default::default()
+            };
+            tmux.setup_workspace(workspace.clone()).unwrap();
+            let session = tmux.find_session("doomed").unwrap().unwrap();
+            assert_eq!(session.window_count(), 3);
+
+            tmux.kill_target("doomed:logs").unwrap();
+            // the scratch window dmux didn't open for it is left
+            tmux.kill_workspace(workspace.clone()).unwrap();
+            let session = tmux.find_session("doomed").unwrap().unwrap();
+            assert_eq!(session.window_count(), 1);
+            assert!(tmux.kill_workspace(workspace).is_err());
+
+            assert!(tmux.kill_target("doomed:nope").is_err());
+            tmux.kill_target("doomed").unwrap();
+            assert_eq!(tmux.find_session("doomed").unwrap(), None);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn forced_commands_are_resent_only_from_the_workspace_window() {
+        with_server(|tmux, dir| {
+            let workspace = WorkSpace {
+                path: dir.to_owned(),
+                session_name: "rerun".to_owned(),
+                format_checksum: "tiled".to_owned(),
+                window_name: Some("dev".to_owned()),
+                number_of_panes: 2,
+                commands: vec!["true".to_owned(), "true".to_owned()],
+                force_commands: true,
+                detached: true,
+                ..Default::default()
+            };
+            tmux.setup_workspace(workspace.clone()).unwrap();
+            let resent = |pane: String| {
+                std::env::set_var("TMUX_PANE", pane);
+                let mut sent = 0;
+                tmux.setup_workspace_with(workspace.clone(), &mut |event| {
+                    if let SetupEvent::CommandSent { .. } = event {
+                        sent += 1;
+                    }
+                })
+                .unwrap();
+                std::env::remove_var("TMUX_PANE");
+                sent
+            };
+
+            let idle = tmux.panes("idle").unwrap().remove(0);
+            assert_eq!(resent(idle.id().unwrap().to_owned()), 0);
+            let own = tmux.panes("rerun:dev").unwrap().remove(1);
+            assert_eq!(resent(own.id().unwrap().to_owned()), 2);
+            assert_eq!(tmux.panes("rerun:dev").unwrap().len(), 2);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reapplying_adds_missing_panes_and_restarts_idle_ones() {
+        with_server(|tmux, dir| {
+            // a shell that starts quickly enough to see sleep running in it
+            let shell = set_option_command(Scope::Global, "default-shell", "/bin/sh", "", tmux);
+            tmux.run(&shell).unwrap();
+            let mut workspace = WorkSpace {
+                path: dir.to_owned(),
+                session_name: "grown".to_owned(),
+                format_checksum: "even-horizontal".to_owned(),
+                window_name: Some("dev".to_owned()),
+                number_of_panes: 2,
+                commands: vec!["sleep 30".to_owned(), "true".to_owned()],
+                detached: true,
+                ..Default::default()
+            };
+            tmux.setup_workspace(workspace.clone()).unwrap();
+            let started = Instant::now();
+            while tmux.panes("grown:dev").unwrap()[0].current_command() != Some("sleep") {
+                assert!(started.elapsed() < Duration::from_secs(5));
+                thread::sleep(Duration::from_millis(50));
+            }
+
+            let reapplied = |workspace: &WorkSpace| {
+                let mut events = vec![];
+                tmux.setup_workspace_with(workspace.clone(), &mut |event| events.push(event))
+                    .unwrap();
+                events
+            };
+            workspace.number_of_panes = 3;
+            workspace.commands.push("true".to_owned());
+            // without it the open window is left as it is
+            assert!(reapplied(&workspace).is_empty());
+
+            workspace.reapply = true;
+            let events = reapplied(&workspace);
+            assert_eq!(tmux.panes("grown:dev").unwrap().len(), 3);
+            assert!(events.contains(&SetupEvent::LayoutApplied {
+                window: "dev".to_owned(),
+                layout: "even-horizontal".to_owned(),
+            }));
+            let sent = |events: &[SetupEvent]| -> Vec<u8> {
+                events
+                    .iter()
+                    .filter_map(|event| match event {
+                        SetupEvent::CommandSent { pane, .. } => Some(*pane),
+                        _ => None,
+                    })
+                    .collect()
+            };
+            assert_eq!(sent(&events), [2]);
+
+            workspace.reapply_commands = true;
+            assert_eq!(sent(&reapplied(&workspace)), [1, 2]);
+            assert_eq!(tmux.panes("grown:dev").unwrap().len(), 3);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn commands_wait_for_slow_shells_to_show_a_prompt() {
+        with_server(|tmux, dir| {
+            use std::os::unix::fs::PermissionsExt;
+
+            let slow = dir.join("slow-sh");
+            std::fs::write(&slow, "#!/bin/sh\nsleep 1\nexec /bin/sh \"$@\"\n").unwrap();
+            std::fs::set_permissions(&slow, std::fs::Permissions::from_mode(0o755)).unwrap();
+            let shell = set_option_command(
+                Scope::Global,
+                "default-shell",
+                slow.to_str().unwrap(),
+                "",
+                tmux,
+            );
+            tmux.run(&shell).unwrap();
+            let workspace = WorkSpace {
+                path: dir.to_owned(),
+                session_name: "slow".to_owned(),
+                format_checksum: "tiled".to_owned(),
+                number_of_panes: 1,
+                commands: vec!["echo $((6 * 7))".to_owned()],
+                wait_for_prompt: Some(Duration::from_secs(5)),
+                detached: true,
+                ..Default::default()
+            };
+            let started = Instant::now();
+            tmux.setup_workspace(workspace).unwrap();
+            assert!(started.elapsed() >= Duration::from_secs(1));
+
+            let window = &tmux.windows("slow").unwrap()[0];
+            let pane = tmux.panes(&window.target()).unwrap().remove(0);
+            let sent = ["echo $((6 * 7))".to_owned()];
+            assert!(tmux
+                .verify_commands(window, &sent, VERIFY_WAIT)
+                .unwrap()
+                .is_empty());
+            assert_eq!(
+                tmux.wait_for_prompts(window, vec![pane.clone()], Duration::ZERO)
+                    .unwrap(),
+                Vec::<u8>::new()
+            );
+
+            let mut session = tmux.find_session("slow").unwrap().unwrap();
+            let blank = session
+                .create_window(tmux, "blank", None, None, false)
+                .unwrap();
+            let pane = tmux.panes(&blank.target()).unwrap().remove(0);
+            assert_eq!(
+                tmux.wait_for_prompts(&blank, vec![pane], Duration::from_millis(200))
+                    .unwrap(),
+                [0]
+            );
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn panes_get_their_titles() {
+        with_server(|tmux, dir| {
+            let titled = |title: &str| PaneSpec {
+                title: Some(title.to_owned()),
+                ..Default::default()
+            };
+            let workspace = WorkSpace {
+                path: dir.to_owned(),
+                session_name: "titled".to_owned(),
+                format_checksum: "tiled".to_owned(),
+                window_name: Some("dev".to_owned()),
+                number_of_panes: 3,
+                panes: vec![titled("editor"), PaneSpec::default(), titled("tests")],
+                detached: true,
+                ..Default::default()
+            };
+            tmux.setup_workspace(workspace.clone()).unwrap();
+            let titles = |target: &str| -> Vec<Option<String>> {
+                tmux.panes(target)
+                    .unwrap()
+                    .iter()
+                    .map(|pane| pane.title().map(str::to_owned))
+                    .collect()
+            };
+            assert_eq!(
+                titles("titled:dev"),
+                [Some("editor".to_owned()), None, Some("tests".to_owned())]
+            );
+
+            let tree = WorkSpace {
+                window_name: Some("tree".to_owned()),
+                splits: Some(SplitTree::main_vertical(2)),
+                panes: vec![PaneSpec::default(), titled("server")],
+                ..workspace
+            };
+            tmux.setup_workspace(tree).unwrap();
+            assert_eq!(titles("titled:tree"), [None, Some("server".to_owned())]);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn synchronized_panes_still_get_their_own_commands() {
+        with_server(|tmux, dir| {
+            let shell = set_option_command(Scope::Global, "default-shell", "/bin/sh", "", tmux);
+            tmux.run(&shell).unwrap();
+            let workspace = WorkSpace {
+                path: dir.to_owned(),
+                session_name: "synced".to_owned(),
+                format_checksum: "tiled".to_owned(),
+                number_of_panes: 2,
+                commands: vec![
+                    "echo first-$((0 + 1))".to_owned(),
+                    "echo second-$((0 + 2))".to_owned(),
+                ],
+                synchronize: true,
+                verify_commands: true,
+                detached: true,
+                ..Default::default()
+            };
+            tmux.setup_workspace(workspace).unwrap();
+            let window = &tmux.windows("synced").unwrap()[0];
+            let panes = tmux.panes(&window.target()).unwrap();
+            tmux.send_keys(window, &panes[0], vec!["echo both-$((1 + 2))", "Enter"])
+                .unwrap();
+            let both = ["both-3".to_owned(), "both-3".to_owned()];
+            assert!(tmux
+                .verify_commands(window, &both, VERIFY_WAIT)
+                .unwrap()
+                .is_empty());
+            assert!(!tmux
+                .capture_pane(window, &panes[0])
+                .unwrap()
+                .contains("second-2"));
+            assert!(!tmux
+                .capture_pane(window, &panes[1])
+                .unwrap()
+                .contains("first-1"));
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn panes_are_found_from_the_servers_index_bases() {
+        with_server(|tmux, dir| {
+            for (name, value) in [("base-index", "1"), ("pane-base-index", "1")] {
+                let command = set_option_command(Scope::Global, name, value, "", tmux);
+                checked(tmux.run(&command).unwrap(), name.to_owned()).unwrap();
+            }
+            let shell = set_option_command(Scope::Global, "default-shell", "/bin/sh", "", tmux);
+            tmux.run(&shell).unwrap();
+            let workspace = WorkSpace {
+                path: dir.to_owned(),
+                session_name: "counted".to_owned(),
+                format_checksum: "tiled".to_owned(),
+                number_of_panes: 2,
+                commands: vec![
+                    "echo one-$((0 + 1))".to_owned(),
+                    "echo two-$((0 + 2))".to_owned(),
+                ],
+                panes: vec![PaneSpec {
+                    title: Some("first".to_owned()),
+                    ..Default::default()
+                }],
+                verify_commands: true,
+                detached: true,
+                ..Default::default()
+            };
+            let mut sent = vec![];
+            tmux.setup_workspace_with(workspace, &mut |event| match event {
+                SetupEvent::CommandSent { pane, .. } => sent.push(pane),
+                SetupEvent::CommandUnverified { command, .. } => panic!("{} got lost", command),
+                _ => {}
+            })
+            .unwrap();
+            assert_eq!(sent, [1, 2]);
+            assert_eq!(
+                tmux.index_bases("counted"),
+                IndexBases { window: 1, pane: 1 }
+            );
+
+            let window = &tmux.windows("counted").unwrap()[0];
+            assert_eq!(window.index, 1);
+            let panes = tmux.panes(&window.target()).unwrap();
+            assert_eq!(panes[0].title(), Some("first"));
+            assert!(!tmux
+                .capture_pane(window, &panes[1])
+                .unwrap()
+                .contains("one-1"));
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn new_windows_start_in_the_session_directory() {
+        with_server(|tmux, dir| {
+            let project = dir.join("project");
+            std::fs::create_dir(&project).unwrap();
+            let mut session = tmux.find_session("idle").unwrap().unwrap();
+            session.set_default_directory(tmux, &project).unwrap();
+
+            let window = session
+                .create_window(tmux, "inherited", None, None, false)
+                .unwrap();
+            let pane = &tmux.panes(&window.target()).unwrap()[0];
+            assert_eq!(
+                pane.current_path().map(|p| p.canonicalize().unwrap()),
+                Some(project.canonicalize().unwrap())
+            );
+            assert!(session
+                .set_default_directory(tmux, &dir.join("missing"))
+                .is_err());
+        });
+    }
+
+    #[cfg(all(unix, feature = "serde"))]
+    #[test]
+    fn the_whole_tree_round_trips_through_json() {
+        with_server(|tmux, dir| {
+            let outcome = tmux
+                .setup_workspace(WorkSpace {
+                    path: dir.to_owned(),
+                    session_name: "tree".to_owned(),
+                    format_checksum: "even-horizontal".to_owned(),
+                    window_name: Some("dev".to_owned()),
+                    number_of_panes: 2,
+                    detached: true,
+                    ..Default::default()
+                })
+                .unwrap();
+
+            let sessions: Vec<Session> =
+                serde_json::from_str(&Tmux::to_json(&tmux.sessions().unwrap()).unwrap()).unwrap();
+            assert_eq!(sessions, tmux.sessions().unwrap());
+            let window = &sessions
+                .iter()
+                .find(|s| s.name == "tree")
+                .unwrap()
+                .windows()[0];
+            assert_eq!(
+                window.layout(),
+                Some(tmux.layout(&outcome.target).unwrap().as_str())
+            );
+            assert_eq!(window.panes.len(), 2);
+            assert!(window.panes[0].current_path().is_some());
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn swapped_panes_trade_indexes() {
+        with_server(|tmux, dir| {
+            let mut session = tmux.find_session("idle").unwrap().unwrap();
+            let window = session
+                .create_window(tmux, "swap", Some(dir), None, false)
+                .unwrap();
+            tmux.run(&tmux.command.split_window().target_pane(window.target()).0)
+                .unwrap();
+            let mut window = tmux.find_windows("idle", "swap").unwrap().remove(0);
+            let before: Vec<(u8, String)> = window
+                .iter_panes()
+                .map(|p| (p.index, p.id().unwrap().to_owned()))
+                .collect();
+            let (first, second) = (before[0].0, before[1].0);
+
+            window.swap_panes(tmux, first, second).unwrap();
+            let after: Vec<(u8, String)> = window
+                .iter_panes()
+                .map(|p| (p.index, p.id().unwrap().to_owned()))
+                .collect();
+            assert_eq!(
+                after,
+                vec![(first, before[1].1.clone()), (second, before[0].1.clone())]
+            );
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn layouts_are_copied_from_another_window() {
+        with_server(|tmux, dir| {
+            let mut session = tmux.find_session("idle").unwrap().unwrap();
+            let mut template = session
+                .create_window(tmux, "template", Some(dir), None, false)
+                .unwrap();
+            let side = Split {
+                direction: SplitDirection::Horizontal,
+                before: false,
+                full: false,
+            };
+            template.split(tmux, side).unwrap();
+            template
+                .split(
+                    tmux,
+                    Split {
+                        direction: SplitDirection::Vertical,
+                        ..side
+                    },
+                )
+                .unwrap();
+
+            let mut copy = session
+                .create_window(tmux, "copy", Some(dir), None, false)
+                .unwrap();
+            copy.apply_layout_from(tmux, &template.target()).unwrap();
+            let geometry = |window: &Window| {
+                tmux.panes(&window.target())
+                    .unwrap()
+                    .iter()
+                    .map(|p| (p.left(), p.top(), p.width(), p.height()))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(copy.iter_panes().count(), 3);
+            assert_eq!(geometry(&copy), geometry(&template));
+
+            // panes aren't killed to make a layout fit
+            assert!(template.apply_layout_from(tmux, &copy.target()).is_ok());
+            copy.split(tmux, side).unwrap();
+            assert!(copy.apply_layout_from(tmux, &template.target()).is_err());
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejected_layouts_are_errors() {
+        with_server(|tmux, dir| {
+            let mut session = tmux.find_session("idle").unwrap().unwrap();
+            let mut window = session
+                .create_window(tmux, "stack", Some(dir), None, false)
+                .unwrap();
+            let side = Split {
+                direction: SplitDirection::Horizontal,
+                before: false,
+                full: false,
+            };
+            window.split(tmux, side).unwrap();
+
+            let stacked = Layout::from_checksum("even-vertical").unwrap();
+            window.select_layout(tmux, &stacked).unwrap();
+            let panes = tmux.panes(&window.target()).unwrap();
+            assert_eq!(panes[0].left(), panes[1].left());
+
+            // a well formed checksum, but for a single pane
+            let body = "80x24,0,0,0";
+            let single =
+                Layout::from_checksum(&format!("{:04x},{}", layout_checksum(body), body)).unwrap();
+            let err = window.select_layout(tmux, &single).unwrap_err();
+            assert!(err.to_string().starts_with("Couldn't apply layout"));
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn full_splits_span_the_window() {
+        with_server(|tmux, dir| {
+            let mut session = tmux.find_session("idle").unwrap().unwrap();
+            let mut window = session
+                .create_window(tmux, "dashboard", Some(dir), None, false)
+                .unwrap();
+            let side = Split {
+                direction: SplitDirection::Horizontal,
+                before: false,
+                full: false,
+            };
+            window.split(tmux, side).unwrap();
+            let log = window
+                .split(
+                    tmux,
+                    Split {
+                        direction: SplitDirection::Vertical,
+                        before: false,
+                        full: true,
+                    },
+                )
+                .unwrap();
+            let width = tmux.find_windows("idle", "dashboard").unwrap()[0].width();
+            assert_eq!(log.width(), width);
+            assert_eq!(window.position_map().last(), Some(&log.index));
+
+            // a pane put before the others takes the first index
+            let top = window
+                .split(
+                    tmux,
+                    Split {
+                        direction: SplitDirection::Vertical,
+                        before: true,
+                        full: true,
+                    },
+                )
+                .unwrap();
+            let indexes: Vec<u8> = window.iter_panes().map(|p| p.index).collect();
+            assert_eq!(window.iter_panes().next().unwrap().id(), top.id());
+            assert_eq!(indexes.len(), 4);
+            assert_eq!(top.index, indexes[0]);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sessions_list_their_clients() {
+        with_server(|tmux, _| {
+            let session = tmux.find_session("idle").unwrap().unwrap();
+            assert_eq!(session.clients(tmux).unwrap(), vec![]);
+
+            // a control client is as attached as any other
+            let controlled = Tmux::with_config(TmuxConfig {
+                control_mode: true,
+                ..Default::default()
+            });
+            let clients = session.clients(&controlled).unwrap();
+            assert_eq!(clients.len(), 1);
+            assert!(!clients[0].name.is_empty());
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn control_mode_runs_commands_and_reconnects() {
+        with_server(|_, dir| {
+            let tmux = Tmux::with_config(TmuxConfig {
+                control_mode: true,
+                ..Default::default()
+            });
+            let mut session = tmux.find_session("idle").unwrap().unwrap();
+            assert!(tmux.control.lock().unwrap().is_some());
+
+            let window = session
+                .create_window(&tmux, "control", Some(dir), None, false)
+                .unwrap();
+            let pane = window.iter_panes().next().unwrap().clone();
+            tmux.send_keys(&window, &pane, vec!["echo \"$HOME\" 'x'"])
+                .unwrap();
+            // the keys show up once the pane's shell has started
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while !tmux
+                .capture_pane(&window, &pane)
+                .unwrap()
+                .contains("echo \"$HOME\" 'x'")
+            {
+                assert!(Instant::now() < deadline, "the keys never showed up");
+                thread::sleep(Duration::from_millis(50));
+            }
+            assert!(!tmux.target_exists("missing"));
+
+            // the control client goes along with the session it's attached
+            // to, and the next command connects again
+            let mut other = tmux.command.new_session();
+            other.detached().session_name("other");
+            checked(tmux.run(&other.0).unwrap(), "new-session".into()).unwrap();
+            let mut kill = tmux.command.kill_session();
+            kill.target_session(session.name.as_str());
+            checked(tmux.run(&kill.0).unwrap(), "kill-session".into()).unwrap();
+            let names: Vec<String> = tmux
+                .sessions()
+                .unwrap()
+                .into_iter()
+                .map(|s| s.name)
+                .collect();
+            assert_eq!(names, vec!["other"]);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn waiting_for_a_session_gives_up_after_the_timeout() {
+        with_server(|tmux, _| {
+            assert_eq!(tmux.wait_for_session("idle").unwrap().name, "idle");
+
+            let impatient = Tmux::with_config(TmuxConfig {
+                timeout: Duration::from_millis(200),
+                ..Default::default()
+            });
+            let err = impatient.wait_for_session("missing").unwrap_err();
+            assert!(matches!(
+                err.downcast_ref(),
+                Some(TmuxError::SessionNotReady { .. })
+            ));
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn attaching_to_the_session_dmux_runs_in_switches_instead() {
+        with_server(|tmux, _| {
+            let pane = tmux.panes("idle").unwrap().remove(0);
+            let window = tmux.windows("idle").unwrap().remove(0);
+            // a $TMUX naming some other server makes dmux think it isn't in
+            // a client, while its pane is in the session it's attaching to
+            let env = std::env::var("TMUX").unwrap();
+            let (socket, _) = env.split_once(',').unwrap();
+            std::env::set_var("TMUX", format!("{},1,0", socket));
+            std::env::set_var("TMUX_PANE", pane.target(&window));
+
+            // there's no client to switch, but tmux wasn't asked to nest
+            let err = tmux.attach("idle").unwrap_err();
+            std::env::remove_var("TMUX_PANE");
+            assert!(err.to_string().starts_with("Couldn't switch to idle"));
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn picked_targets_lead_back_to_their_window() {
+        with_server(|tmux, dir| {
+            let mut session = tmux.find_session("idle").unwrap().unwrap();
+            let window = session
+                .create_window(tmux, "my app.v2", Some(dir), None, false)
+                .unwrap();
+            let targets = tmux.pickable_targets().unwrap();
+            assert_eq!(targets.len(), 2);
+            assert!(targets.contains(&"idle:my-app-v2".to_owned()));
+
+            // there's no client to switch, but the picked name was resolved
+            let err = tmux.attach_target("idle:my-app-v2\n").unwrap_err();
+            assert!(err
+                .to_string()
+                .starts_with(&format!("Couldn't switch to {}", window.id().unwrap())));
+            let err = tmux.attach_target("idle:nothing").unwrap_err();
+            assert_eq!(err.to_string(), "There's nothing open at idle:nothing");
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn attaching_to_a_missing_session_says_so() {
+        with_server(|tmux, _| {
+            let err = tmux.attach("missing").unwrap_err();
+            assert_eq!(err.to_string(), "There's no tmux session named missing");
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn respawned_windows_come_back_with_one_live_pane() {
+        with_server(|tmux, dir| {
+            let mut session = tmux.find_session("idle").unwrap().unwrap();
+            let mut window = session
+                .create_window(tmux, "respawn", Some(dir), None, false)
+                .unwrap();
+            for _ in 0..2 {
+                tmux.run(&tmux.command.split_window().target_pane(window.target()).0)
+                    .unwrap();
+            }
+            assert_eq!(tmux.panes(&window.target()).unwrap().len(), 3);
+
+            window.respawn(tmux, None).unwrap();
+            let panes: Vec<&Pane> = window.iter_panes().collect();
+            assert_eq!(panes.len(), 1);
+            let pid = panes[0].pid().unwrap();
+            assert!(Path::new(&format!("/proc/{}", pid)).exists());
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reset_windows_keep_their_place_and_take_the_new_layout() {
+        with_server(|tmux, dir| {
+            let shell = set_option_command(Scope::Global, "default-shell", "/bin/sh", "", tmux);
+            tmux.run(&shell).unwrap();
+            let mut session = tmux.find_session("idle").unwrap().unwrap();
+            let mut window = session
+                .create_window(tmux, "reset", Some(dir), None, false)
+                .unwrap();
+            for _ in 0..2 {
+                tmux.run(&tmux.command.split_window().target_pane(window.target()).0)
+                    .unwrap();
+            }
+            assert_eq!(tmux.panes(&window.target()).unwrap().len(), 3);
+            let (id, index) = (window.id().map(str::to_owned), window.index);
+
+            let workspace = WorkSpace {
+                path: dir.to_owned(),
+                session_name: "idle".to_owned(),
+                format_checksum: "even-horizontal".to_owned(),
+                number_of_panes: 2,
+                commands: vec![
+                    "echo left-$((0 + 1))".to_owned(),
+                    "echo right-$((0 + 2))".to_owned(),
+                ],
+                detached: true,
+                ..Default::default()
+            };
+            window.reset(tmux, &workspace).unwrap();
+            assert_eq!(window.id().map(str::to_owned), id);
+            assert_eq!(window.index, index);
+            let panes: Vec<Pane> = window.iter_panes().cloned().collect();
+            assert_eq!(panes.len(), 2);
+
+            let ran = ["left-1".to_owned(), "right-2".to_owned()];
+            assert!(tmux
+                .verify_commands(&window, &ran, VERIFY_WAIT)
+                .unwrap()
+                .is_empty());
+            for (pane, output) in panes.iter().zip(ran) {
+                assert!(tmux.capture_pane(&window, pane).unwrap().contains(&output));
+            }
+        });
     }
 }
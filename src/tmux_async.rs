@@ -0,0 +1,1176 @@
+// AsyncTmux::setup_workspace
+// AsyncTmux::sessions
+// AsyncTmux::send_keys
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use tmux_interface::{TmuxCommand, TmuxOutput};
+
+use crate::tmux::{
+    attach_command, border_options, check_border_option, check_layout_fits, check_sent,
+    check_window_size, checked, command_landed, command_lines, command_panes,
+    create_window_command, created_window, current_session_command, current_window_command,
+    first_line, in_client, index_bases_command, kill_session_command, move_window_command,
+    nested_attach, new_session_command, no_server, pane_at, pane_title_command, pickable_target,
+    prompt_panes, prompt_shown, rename_session_command, renumber_windows_command,
+    replaced_session_name, report_no_prompt, report_unverified, select_window_command,
+    send_line_command, server_pid_command, sessions_from, set_option_command, split_window_command,
+    target_exists_command, transient, tree_split_command, typed_keys_commands, warn,
+    window_size_command, windows_with_panes, with_post_attach, IndexBases, Key, Pane, Scope,
+    Session, SetupEvent, SetupOutcome, SplitTree, Tmux, TmuxConfig, TmuxError, Window, WorkSpace,
+    PANE_FORMAT, SESSION_FORMAT, VERIFY_WAIT,
+};
+
+/// The same operations as `Tmux`, run on tokio's process driver so many
+/// sessions can be managed at once without a thread per tmux call.
+#[derive(Debug, Default)]
+pub struct AsyncTmux {
+    tmux: Tmux,
+}
+
+impl AsyncTmux {
+    pub fn new() -> AsyncTmux {
+        AsyncTmux::with_config(TmuxConfig::default())
+    }
+
+    pub fn with_config(config: TmuxConfig) -> AsyncTmux {
+        AsyncTmux {
+            tmux: Tmux::with_config(config),
+        }
+    }
+
+    /// Runs a tmux command, killing it if it outlives the configured timeout.
+    async fn run(&self, command: &TmuxCommand<'_>) -> Result<TmuxOutput> {
+        let mut process = tokio::process::Command::from(self.tmux.process(command));
+        debug!("running {:?}", process);
+        // the output future owns the child, so timing out drops and kills it
+        process.kill_on_drop(true);
+        match tokio::time::timeout(self.tmux.config.timeout, process.output()).await {
+            Ok(output) => Ok(TmuxOutput(output?)),
+            Err(_) => {
+                warn!("killing {:?} after {:?}", process, self.tmux.config.timeout);
+                Err(TmuxError::Timeout {
+                    command: format!("{:?}", self.tmux.process(command)),
+                    after: self.tmux.config.timeout,
+                }
+                .into())
+            }
+        }
+    }
+
+    // Same as the sync `run_retrying`.
+    async fn run_retrying(&self, command: &TmuxCommand<'_>) -> Result<TmuxOutput> {
+        let retry = self.tmux.config.retry;
+        let mut retries = 0;
+        loop {
+            let output = self.run(command).await?;
+            if retries + 1 >= retry.attempts || !transient(&output) {
+                return Ok(output);
+            }
+            debug!(
+                "tmux server unreachable, retrying in {:?}",
+                retry.wait(retries)
+            );
+            tokio::time::sleep(retry.wait(retries)).await;
+            retries += 1;
+        }
+    }
+
+    /// Same as `Tmux::target_exists`.
+    pub async fn target_exists(&self, target: &str) -> bool {
+        self.run(&target_exists_command(target, &self.tmux))
+            .await
+            .is_ok_and(|output| output.0.status.success())
+    }
+
+    /// Sends keys to the given pane of a window.
+    pub async fn send_keys(
+        &self,
+        window: &Window,
+        pane: &Pane,
+        keys: Vec<&str>,
+    ) -> Result<TmuxOutput> {
+        self.send_keys_to_target(&pane.target(window), keys).await
+    }
+
+    /// Same as `Tmux::send_keys_typed`.
+    pub async fn send_keys_typed(
+        &self,
+        window: &Window,
+        pane: &Pane,
+        keys: Vec<Key>,
+    ) -> Result<()> {
+        let target = pane.target(window);
+        debug!("sending {:?} to {}", keys, target);
+        for send_keys in typed_keys_commands(&target, &keys, &self.tmux) {
+            checked(
+                self.run(&send_keys).await?,
+                format!("Couldn't send keys to {}", target),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Sends keys to an already formed target, such as `session:window.1`
+    /// or a pane id like `%12`.
+    pub async fn send_keys_to_target(&self, target: &str, keys: Vec<&str>) -> Result<TmuxOutput> {
+        debug!("sending {:?} to {}", keys, target);
+        let mut send_keys = self.tmux.command.send_keys();
+        send_keys.target_pane(target);
+        for key in keys {
+            send_keys.key(key);
+        }
+        self.run(&send_keys.0).await
+    }
+
+    /// Lists the panes of the window `target` points at.
+    pub async fn panes(&self, target: &str) -> Result<Vec<Pane>> {
+        let output = self
+            .run_retrying(
+                &self
+                    .tmux
+                    .command
+                    .list_panes()
+                    .target(target)
+                    .format(PANE_FORMAT)
+                    .0,
+            )
+            .await?;
+        checked(output, format!("Couldn't list panes for {}", target))?
+            .to_string()
+            .lines()
+            .map(Pane::from_interface)
+            .collect()
+    }
+
+    /// Every session on the server, with their windows and panes. No
+    /// sessions are reported when the server isn't running.
+    pub async fn sessions(&self) -> Result<Vec<Session>> {
+        let output = self
+            .run_retrying(&self.tmux.command.list_sessions().format(SESSION_FORMAT).0)
+            .await?;
+        if no_server(&output) {
+            return Ok(vec![]);
+        }
+        let listed = checked(output, "Couldn't list sessions".to_string())?.to_string();
+        Ok(sessions_from(&listed, self.list_windows(None).await?))
+    }
+
+    /// Finds the session with exactly this name.
+    pub async fn find_session(&self, name: &str) -> Result<Option<Session>> {
+        Ok(self.sessions().await?.into_iter().find(|s| s.name == name))
+    }
+
+    /// Same as `Tmux::wait_for_session`.
+    pub async fn wait_for_session(&self, name: &str) -> Result<Session> {
+        let timeout = self.tmux.config.timeout;
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(session) = self
+                .find_session(name)
+                .await?
+                .filter(|s| s.window_count() > 0)
+            {
+                return Ok(session);
+            }
+            if Instant::now() >= deadline {
+                return Err(TmuxError::SessionNotReady {
+                    session: name.to_owned(),
+                    after: timeout,
+                }
+                .into());
+            }
+            debug!("waiting for session {}", name);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    /// Same as `Tmux::attach`.
+    pub async fn attach(&self, session_name: &str) -> Result<()> {
+        if self.find_session(session_name).await?.is_none() {
+            return Err(anyhow!("There's no tmux session named {}", session_name));
+        }
+        self.attach_to(session_name).await
+    }
+
+    /// Same as `Tmux::pickable_targets`.
+    pub async fn pickable_targets(&self) -> Result<Vec<String>> {
+        Ok(self
+            .sessions()
+            .await?
+            .iter()
+            .flat_map(Session::iter_windows)
+            .map(pickable_target)
+            .collect())
+    }
+
+    /// Same as `Tmux::attach_target`.
+    pub async fn attach_target(&self, selected: &str) -> Result<()> {
+        let selected = selected.trim();
+        let target = self
+            .sessions()
+            .await?
+            .iter()
+            .flat_map(Session::iter_windows)
+            .find(|w| pickable_target(w) == selected)
+            .map_or_else(|| selected.to_owned(), Window::target);
+        if !self.target_exists(&target).await {
+            return Err(anyhow!("There's nothing open at {}", selected));
+        }
+        self.attach_to(&target).await
+    }
+
+    async fn attach_to(&self, target: &str) -> Result<()> {
+        let client = in_client(&self.run(&server_pid_command(&self.tmux)).await.ok())
+            || nested_attach(self.pane_session().await.as_deref(), target);
+        let attach = attach_command(target, client, &self.tmux);
+        info!("attaching to {}", target);
+        if client {
+            checked(
+                self.run(&attach).await?,
+                format!("Couldn't switch to {}", target),
+            )?;
+        } else if !tokio::process::Command::from(self.tmux.process(&attach))
+            .status()
+            .await?
+            .success()
+        {
+            return Err(anyhow!("Couldn't attach to {}", target));
+        }
+        Ok(())
+    }
+
+    /// Lists the windows of a session, along with their panes.
+    pub async fn windows(&self, session_name: &str) -> Result<Vec<Window>> {
+        self.list_windows(Some(session_name)).await
+    }
+
+    /// Finds every window of a session with the given name, ordered by index.
+    pub async fn find_windows(&self, session_name: &str, name: &str) -> Result<Vec<Window>> {
+        Ok(self
+            .find_session(session_name)
+            .await?
+            .map(|session| session.find_windows(name).into_iter().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn list_windows(&self, session_name: Option<&str>) -> Result<Vec<Window>> {
+        let (list_windows, list_panes) = self.tmux.list_windows_commands(session_name);
+        let target = session_name.unwrap_or("all sessions");
+        let windows = checked(
+            self.run_retrying(&list_windows).await?,
+            format!("Couldn't list windows for {}", target),
+        )?;
+        let panes = checked(
+            self.run_retrying(&list_panes).await?,
+            format!("Couldn't list panes for {}", target),
+        )?;
+        windows_with_panes(&windows.to_string(), &panes.to_string())
+    }
+
+    /// Opens the workspace the same way `Tmux::setup_workspace` does.
+    pub async fn setup_workspace(&self, workspace: WorkSpace) -> Result<SetupOutcome> {
+        self.setup_workspace_with(workspace, &mut |_| {}).await
+    }
+
+    /// Same as `setup_workspace`, reporting each step to `observer` as it
+    /// completes so a UI can show progress.
+    pub async fn setup_workspace_with(
+        &self,
+        workspace: WorkSpace,
+        observer: &mut (dyn FnMut(SetupEvent) + Send),
+    ) -> Result<SetupOutcome> {
+        self.setup(workspace, observer, &mut || Ok(())).await
+    }
+
+    /// Same as `Tmux::setup_workspace_then`.
+    pub async fn setup_workspace_then(
+        &self,
+        workspace: WorkSpace,
+        ready: &mut (dyn FnMut() -> Result<()> + Send),
+    ) -> Result<SetupOutcome> {
+        self.setup(workspace, &mut |_| {}, ready).await
+    }
+
+    // Same as the sync `setup_workspace`.
+    async fn setup(
+        &self,
+        workspace: WorkSpace,
+        observer: &mut (dyn FnMut(SetupEvent) + Send),
+        ready: &mut (dyn FnMut() -> Result<()> + Send),
+    ) -> Result<SetupOutcome> {
+        let mut workspace = workspace.normalized()?;
+        let tmux = &self.tmux;
+        let replaced = if workspace.fresh {
+            self.replace_session(&workspace).await?
+        } else {
+            None
+        };
+        let mut existing = self.find_session(&workspace.session_name).await?;
+        if existing.is_some() && workspace.renumber_windows {
+            debug!("renumbering the windows of {}", workspace.session_name);
+            checked(
+                self.run(&renumber_windows_command(&workspace.session_name, tmux))
+                    .await?,
+                format!("Couldn't renumber windows of {}", workspace.session_name),
+            )?;
+            existing = self.find_session(&workspace.session_name).await?;
+        }
+        let session_created = existing.is_none();
+        let window = if let Some(session) = existing {
+            if let Some(window) = workspace.window_in(&session, false) {
+                info!("{} is already open", window.target());
+                if workspace.reapply {
+                    info!("reapplying the workspace to {}", window.target());
+                    self.reapply_window(&workspace, window, observer).await?;
+                } else if workspace.force_commands
+                    && window.id().is_some()
+                    && self.pane_window().await.as_deref() == window.id()
+                {
+                    info!("sending the commands to {} again", window.target());
+                    self.send_commands(&workspace, window, observer).await?;
+                }
+                self.setup_other_windows(&workspace, observer).await?;
+                let target = self
+                    .attach_to_window(&workspace, window, observer, ready)
+                    .await?;
+                self.kill_replaced(replaced).await?;
+                return Ok(SetupOutcome {
+                    session_created: false,
+                    window_created: false,
+                    session: workspace.session_name,
+                    target,
+                });
+            }
+            info!("creating window {}", workspace.window_slot());
+            let created = self.create_window(&workspace).await?;
+            let window = self
+                .setup_panes_with_commands(&workspace, created, observer)
+                .await?;
+            self.setup_other_windows(&workspace, observer).await?;
+            window
+        } else {
+            info!(
+                "creating session {} in {}",
+                workspace.session_name,
+                workspace.path_str()
+            );
+            let new_session = self
+                .run_retrying(&new_session_command(&workspace, tmux))
+                .await?;
+            workspace.created_session(new_session)?;
+            match self.setup_created_session(&workspace, observer).await {
+                Ok(window) => window,
+                Err(err) => {
+                    self.roll_back_session(&workspace.session_name, replaced.as_deref())
+                        .await;
+                    return Err(err);
+                }
+            }
+        };
+
+        let target = self
+            .attach_to_window(&workspace, &window, observer, ready)
+            .await?;
+        self.kill_replaced(replaced).await?;
+        Ok(SetupOutcome {
+            session_created,
+            window_created: true,
+            session: workspace.session_name,
+            target,
+        })
+    }
+
+    // Same as the sync `setup_created_session`.
+    async fn setup_created_session(
+        &self,
+        workspace: &WorkSpace,
+        observer: &mut (dyn FnMut(SetupEvent) + Send),
+    ) -> Result<Window> {
+        let tmux = &self.tmux;
+        self.wait_for_session(&workspace.session_name).await?;
+        observer(SetupEvent::SessionCreated {
+            session: workspace.session_name.clone(),
+        });
+        self.apply_options(
+            workspace,
+            &[Scope::Global, Scope::Session],
+            &workspace.session_name,
+        )
+        .await?;
+
+        let mut created = None;
+        if workspace.keep_initial_window {
+            info!("creating window {}", workspace.window_slot());
+            created = self.create_window(workspace).await?;
+        } else if let Some(index) = workspace.window_index {
+            // the first window starts at base-index, which might not be
+            // the index the workspace asked for
+            let session = self.find_session(&workspace.session_name).await?;
+            if let Some(first) = session
+                .as_ref()
+                .and_then(|s| s.iter_windows().find(|w| w.index != index))
+            {
+                debug!("moving {} to index {}", first.target(), index);
+                checked(
+                    self.run(&move_window_command(workspace, first, tmux))
+                        .await?,
+                    format!("Couldn't move {} to index {}", first.target(), index),
+                )?;
+            }
+        }
+
+        let window = self
+            .setup_panes_with_commands(workspace, created, observer)
+            .await?;
+        self.setup_other_windows(workspace, observer).await?;
+        Ok(window)
+    }
+
+    // Same as the sync `setup_other_windows`.
+    async fn setup_other_windows(
+        &self,
+        workspace: &WorkSpace,
+        observer: &mut (dyn FnMut(SetupEvent) + Send),
+    ) -> Result<()> {
+        for other in workspace.window_workspaces()? {
+            let session = self.find_session(&other.session_name).await?;
+            if let Some(window) = session.as_ref().and_then(|s| other.window_in(s, false)) {
+                info!("{} is already open", window.target());
+                if other.reapply {
+                    self.reapply_window(&other, window, observer).await?;
+                }
+                continue;
+            }
+            info!("creating window {}", other.window_slot());
+            let created = self.create_window(&other).await?;
+            self.setup_panes_with_commands(&other, created, observer)
+                .await?;
+        }
+        Ok(())
+    }
+
+    // Same as the sync `setup_panes_with_commands`.
+    async fn setup_panes_with_commands(
+        &self,
+        workspace: &WorkSpace,
+        created: Option<Window>,
+        observer: &mut (dyn FnMut(SetupEvent) + Send),
+    ) -> Result<Window> {
+        let tmux = &self.tmux;
+        let window = match created {
+            Some(window) => window,
+            // the window was just created, so it's the newest one with this
+            // name
+            None => self
+                .find_session(&workspace.session_name)
+                .await?
+                .and_then(|session| workspace.window_in(&session, true).cloned())
+                .ok_or_else(|| {
+                    anyhow!("Couldn't find window {}", workspace.target_session(None))
+                })?,
+        };
+        observer(SetupEvent::WindowCreated {
+            session: window.session_name.clone(),
+            window: window.name.clone(),
+            index: window.index,
+        });
+        self.apply_options(workspace, &[Scope::Window], &window.target())
+            .await?;
+        let target = window.target();
+        for (name, value) in border_options(workspace) {
+            let command = set_option_command(Scope::Window, name, value, &target, tmux);
+            check_border_option(name, &self.run(&command).await.ok());
+        }
+
+        if let Some(splits) = &workspace.splits {
+            let targets = self
+                .split_by_tree(workspace, splits, &window, observer)
+                .await?;
+            self.title_panes(workspace, &window, Some(targets)).await?;
+            self.send_commands(workspace, &window, observer).await?;
+            return Ok(window);
+        }
+        debug!(
+            "splitting {} into {} panes",
+            window.target(),
+            workspace.number_of_panes()
+        );
+        for panes in 2..=workspace.number_of_panes() {
+            checked(
+                self.run(&split_window_command(workspace, &window, panes - 1, tmux))
+                    .await?,
+                format!("Couldn't split {}", window.target()),
+            )?;
+            observer(SetupEvent::PaneSplit {
+                window: window.name.clone(),
+                panes,
+                of: workspace.number_of_panes(),
+            });
+        }
+
+        debug!(
+            "applying layout {} to {}",
+            workspace.format_checksum,
+            window.target()
+        );
+        self.select_layout(workspace, &window).await?;
+        observer(SetupEvent::LayoutApplied {
+            window: window.name.clone(),
+            layout: workspace.format_checksum.clone(),
+        });
+        self.title_panes(workspace, &window, None).await?;
+        self.send_commands(workspace, &window, observer).await?;
+        Ok(window)
+    }
+
+    // Same as the sync `create_window`.
+    async fn create_window(&self, workspace: &WorkSpace) -> Result<Option<Window>> {
+        Ok(created_window(checked(
+            self.run(&create_window_command(workspace, &self.tmux))
+                .await?,
+            format!("Couldn't create window {}", workspace.window_slot()),
+        )?))
+    }
+
+    // Same as `Window::select_layout`, warning about a layout that might
+    // not fit once it's applied. The main pane option is already among the
+    // workspace's options.
+    async fn select_layout(&self, workspace: &WorkSpace, window: &Window) -> Result<()> {
+        debug!(
+            "applying layout {} to {}",
+            workspace.format_checksum,
+            window.target()
+        );
+        checked(
+            self.run(
+                &self
+                    .tmux
+                    .command
+                    .select_layout()
+                    .target_pane(window.target())
+                    .layout_name(&workspace.format_checksum)
+                    .0,
+            )
+            .await?,
+            format!(
+                "Couldn't apply layout {} to {}",
+                workspace.format_checksum, window.name
+            ),
+        )?;
+        check_layout_fits(workspace, window);
+        Ok(())
+    }
+
+    // Same as the sync `roll_back_session`.
+    async fn roll_back_session(&self, name: &str, replaced: Option<&str>) {
+        warn!("setting up session {} failed, killing it", name);
+        let killed = self.run(&kill_session_command(name, &self.tmux)).await;
+        if !killed.is_ok_and(|output| output.success()) {
+            warn!("couldn't kill session {}", name);
+        }
+        if let Some(replaced) = replaced {
+            info!("renaming session {} back to {}", replaced, name);
+            let renamed = self
+                .run(&rename_session_command(replaced, name, &self.tmux))
+                .await;
+            if !renamed.is_ok_and(|output| output.success()) {
+                warn!("couldn't rename session {} back to {}", replaced, name);
+            }
+        }
+    }
+
+    // Same as the sync `split_by_tree`.
+    async fn split_by_tree(
+        &self,
+        workspace: &WorkSpace,
+        splits: &SplitTree,
+        window: &Window,
+        observer: &mut (dyn FnMut(SetupEvent) + Send),
+    ) -> Result<Vec<String>> {
+        let mut targets = vec![None; splits.pane_count()];
+        targets[0] = self
+            .panes(&window.target())
+            .await?
+            .first()
+            .map(|pane| pane.target(window));
+        for (done, step) in splits.plan().iter().enumerate() {
+            let target = targets[step.target]
+                .clone()
+                .ok_or_else(|| anyhow!("Couldn't find pane {} of {}", step.target, window.name))?;
+            debug!("splitting {} with {:?}", target, step);
+            let output = checked(
+                self.run(&tree_split_command(workspace, step, &target, &self.tmux))
+                    .await?,
+                format!("Couldn't split pane {} of {}", step.target, window.name),
+            )?;
+            targets[step.pane] = Some(output.to_string().trim_end().to_owned());
+            observer(SetupEvent::PaneSplit {
+                window: window.name.clone(),
+                panes: done as u8 + 2,
+                of: workspace.number_of_panes(),
+            });
+        }
+        Ok(targets.into_iter().flatten().collect())
+    }
+
+    // Same as the sync `title_panes`.
+    async fn title_panes(
+        &self,
+        workspace: &WorkSpace,
+        window: &Window,
+        targets: Option<Vec<String>>,
+    ) -> Result<()> {
+        if workspace.panes.iter().all(|pane| pane.title.is_none()) {
+            return Ok(());
+        }
+        let targets = match targets {
+            Some(targets) => targets,
+            None => {
+                let panes = self.panes(&window.target()).await.unwrap_or_default();
+                let base = self.index_bases(&window.target()).await.pane;
+                (0..workspace.panes.len())
+                    .map(|nth| pane_at(&panes, nth, base).target(window))
+                    .collect()
+            }
+        };
+        for (nth, title) in workspace.pane_titles() {
+            let Some(target) = targets.get(nth) else {
+                continue;
+            };
+            debug!("titling {} {:?}", target, title);
+            checked(
+                self.run(&pane_title_command(target, title, &self.tmux))
+                    .await?,
+                format!("Couldn't set the title of pane {} of {}", nth, window.name),
+            )?;
+        }
+        Ok(())
+    }
+
+    // Same as the sync `reapply_window`.
+    async fn reapply_window(
+        &self,
+        workspace: &WorkSpace,
+        window: &Window,
+        observer: &mut (dyn FnMut(SetupEvent) + Send),
+    ) -> Result<()> {
+        let tmux = &self.tmux;
+        let open: Vec<u8> = self
+            .panes(&window.target())
+            .await?
+            .iter()
+            .map(|pane| pane.index)
+            .collect();
+        let of = workspace.number_of_panes();
+        if let Some(splits) = &workspace.splits {
+            if open.len() == 1 && of > 1 {
+                let targets = self
+                    .split_by_tree(workspace, splits, window, observer)
+                    .await?;
+                self.title_panes(workspace, window, Some(targets)).await?;
+            } else if open.len() < of.into() {
+                warn(format!(
+                    "{} is already split, so its splits aren't applied again",
+                    window.target()
+                ));
+            }
+        } else {
+            for panes in open.len() as u8 + 1..=of {
+                checked(
+                    self.run(&split_window_command(workspace, window, panes - 1, tmux))
+                        .await?,
+                    format!("Couldn't split {}", window.target()),
+                )?;
+                observer(SetupEvent::PaneSplit {
+                    window: window.name.clone(),
+                    panes,
+                    of,
+                });
+            }
+            self.select_layout(workspace, window).await?;
+            observer(SetupEvent::LayoutApplied {
+                window: window.name.clone(),
+                layout: workspace.format_checksum.clone(),
+            });
+            self.title_panes(workspace, window, None).await?;
+        }
+        let resend = |pane: &Pane| {
+            !open.contains(&pane.index) || (workspace.reapply_commands && pane.is_idle())
+        };
+        self.send_commands_where(workspace, window, observer, &resend)
+            .await
+    }
+
+    // Same as the sync `send_commands`.
+    async fn send_commands(
+        &self,
+        workspace: &WorkSpace,
+        window: &Window,
+        observer: &mut (dyn FnMut(SetupEvent) + Send),
+    ) -> Result<()> {
+        self.send_commands_where(workspace, window, observer, &|_| true)
+            .await
+    }
+
+    // Same as the sync `send_commands_where`.
+    async fn send_commands_where(
+        &self,
+        workspace: &WorkSpace,
+        window: &Window,
+        observer: &mut (dyn FnMut(SetupEvent) + Send),
+        keep: &(dyn Fn(&Pane) -> bool + Sync),
+    ) -> Result<()> {
+        // fall back to index based targets if the panes can't be listed
+        let (panes, commands): (Vec<Pane>, Vec<&String>) = command_panes(
+            workspace,
+            self.panes(&window.target()).await.unwrap_or_default(),
+            self.index_bases(&window.target()).await.pane,
+        )
+        .into_iter()
+        .zip(&workspace.commands)
+        .filter(|(pane, _)| keep(pane))
+        .unzip();
+        if let Some(wait) = workspace.wait_for_prompt {
+            let waiting = prompt_panes(&panes, &commands);
+            for pane in self.wait_for_prompts(window, waiting, wait).await? {
+                report_no_prompt(window, pane, wait);
+            }
+        }
+        // same as the sync `send_commands_where`, the window is only
+        // synchronized once each pane has its own command
+        if workspace.synchronize && !commands.is_empty() {
+            self.synchronize_panes(window, false).await?;
+        }
+        let mut last_lines = vec![];
+        for (pane, com) in panes.iter().zip(commands) {
+            let lines = command_lines(com)?;
+            for line in &lines {
+                let target = pane.target(window);
+                debug!("sending {:?} to {}", line, target);
+                let sent = self
+                    .run(&send_line_command(&target, line, &self.tmux))
+                    .await?;
+                check_sent(sent, window, pane, line)?;
+            }
+            observer(SetupEvent::CommandSent {
+                window: window.name.clone(),
+                pane: pane.index,
+                command: com.clone(),
+            });
+            last_lines.push(lines.last().cloned().unwrap_or_default());
+        }
+
+        if workspace.verify_commands {
+            let sent = panes.into_iter().zip(&last_lines).collect();
+            for (pane, command) in self.verify_sent(window, sent, VERIFY_WAIT).await? {
+                report_unverified(window, pane, command, observer);
+            }
+        }
+        if workspace.synchronize {
+            self.synchronize_panes(window, true).await?;
+        }
+        Ok(())
+    }
+
+    // Same as `Window::synchronize_panes`.
+    async fn synchronize_panes(&self, window: &Window, on: bool) -> Result<()> {
+        let target = window.target();
+        let value = if on { "on" } else { "off" };
+        let command = set_option_command(
+            Scope::Window,
+            "synchronize-panes",
+            value,
+            &target,
+            &self.tmux,
+        );
+        checked(
+            self.run(&command).await?,
+            format!(
+                "Couldn't set synchronize-panes to {:?} on {}",
+                value, window.name
+            ),
+        )?;
+        Ok(())
+    }
+
+    // Same as the sync `pane_session`.
+    async fn pane_session(&self) -> Option<String> {
+        let pane = std::env::var("TMUX_PANE").ok()?;
+        let output = self
+            .run(&current_session_command(&pane, &self.tmux))
+            .await
+            .ok()?;
+        first_line(output)
+    }
+
+    // Same as the sync `pane_window`.
+    async fn pane_window(&self) -> Option<String> {
+        let pane = std::env::var("TMUX_PANE").ok()?;
+        let output = self
+            .run(&current_window_command(&pane, &self.tmux))
+            .await
+            .ok()?;
+        first_line(output)
+    }
+
+    // Same as the sync `replace_session`.
+    async fn replace_session(&self, workspace: &WorkSpace) -> Result<Option<String>> {
+        let name = &workspace.session_name;
+        if self.find_session(name).await?.is_none() {
+            return Ok(None);
+        }
+        if self.pane_session().await.as_ref() == Some(name) {
+            let replaced = replaced_session_name(name);
+            info!(
+                "renaming session {} to {} until it's replaced",
+                name, replaced
+            );
+            checked(
+                self.run(&rename_session_command(name, &replaced, &self.tmux))
+                    .await?,
+                format!("Couldn't rename session {}", name),
+            )?;
+            Ok(Some(replaced))
+        } else {
+            info!("killing session {}", name);
+            checked(
+                self.run(&kill_session_command(name, &self.tmux)).await?,
+                format!("Couldn't kill session {}", name),
+            )?;
+            Ok(None)
+        }
+    }
+
+    async fn kill_replaced(&self, replaced: Option<String>) -> Result<()> {
+        if let Some(replaced) = replaced {
+            info!("killing session {}", replaced);
+            checked(
+                self.run(&kill_session_command(&replaced, &self.tmux))
+                    .await?,
+                format!("Couldn't kill session {}", replaced),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The visible contents of a pane, with wrapped lines joined back up.
+    pub async fn capture_pane(&self, window: &Window, pane: &Pane) -> Result<String> {
+        let output = self
+            .run(
+                &self
+                    .tmux
+                    .command
+                    .capture_pane()
+                    .stdout()
+                    .join()
+                    .target_pane(pane.target(window))
+                    .0,
+            )
+            .await?;
+        Ok(checked(
+            output,
+            format!("Couldn't capture pane {} of {}", pane.index, window.name),
+        )?
+        .to_string())
+    }
+
+    /// Same as `Tmux::verify_commands`.
+    pub async fn verify_commands(
+        &self,
+        window: &Window,
+        commands: &[String],
+        wait: Duration,
+    ) -> Result<Vec<(u8, String)>> {
+        let panes = self.panes(&window.target()).await.unwrap_or_default();
+        let base = self.index_bases(&window.target()).await.pane;
+        let sent = commands
+            .iter()
+            .enumerate()
+            .map(|(i, command)| (pane_at(&panes, i, base), command))
+            .collect();
+        self.verify_sent(window, sent, wait).await
+    }
+
+    /// Same as `Tmux::index_bases`, sharing what's been read with it.
+    pub async fn index_bases(&self, target: &str) -> IndexBases {
+        if let Some(bases) = *self.known_bases() {
+            return bases;
+        }
+        let read = self
+            .run(&index_bases_command(target, &self.tmux))
+            .await
+            .ok()
+            .and_then(first_line)
+            .and_then(|line| IndexBases::from_interface(&line));
+        debug!("index bases for {}: {:?}", target, read);
+        *self.known_bases() = read;
+        read.unwrap_or_default()
+    }
+
+    // the lock isn't held across an await
+    fn known_bases(&self) -> std::sync::MutexGuard<'_, Option<IndexBases>> {
+        self.tmux
+            .index_bases
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Same as the sync `wait_for_prompts`.
+    async fn wait_for_prompts(
+        &self,
+        window: &Window,
+        mut pending: Vec<Pane>,
+        wait: Duration,
+    ) -> Result<Vec<u8>> {
+        let deadline = Instant::now() + wait;
+        loop {
+            let mut still_pending = vec![];
+            for pane in pending {
+                if !prompt_shown(&self.capture_pane(window, &pane).await?) {
+                    still_pending.push(pane);
+                }
+            }
+            pending = still_pending;
+            if pending.is_empty() || Instant::now() >= deadline {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        Ok(pending.into_iter().map(|pane| pane.index).collect())
+    }
+
+    async fn verify_sent(
+        &self,
+        window: &Window,
+        mut pending: Vec<(Pane, &String)>,
+        wait: Duration,
+    ) -> Result<Vec<(u8, String)>> {
+        let deadline = Instant::now() + wait;
+        loop {
+            let mut still_pending = vec![];
+            for (pane, command) in pending {
+                if !command_landed(&self.capture_pane(window, &pane).await?, command) {
+                    still_pending.push((pane, command));
+                }
+            }
+            pending = still_pending;
+            if pending.is_empty() || Instant::now() >= deadline {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        Ok(pending
+            .into_iter()
+            .map(|(pane, command)| (pane.index, command.clone()))
+            .collect())
+    }
+
+    async fn apply_options(
+        &self,
+        workspace: &WorkSpace,
+        scopes: &[Scope],
+        target: &str,
+    ) -> Result<()> {
+        for (scope, name, value) in workspace.options_in(scopes) {
+            debug!(
+                "setting {:?} option {} to {:?} on {}",
+                scope, name, value, target
+            );
+            checked(
+                self.run(&set_option_command(*scope, name, value, target, &self.tmux))
+                    .await?,
+                format!("Couldn't set {} to {:?}", name, value),
+            )?;
+        }
+        Ok(())
+    }
+
+    // attaching blocks until the client detaches, so it isn't held to the
+    // timeout
+    async fn attach_to_window(
+        &self,
+        workspace: &WorkSpace,
+        window: &Window,
+        observer: &mut (dyn FnMut(SetupEvent) + Send),
+        ready: &mut (dyn FnMut() -> Result<()> + Send),
+    ) -> Result<String> {
+        let own = workspace.window_target(window);
+        if let Some(size) = workspace.window_size {
+            let output = self
+                .run(&window_size_command(size, &own, &self.tmux))
+                .await
+                .ok();
+            check_window_size(size, &output);
+        }
+        let target = match workspace.other_active_window() {
+            Some(name) => self
+                .find_windows(&workspace.session_name, name)
+                .await?
+                .first()
+                .map(Window::target)
+                .unwrap_or_else(|| format!("{}:{}", workspace.session_name, name)),
+            None => own,
+        };
+        checked(
+            self.run(&select_window_command(&target, &self.tmux))
+                .await?,
+            format!("Couldn't select window {}", target),
+        )?;
+        ready()?;
+        if workspace.detached {
+            info!("leaving {} detached", target);
+            return Ok(target);
+        }
+        info!("attaching to {}", target);
+        observer(SetupEvent::Attaching {
+            target: target.clone(),
+        });
+        let client = self.tmux.config.host.is_none()
+            && (in_client(&self.run(&server_pid_command(&self.tmux)).await.ok())
+                || nested_attach(self.pane_session().await.as_deref(), &target));
+        let attach = with_post_attach(
+            attach_command(&target, client, &self.tmux),
+            &workspace.post_attach,
+        );
+        if self.tmux.dry_run(&attach).is_some() {
+            return Ok(target);
+        }
+        if client {
+            checked(
+                self.run(&attach).await?,
+                "Couldn't switch client".to_string(),
+            )?;
+        } else if !tokio::process::Command::from(self.tmux.process(&attach))
+            .status()
+            .await?
+            .success()
+        {
+            return Err(anyhow!("Couldn't attach to {}", target));
+        }
+        Ok(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use crate::tmux::tests::with_server;
+    #[cfg(unix)]
+    use std::{future::Future, path::PathBuf};
+
+    // Runs `test` against the sync tests' throwaway server, which the async
+    // client finds the same way `Tmux::new` does.
+    #[cfg(unix)]
+    fn with_async_server<F: Future<Output = ()>>(test: impl FnOnce(AsyncTmux, PathBuf) -> F) {
+        with_server(|_, dir| {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(test(AsyncTmux::new(), dir.to_owned()))
+        });
+    }
+
+    #[cfg(unix)]
+    fn two_windows(dir: PathBuf) -> WorkSpace {
+        WorkSpace {
+            path: dir,
+            session_name: "async".to_owned(),
+            format_checksum: "tiled".to_owned(),
+            window_name: Some("editor".to_owned()),
+            number_of_panes: 2,
+            windows: vec![crate::tmux::WindowSpec {
+                name: "server".to_owned(),
+                number_of_panes: 1,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn setup_opens_the_workspace_then_reuses_it() {
+        with_async_server(|tmux, dir| async move {
+            let workspace = WorkSpace {
+                detached: true,
+                ..two_windows(dir)
+            };
+            let outcome = tmux.setup_workspace(workspace.clone()).await.unwrap();
+            assert!(outcome.session_created && outcome.window_created);
+            assert_eq!(outcome.session, "async");
+            assert!(tmux.target_exists(&outcome.target).await);
+            let windows = tmux.windows("async").await.unwrap();
+            let names: Vec<&str> = windows.iter().map(|w| w.name.as_str()).collect();
+            assert_eq!(names, ["editor", "server"]);
+            assert_eq!(tmux.panes(&windows[0].target()).await.unwrap().len(), 2);
+
+            let again = tmux.setup_workspace(workspace).await.unwrap();
+            assert!(!again.session_created && !again.window_created);
+            assert_eq!(again.target, outcome.target);
+            assert_eq!(tmux.windows("async").await.unwrap().len(), 2);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ready_runs_before_attaching_and_its_error_stops_it() {
+        with_async_server(|tmux, dir| async move {
+            let mut calls = 0;
+            let err = tmux
+                .setup_workspace_then(two_windows(dir), &mut || {
+                    calls += 1;
+                    Err(anyhow!("hook failed"))
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(calls, 1);
+            assert_eq!(err.to_string(), "hook failed");
+            // the workspace is left open, only attaching is skipped
+            assert!(tmux.target_exists("async:editor").await);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dry_runs_leave_the_client_where_it_is() {
+        with_async_server(|tmux, dir| async move {
+            let workspace = two_windows(dir);
+            tmux.setup_workspace(WorkSpace {
+                detached: true,
+                ..workspace.clone()
+            })
+            .await
+            .unwrap();
+
+            // there's no client to switch, so switching for real would fail
+            let dry = AsyncTmux::with_config(TmuxConfig {
+                dry_run: true,
+                ..Default::default()
+            });
+            let outcome = dry.setup_workspace(workspace).await.unwrap();
+            assert!(tmux.target_exists(&outcome.target).await);
+        });
+    }
+
+    #[tokio::test]
+    async fn run_kills_commands_that_outlive_the_timeout() {
+        let tmux = AsyncTmux::with_config(TmuxConfig {
+            timeout: Duration::from_millis(50),
+            ..Default::default()
+        });
+        let mut sleep = TmuxCommand::new();
+        sleep.bin("sleep").cmd("5");
+
+        let started = Instant::now();
+        let err = tmux.run(&sleep).await.unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(matches!(
+            err.downcast_ref::<TmuxError>(),
+            Some(TmuxError::Timeout { .. })
+        ));
+    }
+}
@@ -0,0 +1,233 @@
+// WezTerm::open
+// workspace_panes
+
+use anyhow::Result;
+
+use crate::multiplexer::{
+    cli_runs, run_cli, send_commands, split_panes, warn_env_ignored, Multiplexer,
+};
+use crate::tmux::{SplitDirection, WorkSpace};
+
+/// Opens workspaces in WezTerm's own multiplexer through `wezterm cli`, so
+/// tmux isn't needed at all. A dmux session is a WezTerm workspace, its
+/// windows are tabs, and the panes are split the way `splits` says, or with
+/// a main pane on the left when there's no tree. Layout checksums are tmux's
+/// own, so they're left out.
+#[derive(Debug, Clone)]
+pub struct WezTerm {
+    pub(crate) bin: String,
+}
+
+impl Default for WezTerm {
+    fn default() -> Self {
+        WezTerm {
+            bin: "wezterm".to_owned(),
+        }
+    }
+}
+
+impl WezTerm {
+    pub fn new() -> WezTerm {
+        WezTerm::default()
+    }
+
+    fn cli(&self, args: &[&str]) -> Result<String> {
+        let args: Vec<String> = ["cli"].iter().chain(args).map(|a| a.to_string()).collect();
+        run_cli(&self.bin, &args)
+    }
+
+    // The ids of the panes in the WezTerm workspace `name`, in the order
+    // `wezterm cli list` gives them
+    fn workspace_panes(&self, name: &str) -> Result<Vec<String>> {
+        Ok(workspace_panes(&self.cli(&["list"])?)
+            .into_iter()
+            .filter(|(workspace, _)| workspace == name)
+            .map(|(_, pane)| pane)
+            .collect())
+    }
+
+    // Splits the tab holding `first` into the workspace's panes and types
+    // each command into its pane
+    fn populate(&self, workspace: &WorkSpace, first: &str) -> Result<()> {
+        // older versions can't title tabs, which is only cosmetic
+        if let Err(err) = self.cli(&[
+            "set-tab-title",
+            "--pane-id",
+            first,
+            &workspace.window_name(),
+        ]) {
+            debug!("couldn't title the tab of pane {}: {}", first, err);
+        }
+
+        let panes = split_panes(workspace, first.to_owned(), |step, target| {
+            let side = match step.direction {
+                SplitDirection::Horizontal => "--right",
+                SplitDirection::Vertical => "--bottom",
+            };
+            self.cli(&[
+                "split-pane",
+                "--pane-id",
+                target,
+                side,
+                "--percent",
+                &step.percent.to_string(),
+                "--cwd",
+                &workspace.pane_dir(step.pane as u8),
+            ])
+        })?;
+        send_commands(workspace, &panes, |pane, line| {
+            let text = format!("{}\r", line);
+            self.cli(&["send-text", "--pane-id", pane, "--no-paste", &text])?;
+            Ok(())
+        })
+    }
+}
+
+impl Multiplexer for WezTerm {
+    fn name(&self) -> &'static str {
+        "wezterm"
+    }
+
+    fn is_installed(&self) -> bool {
+        cli_runs(&self.bin, &["--version"])
+    }
+
+    fn session_names(&self) -> Result<Vec<String>> {
+        let mut names: Vec<String> = workspace_panes(&self.cli(&["list"])?)
+            .into_iter()
+            .map(|(workspace, _)| workspace)
+            .collect();
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    fn open(&self, workspace: WorkSpace, ready: &mut dyn FnMut() -> Result<()>) -> Result<String> {
+        let workspace = workspace.normalized()?;
+        let name = workspace.session_name.clone();
+        warn_env_ignored(self.name(), &workspace);
+
+        let first = match self.workspace_panes(&name)?.into_iter().next() {
+            Some(pane) => pane,
+            None => {
+                let first = self.cli(&[
+                    "spawn",
+                    "--new-window",
+                    "--workspace",
+                    &name,
+                    "--cwd",
+                    &workspace.pane_dir(0),
+                ])?;
+                self.populate(&workspace, &first)?;
+                for window in workspace.window_workspaces()? {
+                    // spawning next to a pane opens a tab in its window
+                    let tab =
+                        self.cli(&["spawn", "--pane-id", &first, "--cwd", &window.pane_dir(0)])?;
+                    self.populate(&window, &tab)?;
+                }
+                first
+            }
+        };
+
+        ready()?;
+        if workspace.detached {
+            return Ok(name);
+        }
+        self.cli(&["activate-pane", "--pane-id", &first])?;
+        Ok(name)
+    }
+}
+
+// The workspace and pane id of each row of `wezterm cli list`, going by its
+// header since the columns after them hold titles with spaces in.
+fn workspace_panes(list: &str) -> Vec<(String, String)> {
+    let mut lines = list.lines();
+    let header: Vec<&str> = lines
+        .next()
+        .unwrap_or_default()
+        .split_whitespace()
+        .collect();
+    let column = |name: &str| header.iter().position(|h| *h == name);
+    let (Some(workspace), Some(pane)) = (column("WORKSPACE"), column("PANEID")) else {
+        return vec![];
+    };
+    lines
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            Some((
+                fields.get(workspace)?.to_string(),
+                fields.get(pane)?.to_string(),
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_rows_are_read_by_their_header() {
+        let list = "WINID TABID PANEID WORKSPACE SIZE  TITLE          CWD\n\
+                        0     0      0 default   80x24 fish           file:///home\n\
+                        1     2      3 site      80x24 nvim index.js  file:///src/site\n";
+        assert_eq!(
+            workspace_panes(list),
+            [
+                ("default".to_owned(), "0".to_owned()),
+                ("site".to_owned(), "3".to_owned())
+            ]
+        );
+        assert!(workspace_panes("").is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn workspaces_are_spawned_split_and_sent_their_commands() {
+        use crate::multiplexer::fake_cli;
+
+        // new panes are numbered by how many calls came before them
+        let (bin, log) = fake_cli(
+            "wezterm",
+            "case \"$2\" in\n\
+             list) echo 'WINID TABID PANEID WORKSPACE SIZE TITLE CWD' ;;\n\
+             spawn|split-pane) wc -l < $LOG | tr -d ' ' ;;\n\
+             esac",
+        );
+        let wezterm = WezTerm { bin };
+        let dir = std::env::temp_dir();
+        let workspace = WorkSpace {
+            path: dir.clone(),
+            session_name: "site".to_owned(),
+            format_checksum: "tiled".to_owned(),
+            window_name: Some("dev".to_owned()),
+            commands: vec!["nvim".to_owned(), String::new(), "npm start".to_owned()],
+            detached: true,
+            ..Default::default()
+        };
+        wezterm.open(workspace, &mut || Ok(())).unwrap();
+
+        let dir = dir.display();
+        let calls = std::fs::read_to_string(log).unwrap();
+        let expected = [
+            "cli list".to_owned(),
+            format!("cli spawn --new-window --workspace site --cwd {}", dir),
+            "cli set-tab-title --pane-id 2 dev".to_owned(),
+            format!(
+                "cli split-pane --pane-id 2 --right --percent 50 --cwd {}",
+                dir
+            ),
+            format!(
+                "cli split-pane --pane-id 4 --bottom --percent 50 --cwd {}",
+                dir
+            ),
+            "cli send-text --pane-id 2 --no-paste nvim\r".to_owned(),
+            "cli send-text --pane-id 5 --no-paste npm start\r".to_owned(),
+        ];
+        // split by hand, since `lines` would drop the carriage returns
+        assert_eq!(
+            calls.trim_end_matches('\n').split('\n').collect::<Vec<_>>(),
+            expected
+        );
+    }
+}
@@ -0,0 +1,156 @@
+// worktree_for
+// worktrees
+
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The worktree a branch is checked out in, and the `repo@branch` session
+/// it's opened as.
+pub struct Worktree {
+    pub dir: PathBuf,
+    pub session_name: String,
+}
+
+// Runs git in `dir`, returning what it printed, or what it complained about
+// as the error.
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Couldn't run git {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The worktree of the repo in `repo_dir` that `branch` is checked out in.
+/// When it isn't checked out anywhere, a worktree is added for it in
+/// `worktree_dir`, or next to the repo, named `repo@branch`. A branch that
+/// doesn't exist yet, locally or on a remote, is made from the current HEAD.
+pub fn worktree_for(
+    repo_dir: &Path,
+    branch: &str,
+    worktree_dir: Option<&Path>,
+) -> Result<Worktree> {
+    let listed = git(repo_dir, &["worktree", "list", "--porcelain"])
+        .map_err(|_| anyhow!("{} isn't in a git repository", repo_dir.display()))?;
+    let worktrees = worktrees(&listed);
+    let (main, _) = worktrees
+        .first()
+        .ok_or_else(|| anyhow!("git didn't list the repository's worktrees"))?;
+    let repo = repo_name(main);
+    let session_name = format!("{}@{}", repo, branch);
+    if let Some((dir, _)) = worktrees
+        .iter()
+        .find(|(_, checked_out)| checked_out.as_deref() == Some(branch))
+    {
+        return Ok(Worktree {
+            dir: dir.clone(),
+            session_name,
+        });
+    }
+
+    let parent = match worktree_dir {
+        Some(dir) => dir.to_owned(),
+        None => main
+            .parent()
+            .ok_or_else(|| anyhow!("{} has no parent to put worktrees in", main.display()))?
+            .to_owned(),
+    };
+    let dir = parent.join(format!("{}@{}", repo, branch.replace('/', "-")));
+    let path = dir
+        .to_str()
+        .ok_or_else(|| anyhow!("Specified target couldn't be used {:?}", dir))?;
+    if branch_exists(main, branch)? {
+        git(main, &["worktree", "add", path, branch])?;
+    } else {
+        git(main, &["worktree", "add", "-b", branch, path])?;
+    }
+    Ok(Worktree { dir, session_name })
+}
+
+// Whether `branch` is a local branch or on a remote, where git checks it out
+// as a new branch tracking the remote's when it's only on one
+fn branch_exists(main: &Path, branch: &str) -> Result<bool> {
+    let local = format!("refs/heads/{}", branch);
+    if git(main, &["rev-parse", "--verify", "--quiet", &local]).is_ok() {
+        return Ok(true);
+    }
+    let remote = format!("refs/remotes/*/{}", branch);
+    Ok(
+        !git(main, &["for-each-ref", "--format=%(refname)", &remote])?
+            .trim()
+            .is_empty(),
+    )
+}
+
+// Each worktree in `git worktree list --porcelain` with the branch checked
+// out in it, the main one first. Worktrees are separated by blank lines.
+fn worktrees(listed: &str) -> Vec<(PathBuf, Option<String>)> {
+    listed
+        .split("\n\n")
+        .filter_map(|worktree| {
+            let mut dir = None;
+            let mut branch = None;
+            for line in worktree.lines() {
+                if let Some(path) = line.strip_prefix("worktree ") {
+                    dir = Some(PathBuf::from(path));
+                } else if let Some(name) = line.strip_prefix("branch refs/heads/") {
+                    branch = Some(name.to_owned());
+                }
+            }
+            Some((dir?, branch))
+        })
+        .collect()
+}
+
+// The repo's name going by its main worktree, which for a bare repo is the
+// `repo.git` dir itself
+fn repo_name(main: &Path) -> String {
+    let name = main
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    name.trim_end_matches(".git").to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn worktrees_are_read_with_their_branches() {
+        let listed = "worktree /src/dmux\n\
+                      HEAD 1d2c3b4a\n\
+                      branch refs/heads/main\n\
+                      \n\
+                      worktree /src/dmux@review\n\
+                      HEAD 5e6f7a8b\n\
+                      detached\n\
+                      \n\
+                      worktree /src/dmux@fix-attach\n\
+                      HEAD 9c0d1e2f\n\
+                      branch refs/heads/fix/attach\n\
+                      \n";
+        assert_eq!(
+            worktrees(listed),
+            [
+                (PathBuf::from("/src/dmux"), Some("main".to_owned())),
+                (PathBuf::from("/src/dmux@review"), None),
+                (
+                    PathBuf::from("/src/dmux@fix-attach"),
+                    Some("fix/attach".to_owned())
+                ),
+            ]
+        );
+        assert_eq!(repo_name(Path::new("/src/dmux.git")), "dmux");
+    }
+}
@@ -0,0 +1,225 @@
+// Zellij::open
+// setup_actions
+
+use std::process::{Command, Stdio};
+
+use anyhow::Result;
+
+use crate::multiplexer::{cli_runs, run_cli, warn_env_ignored, Multiplexer};
+use crate::tmux::{command_lines, WorkSpace};
+
+/// Opens workspaces in Zellij, starting the session in the background and
+/// then making its tabs and panes with `zellij action`. Zellij has no layout
+/// checksums or per pane environment, so Zellij places the panes itself and
+/// only their number, directories and commands carry over.
+#[derive(Debug, Clone)]
+pub struct Zellij {
+    pub(crate) bin: String,
+}
+
+impl Default for Zellij {
+    fn default() -> Self {
+        Zellij {
+            bin: "zellij".to_owned(),
+        }
+    }
+}
+
+impl Zellij {
+    pub fn new() -> Zellij {
+        Zellij::default()
+    }
+
+    fn command(&self, args: &[String]) -> Command {
+        let mut command = Command::new(&self.bin);
+        command.args(args);
+        command
+    }
+}
+
+impl Multiplexer for Zellij {
+    fn name(&self) -> &'static str {
+        "zellij"
+    }
+
+    fn is_installed(&self) -> bool {
+        cli_runs(&self.bin, &["--version"])
+    }
+
+    fn session_names(&self) -> Result<Vec<String>> {
+        let output = self
+            .command(&["list-sessions".to_owned(), "--short".to_owned()])
+            .stdin(Stdio::null())
+            .output()?;
+        // with no sessions zellij says so on stderr and fails
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .map(str::to_owned)
+            .collect())
+    }
+
+    fn open(&self, workspace: WorkSpace, ready: &mut dyn FnMut() -> Result<()>) -> Result<String> {
+        let workspace = workspace.normalized()?;
+        let name = workspace.session_name.clone();
+        warn_env_ignored(self.name(), &workspace);
+
+        if !self.session_names()?.contains(&name) {
+            let actions = setup_actions(&workspace)?;
+            // the first pane starts wherever the session is started from
+            let mut create = self.command(&[
+                "attach".to_owned(),
+                "--create-background".to_owned(),
+                name.clone(),
+            ]);
+            create.current_dir(workspace.pane_dir(0));
+            debug!("running {:?}", create);
+            let created = create.stdin(Stdio::null()).output()?;
+            if !created.status.success() {
+                return Err(anyhow!(
+                    "Couldn't create session {}: {}",
+                    name,
+                    String::from_utf8_lossy(&created.stderr).trim()
+                ));
+            }
+            for action in actions {
+                let mut args = vec!["--session".to_owned(), name.clone(), "action".to_owned()];
+                args.extend(action);
+                run_cli(&self.bin, &args)?;
+            }
+        }
+
+        ready()?;
+        if workspace.detached {
+            return Ok(name);
+        }
+        let attached = self
+            .command(&["attach".to_owned(), name.clone()])
+            .status()?;
+        if !attached.success() {
+            return Err(anyhow!("Couldn't attach to session {}", name));
+        }
+        Ok(name)
+    }
+}
+
+/// The `zellij action` arguments that turn the first tab of a new session
+/// into the workspace: its panes and their commands, then a tab for each of
+/// the workspace's other windows. The first tab is focused again at the end.
+pub(crate) fn setup_actions(workspace: &WorkSpace) -> Result<Vec<Vec<String>>> {
+    let mut actions = vec![vec!["rename-tab".to_owned(), workspace.window_name()]];
+    tab_actions(workspace, &mut actions)?;
+
+    let windows = workspace.window_workspaces()?;
+    for window in &windows {
+        actions.push(vec![
+            "new-tab".to_owned(),
+            "--name".to_owned(),
+            window.window_name(),
+            "--cwd".to_owned(),
+            window.pane_dir(0),
+        ]);
+        tab_actions(window, &mut actions)?;
+    }
+    if !windows.is_empty() {
+        actions.push(vec!["go-to-tab".to_owned(), "1".to_owned()]);
+    }
+    Ok(actions)
+}
+
+// Splits the focused tab into the workspace's panes, typing each command
+// into the pane just made, which is the focused one
+fn tab_actions(workspace: &WorkSpace, actions: &mut Vec<Vec<String>>) -> Result<()> {
+    for nth in 0..workspace.number_of_panes() {
+        if nth > 0 {
+            actions.push(vec![
+                "new-pane".to_owned(),
+                "--cwd".to_owned(),
+                workspace.pane_dir(nth),
+            ]);
+        }
+        let command = workspace.commands.get(nth as usize).map(String::as_str);
+        for line in command_lines(command.unwrap_or_default())? {
+            if line.is_empty() {
+                continue;
+            }
+            actions.push(vec!["write-chars".to_owned(), line]);
+            // Enter
+            actions.push(vec!["write".to_owned(), "13".to_owned()]);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tmux::{PaneSpec, WindowSpec};
+    use std::path::PathBuf;
+
+    fn actions(workspace: &WorkSpace) -> Vec<String> {
+        setup_actions(workspace)
+            .unwrap()
+            .iter()
+            .map(|action| action.join(" "))
+            .collect()
+    }
+
+    #[test]
+    fn panes_and_commands_become_actions() {
+        let workspace = WorkSpace {
+            path: PathBuf::from("/src/mono"),
+            session_name: "mono".to_owned(),
+            window_name: Some("dev".to_owned()),
+            commands: vec!["nvim".to_owned(), String::new(), "cargo watch".to_owned()],
+            number_of_panes: 3,
+            panes: vec![
+                PaneSpec::default(),
+                PaneSpec {
+                    dir: Some(PathBuf::from("api")),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            actions(&workspace),
+            [
+                "rename-tab dev",
+                "write-chars nvim",
+                "write 13",
+                "new-pane --cwd /src/mono/api",
+                "new-pane --cwd /src/mono",
+                "write-chars cargo watch",
+                "write 13",
+            ]
+        );
+    }
+
+    #[test]
+    fn other_windows_get_tabs_of_their_own() {
+        let workspace = WorkSpace {
+            path: PathBuf::from("/src/site"),
+            session_name: "site".to_owned(),
+            format_checksum: "tiled".to_owned(),
+            window_name: Some("dev".to_owned()),
+            number_of_panes: 1,
+            windows: vec![WindowSpec {
+                name: "logs".to_owned(),
+                commands: vec!["tail -f log".to_owned()],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            actions(&workspace),
+            [
+                "rename-tab dev",
+                "new-tab --name logs --cwd /src/site",
+                "write-chars tail -f log",
+                "write 13",
+                "go-to-tab 1",
+            ]
+        );
+    }
+}